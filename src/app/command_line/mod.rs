@@ -1,4 +1,8 @@
-use crate::commands::Command;
+pub mod range;
+
+use crate::{app::InputMode, commands::Command};
+
+use self::range::{parse_range, LineRange};
 
 #[derive(Debug)]
 pub struct CommandLine {
@@ -67,11 +71,162 @@ impl CommandLine {
     }
 
     pub fn get_commands(&self) -> Vec<Command> {
-        match self.value.as_str() {
-            "q" => vec![Command::Quit],
-            "w" => vec![Command::EditorSave],
-            "wq" => vec![Command::EditorSave, Command::Quit],
-            _ => todo!(),
+        parse_command(&self.value)
+    }
+}
+
+/// Turns an ex command, as typed after `:`, into the commands to run
+pub fn parse_command(value: &str) -> Vec<Command> {
+    let value = value.trim();
+    let (range, rest) = parse_range(value);
+    let rest = rest.trim_start();
+    if let Some(command) = rest.strip_prefix('!') {
+        return shell_command(range, command.trim());
+    }
+    if let Some(command) = read_shell_command(rest) {
+        if command.is_empty() {
+            return vec![Command::ShowError("Argument required".into())];
+        }
+        return vec![Command::ReadShell {
+            range,
+            command: command.into(),
+        }];
+    }
+    if range.is_some() && !rest.is_empty() {
+        return vec![Command::ShowError("No range allowed".into())];
+    }
+
+    let (name, args) = match value.split_once(' ') {
+        Some((name, args)) => (name, args.trim()),
+        None => (value, ""),
+    };
+
+    match name {
+        "set" | "se" => return vec![Command::SetOption(args.into())],
+        "setlocal" | "setl" => return vec![Command::SetLocalOption(args.into())],
+        "map" | "nmap" | "nm" => return map_command(InputMode::Normal, args, true),
+        "imap" | "im" => return map_command(InputMode::Insert, args, true),
+        "cmap" | "cm" => return map_command(InputMode::Command, args, true),
+        "noremap" | "no" | "nnoremap" | "nn" => return map_command(InputMode::Normal, args, false),
+        "inoremap" | "ino" => return map_command(InputMode::Insert, args, false),
+        "cnoremap" | "cno" => return map_command(InputMode::Command, args, false),
+        "unmap" | "unm" | "nunmap" | "nun" => return unmap_command(InputMode::Normal, args),
+        "iunmap" | "iu" => return unmap_command(InputMode::Insert, args),
+        "cunmap" | "cu" => return unmap_command(InputMode::Command, args),
+        "TSInstallLocal" => {
+            if args.is_empty() {
+                return vec![Command::ShowError("Argument required".into())];
+            }
+            return vec![Command::InstallGrammar(args.into())];
+        }
+        "colorscheme" | "colo" => {
+            let name = (!args.is_empty()).then(|| args.to_string());
+            return vec![Command::ColorScheme(name)];
+        }
+        "jobs" => return vec![Command::ListJobs],
+        "jobstop" => {
+            if args.is_empty() {
+                return vec![Command::CancelJob(None)];
+            }
+            return match args.parse() {
+                Ok(id) => vec![Command::CancelJob(Some(id))],
+                Err(_) => vec![Command::ShowError(format!("Invalid job id: {}", args))],
+            };
+        }
+        "make" | "mak" => {
+            let args = (!args.is_empty()).then(|| args.to_string());
+            return vec![Command::Make(args)];
+        }
+        "cc" => {
+            if args.is_empty() {
+                return vec![Command::QuickfixSelect(None)];
+            }
+            return match args.parse() {
+                Ok(number) if number > 0 => vec![Command::QuickfixSelect(Some(number))],
+                _ => vec![Command::ShowError(format!("Invalid entry: {}", args))],
+            };
+        }
+        "source" | "so" => {
+            let path = (!args.is_empty()).then(|| args.to_string());
+            return vec![Command::SourceConfig(path)];
         }
+        _ => {}
+    }
+
+    match value {
+        "q" | "quit" => vec![Command::Quit],
+        "q!" | "quit!" => vec![Command::ForceQuit],
+        "qa" | "qall" => vec![Command::QuitAll],
+        "qa!" | "qall!" => vec![Command::ForceQuitAll],
+        "w" | "write" => vec![Command::EditorSave],
+        "w!" | "write!" => vec![Command::EditorForceSave],
+        "e!" | "edit!" => vec![Command::EditorReload],
+        "Format" => vec![Command::EditorFormat],
+        "retab" | "ret" => vec![Command::EditorRetab],
+        "undo" | "u" => vec![Command::EditorUndo],
+        "redo" | "red" => vec![Command::EditorRedo],
+        "copen" | "cope" => vec![Command::QuickfixOpen],
+        "cclose" | "ccl" => vec![Command::QuickfixClose],
+        "cnext" | "cn" => vec![Command::QuickfixNext],
+        "cprevious" | "cprev" | "cp" | "cNext" | "cN" => vec![Command::QuickfixPrev],
+        "wa" | "wall" => vec![Command::EditorSaveAll],
+        "wq" => vec![Command::EditorSave, Command::Quit],
+        "wqa" | "wqall" | "xa" | "xall" => vec![Command::EditorSaveAll, Command::QuitAll],
+        "x" | "xit" => vec![Command::EditorSaveIfModified, Command::Quit],
+        "" => vec![],
+        other => vec![Command::ShowError(format!(
+            "Not an editor command: {}",
+            other
+        ))],
+    }
+}
+
+/// `:!cmd` on the terminal, or `:{range}!cmd` filtering lines
+fn shell_command(range: Option<LineRange>, command: &str) -> Vec<Command> {
+    if command.is_empty() {
+        return vec![Command::ShowError("Argument required".into())];
+    }
+    match range {
+        Some(range) => vec![Command::FilterLines {
+            range,
+            command: command.into(),
+        }],
+        None => vec![Command::Shell(command.into())],
+    }
+}
+
+/// The command of `:r !cmd`, reading files isn't supported
+fn read_shell_command(value: &str) -> Option<&str> {
+    let name_length = value.len() - value.trim_start_matches(char::is_alphabetic).len();
+    if !matches!(&value[..name_length], "r" | "read") {
+        return None;
+    }
+    let command = value[name_length..].trim_start().strip_prefix('!')?;
+    Some(command.trim())
+}
+
+/// `:map {lhs} {rhs}` and friends, listing the mappings when given nothing
+fn map_command(mode: InputMode, args: &str, remap: bool) -> Vec<Command> {
+    if args.is_empty() {
+        return vec![Command::ListMappings];
+    }
+    match args.split_once(' ') {
+        Some((lhs, rhs)) => vec![Command::Map {
+            mode,
+            lhs: lhs.into(),
+            rhs: rhs.trim().into(),
+            remap,
+        }],
+        None => vec![Command::ShowError("Argument required".into())],
+    }
+}
+
+fn unmap_command(mode: InputMode, args: &str) -> Vec<Command> {
+    if args.is_empty() {
+        return vec![Command::ShowError("Argument required".into())];
     }
+    vec![Command::Unmap {
+        mode,
+        lhs: args.into(),
+    }]
 }
@@ -0,0 +1,171 @@
+use serde::Deserialize;
+
+/// Where a line address starts counting from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Address {
+    /// `.`, the cursor line
+    Current,
+    /// `$`, the last line
+    Last,
+    /// A line number, counting from 1
+    Line(usize),
+}
+
+/// A line given to an ex command, such as `.+2` or `$-1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct LineAddress {
+    pub base: Address,
+    pub offset: isize,
+}
+
+/// The lines an ex command works on, as in `:1,5!sort` or `:%!jq .`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct LineRange {
+    pub start: LineAddress,
+    pub end: LineAddress,
+}
+
+impl LineAddress {
+    pub fn line(line: usize) -> Self {
+        Self {
+            base: Address::Line(line),
+            offset: 0,
+        }
+    }
+
+    /// The line number, counting from 1, with 0 standing for before the
+    /// first line.
+    pub fn resolve(&self, current: usize, last: usize) -> Result<usize, String> {
+        let base = match self.base {
+            Address::Current => current,
+            Address::Last => last,
+            Address::Line(line) => line,
+        };
+        base.checked_add_signed(self.offset)
+            .filter(|line| *line <= last)
+            .ok_or_else(|| "Invalid range".to_string())
+    }
+}
+
+impl LineRange {
+    /// The whole buffer, `%`
+    pub fn all() -> Self {
+        Self {
+            start: LineAddress::line(1),
+            end: LineAddress {
+                base: Address::Last,
+                offset: 0,
+            },
+        }
+    }
+
+    /// First and last line numbers, counting from 1, in order.
+    pub fn resolve(&self, current: usize, last: usize) -> Result<(usize, usize), String> {
+        let start = self.start.resolve(current, last)?;
+        let end = self.end.resolve(current, last)?;
+        Ok((start.min(end), start.max(end)))
+    }
+}
+
+/// Splits the range off the front of an ex command, returning what's left.
+pub fn parse_range(input: &str) -> (Option<LineRange>, &str) {
+    if let Some(rest) = input.strip_prefix('%') {
+        return (Some(LineRange::all()), rest);
+    }
+    let (Some(start), rest) = parse_address(input) else {
+        return (None, input);
+    };
+    let Some(rest) = rest.strip_prefix([',', ';']) else {
+        return (Some(LineRange { start, end: start }), rest);
+    };
+    // A missing end, as in `:3,`, is the cursor line
+    let (end, rest) = parse_address(rest);
+    let end = end.unwrap_or(LineAddress {
+        base: Address::Current,
+        offset: 0,
+    });
+    (Some(LineRange { start, end }), rest)
+}
+
+fn parse_address(input: &str) -> (Option<LineAddress>, &str) {
+    let (base, mut rest) = if let Some(rest) = input.strip_prefix('.') {
+        (Some(Address::Current), rest)
+    } else if let Some(rest) = input.strip_prefix('$') {
+        (Some(Address::Last), rest)
+    } else {
+        match split_number(input) {
+            (Some(line), rest) => (Some(Address::Line(line)), rest),
+            (None, _) => (None, input),
+        }
+    };
+
+    // Offsets add up, a sign without a number counting as one
+    let mut offset: isize = 0;
+    let mut has_offset = false;
+    while let Some(sign) = rest.chars().next().filter(|ch| matches!(ch, '+' | '-')) {
+        let (amount, after) = split_number(&rest[1..]);
+        let amount = amount.unwrap_or(1).min(isize::MAX as usize) as isize;
+        offset = if sign == '+' {
+            offset.saturating_add(amount)
+        } else {
+            offset.saturating_sub(amount)
+        };
+        has_offset = true;
+        rest = after;
+    }
+
+    match base {
+        Some(base) => (Some(LineAddress { base, offset }), rest),
+        None if has_offset => (
+            Some(LineAddress {
+                base: Address::Current,
+                offset,
+            }),
+            rest,
+        ),
+        None => (None, input),
+    }
+}
+
+fn split_number(input: &str) -> (Option<usize>, &str) {
+    let digits = input.len()
+        - input
+            .trim_start_matches(|ch: char| ch.is_ascii_digit())
+            .len();
+    if digits == 0 {
+        return (None, input);
+    }
+    // Too large to be a line, which resolving reports
+    let number = input[..digits].parse().unwrap_or(usize::MAX);
+    (Some(number), &input[digits..])
+}
+
+#[cfg(test)]
+mod miv_range_tests {
+    use super::*;
+
+    fn resolve(input: &str) -> Result<(usize, usize), String> {
+        let (range, _) = parse_range(input);
+        range.unwrap().resolve(5, 10)
+    }
+
+    #[test]
+    fn ranges_are_parsed_and_resolved() {
+        assert_eq!(resolve("%"), Ok((1, 10)));
+        assert_eq!(resolve("."), Ok((5, 5)));
+        assert_eq!(resolve(".,.+2"), Ok((5, 7)));
+        assert_eq!(resolve("2,$-1"), Ok((2, 9)));
+        assert_eq!(resolve("+,++"), Ok((6, 7)));
+        assert_eq!(resolve("8,3"), Ok((3, 8)));
+        assert_eq!(resolve("0"), Ok((0, 0)));
+        assert!(resolve("11").is_err());
+        assert!(resolve(".-6").is_err());
+    }
+
+    #[test]
+    fn the_rest_of_the_command_is_left() {
+        assert_eq!(parse_range("1,5!sort").1, "!sort");
+        assert_eq!(parse_range("r !ls"), (None, "r !ls"));
+        assert_eq!(parse_range("$r !date").1, "r !date");
+    }
+}
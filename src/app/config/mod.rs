@@ -0,0 +1,427 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer};
+use toml::Spanned;
+
+use crate::utils::get_config_dir;
+
+use crate::input_handling::keymaps::Binding;
+
+use super::editor::filetypes::FileType;
+use super::options::{find_option, OptionDef, OptionKind, OptionScope, OptionValue};
+use super::quickfix::errorformat::ErrorFormat;
+use super::theme::Theme;
+
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Settings read from `config.toml`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Values for the options in the `[options]` table, checked against
+    /// their definitions when parsing
+    #[serde(skip)]
+    pub options: Vec<(String, OptionValue)>,
+    #[serde(rename = "options")]
+    raw_options: BTreeMap<String, Spanned<toml::Value>>,
+    /// Buffer and window options per filetype from `[ftoptions.<filetype>]`,
+    /// taking the place of the global value for buffers of that type
+    #[serde(skip)]
+    pub ftoptions: HashMap<FileType, Vec<(String, OptionValue)>>,
+    #[serde(rename = "ftoptions")]
+    raw_ftoptions: HashMap<FileType, BTreeMap<String, Spanned<toml::Value>>>,
+    /// Name of the colour scheme
+    #[serde(deserialize_with = "deserialize_theme")]
+    pub theme: String,
+    pub keymap: KeymapConfig,
+    pub filetype: FileTypeConfig,
+    /// Formatter command per filetype, an empty one turning formatting off
+    pub formatter: HashMap<FileType, String>,
+    /// Compilers for `:make` by name, picked with the `compiler` option
+    pub compiler: HashMap<String, CompilerConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            options: vec![],
+            raw_options: BTreeMap::new(),
+            ftoptions: HashMap::new(),
+            raw_ftoptions: HashMap::new(),
+            theme: Theme::DEFAULT_NAME.into(),
+            keymap: KeymapConfig::default(),
+            filetype: FileTypeConfig::default(),
+            formatter: HashMap::new(),
+            compiler: HashMap::new(),
+        }
+    }
+}
+
+/// Extra bindings per mode, from keys such as `"<leader>w"` to what they do.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeymapConfig {
+    pub normal: HashMap<String, Binding>,
+    pub insert: HashMap<String, Binding>,
+    pub command: HashMap<String, Binding>,
+}
+
+/// Extra filetype detection rules, looked at before the built in ones.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileTypeConfig {
+    /// By extension without the dot, such as `rs`
+    pub extension: HashMap<String, FileType>,
+    /// By whole file name, such as `Makefile`
+    pub filename: HashMap<String, FileType>,
+    /// By the program in a `#!` line, such as `python3`
+    pub interpreter: HashMap<String, FileType>,
+    /// By how the first line starts
+    pub first_line: HashMap<String, FileType>,
+}
+
+/// A compiler for `:make`, replacing parts of a built in one of the same name.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompilerConfig {
+    /// What `:make` runs unless `makeprg` is set
+    pub command: Option<String>,
+    /// Patterns its messages are read with, tried in order
+    pub errorformat: Vec<ErrorFormat>,
+}
+
+/// A config file that couldn't be loaded, pointing at the offending spot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let file_name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            file_name, self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Where the config file is read from.
+///
+/// `MIV_EDITOR_CONFIG` may point at the config directory or straight at a file.
+pub fn config_path() -> PathBuf {
+    let config_dir = get_config_dir();
+    if config_dir.is_file() {
+        config_dir
+    } else {
+        config_dir.join(CONFIG_FILE)
+    }
+}
+
+/// The directory holding the config file, along with installed grammars
+/// and queries.
+pub fn config_dir() -> PathBuf {
+    let config_dir = get_config_dir();
+    match config_dir.parent() {
+        Some(parent) if config_dir.is_file() => parent.to_path_buf(),
+        _ => config_dir,
+    }
+}
+
+impl Config {
+    /// Reads the config at `path`, a missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(source) => Self::parse(&source, path),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(ConfigError {
+                path: path.to_path_buf(),
+                line: 1,
+                column: 1,
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    pub fn parse(source: &str, path: &Path) -> Result<Self, ConfigError> {
+        let error = |offset: Option<usize>, message: String| {
+            let (line, column) = offset
+                .map(|offset| line_and_column(source, offset))
+                .unwrap_or((1, 1));
+            ConfigError {
+                path: path.to_path_buf(),
+                line,
+                column,
+                message,
+            }
+        };
+
+        let mut config: Self = toml::from_str(source)
+            .map_err(|err| error(err.span().map(|span| span.start), err.message().into()))?;
+        config.options = parse_options(&config.raw_options, false)
+            .map_err(|(offset, message)| error(Some(offset), message))?;
+        for (filetype, raw_options) in &config.raw_ftoptions {
+            let options = parse_options(raw_options, true)
+                .map_err(|(offset, message)| error(Some(offset), message))?;
+            config.ftoptions.insert(*filetype, options);
+        }
+        Ok(config)
+    }
+}
+
+/// Checks an options table, giving the offset of a bad value along with
+/// what's wrong with it. Options for a filetype can only be buffer or
+/// window ones.
+fn parse_options(
+    raw_options: &BTreeMap<String, Spanned<toml::Value>>,
+    per_filetype: bool,
+) -> Result<Vec<(String, OptionValue)>, (usize, String)> {
+    raw_options
+        .iter()
+        .map(|(name, value)| {
+            find_option(name)
+                .ok_or_else(|| format!("unknown option `{}`", name))
+                .and_then(|def| {
+                    if per_filetype && def.scope == OptionScope::Global {
+                        return Err(format!("`{}` can't be set per filetype", def.name));
+                    }
+                    Ok((
+                        def.name.to_string(),
+                        option_from_toml(def, value.get_ref())?,
+                    ))
+                })
+                .map_err(|message| (value.span().start, message))
+        })
+        .collect()
+}
+
+/// Converts a TOML value into a valid value for the option.
+fn option_from_toml(def: &OptionDef, value: &toml::Value) -> Result<OptionValue, String> {
+    let value = match (def.kind, value) {
+        (OptionKind::Bool, toml::Value::Boolean(value)) => OptionValue::Bool(*value),
+        (OptionKind::Int { .. }, toml::Value::Integer(value)) => OptionValue::Int(*value),
+        (OptionKind::String, toml::Value::String(value)) => OptionValue::String(value.clone()),
+        (OptionKind::Enum(_), toml::Value::String(value)) => OptionValue::Enum(value.clone()),
+        (OptionKind::List, toml::Value::String(value)) => return def.kind.parse(value),
+        (OptionKind::List, toml::Value::Array(items)) => OptionValue::List(
+            items
+                .iter()
+                .map(|item| item.as_str().map(String::from))
+                .collect::<Option<_>>()
+                .ok_or_else(|| format!("`{}` must be a list of strings", def.name))?,
+        ),
+        (kind, _) => return Err(format!("`{}` must be {}", def.name, kind.type_name())),
+    };
+    def.kind
+        .validate(value)
+        .map_err(|message| format!("`{}`: {}", def.name, message))
+}
+
+/// 1-based line and column of a byte offset.
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
+
+fn deserialize_theme<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    if !Theme::exists(&name) {
+        return Err(serde::de::Error::custom(format!(
+            "unknown theme `{}`",
+            name
+        )));
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod miv_config_tests {
+    use super::*;
+    use crate::{app::editor::motions::Motion, commands::Command};
+
+    fn parse(source: &str) -> Result<Config, ConfigError> {
+        Config::parse(source, Path::new("config.toml"))
+    }
+
+    #[test]
+    fn empty_config_is_the_default() {
+        assert_eq!(parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn options_and_keymaps_are_read() {
+        let config = parse(
+            r#"
+[options]
+tabstop = 8
+wrap = true
+backup = "adjacent"
+
+[keymap.normal]
+"<C-s>" = "w"
+"<leader>q" = ["w", "q"]
+"Y" = { keys = "y$", noremap = true }
+"<leader>x" = { commands = ["Quit", { EditorMove = "LineEnd" }] }
+"<leader>w" = { ex = "w", desc = "Save" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.options,
+            vec![
+                ("backup".to_string(), OptionValue::Enum("adjacent".into())),
+                ("tabstop".to_string(), OptionValue::Int(8)),
+                ("wrap".to_string(), OptionValue::Bool(true)),
+            ]
+        );
+        let normal = &config.keymap.normal;
+        assert_eq!(normal["<C-s>"], Binding::Ex("w".into()));
+        assert_eq!(
+            normal["<leader>q"],
+            Binding::ExSequence(vec!["w".into(), "q".into()])
+        );
+        assert_eq!(
+            normal["Y"],
+            Binding::Keys {
+                keys: "y$".into(),
+                noremap: true,
+                desc: None,
+            }
+        );
+        assert_eq!(
+            normal["<leader>w"],
+            Binding::DescribedEx {
+                ex: vec!["w".into()],
+                desc: Some("Save".into()),
+            }
+        );
+        assert_eq!(
+            normal["<leader>x"],
+            Binding::Commands {
+                commands: vec![Command::Quit, Command::EditorMove(Motion::LineEnd)],
+                desc: None,
+            }
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_value() {
+        let err = parse("[options]\ntabstop = 0\n").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 11));
+        assert_eq!(
+            err.to_string(),
+            "config.toml:2:11: `tabstop`: Value must be between 1 and 64"
+        );
+
+        let err = parse("[options]\nnumber = 3\n").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 10));
+        assert_eq!(err.message, "`number` must be a boolean");
+
+        let err = parse("[options]\nbogus = 3\n").unwrap_err();
+        assert_eq!(err.message, "unknown option `bogus`");
+    }
+
+    #[test]
+    fn filetype_rules_are_read() {
+        let config = parse(
+            r#"
+[filetype.extension]
+jsonc = "json"
+
+[filetype.filename]
+Justfile = "make"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.filetype.extension["jsonc"], FileType::Json);
+        assert_eq!(config.filetype.filename["Justfile"], FileType::Make);
+
+        let err = parse(
+            "[filetype.extension]
+foo = \"cobol\"\n",
+        )
+        .unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn formatters_are_read_per_filetype() {
+        let config = parse(
+            r#"
+[formatter]
+rust = "rustfmt --edition 2018"
+json = ""
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.formatter[&FileType::Rust], "rustfmt --edition 2018");
+        assert_eq!(config.formatter[&FileType::Json], "");
+        assert!(parse("[formatter]\ncobol = \"x\"\n").is_err());
+    }
+
+    #[test]
+    fn options_are_read_per_filetype() {
+        let config = parse(
+            r#"
+[ftoptions.python]
+expandtab = true
+trimwhitespace = "modified"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.ftoptions[&FileType::Python],
+            vec![
+                ("expandtab".to_string(), OptionValue::Bool(true)),
+                (
+                    "trimwhitespace".to_string(),
+                    OptionValue::Enum("modified".into())
+                ),
+            ]
+        );
+
+        let err = parse("[ftoptions.rust]\nmouse = false\n").unwrap_err();
+        assert_eq!(err.message, "`mouse` can't be set per filetype");
+    }
+
+    #[test]
+    fn compilers_are_read() {
+        let config = parse(
+            r#"
+[compiler.tsc]
+command = "npx tsc --pretty false"
+errorformat = ["%f(%l,%c): %t%*: %m"]
+"#,
+        )
+        .unwrap();
+        let tsc = &config.compiler["tsc"];
+        assert_eq!(tsc.command.as_deref(), Some("npx tsc --pretty false"));
+        assert_eq!(tsc.errorformat[0].to_string(), "%f(%l,%c): %t%*: %m");
+
+        let err = parse("[compiler.x]\nerrorformat = [\"%f:%l\"]\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn unknown_theme_is_rejected() {
+        let err = parse("theme = \"solarized\"\n").unwrap_err();
+        assert_eq!(err.line, 1);
+        assert!(err.message.contains("solarized"));
+    }
+}
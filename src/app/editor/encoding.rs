@@ -0,0 +1,275 @@
+use strum_macros::{Display, EnumString};
+
+use crate::app::AppResult;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// How lines are terminated in the file, named like vim's `fileformat`.
+#[derive(Display, Debug, EnumString, PartialEq, Clone, Copy, Default)]
+pub enum LineEnding {
+    #[default]
+    #[strum(serialize = "unix")]
+    Lf,
+    #[strum(serialize = "dos")]
+    CrLf,
+    #[strum(serialize = "mac")]
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+/// Character encoding of the file, named like vim's `fileencoding`.
+#[derive(Display, Debug, EnumString, PartialEq, Clone, Copy, Default)]
+pub enum Encoding {
+    #[default]
+    #[strum(to_string = "utf-8", serialize = "utf8")]
+    Utf8,
+    #[strum(serialize = "utf-16le")]
+    Utf16Le,
+    #[strum(to_string = "utf-16be", serialize = "utf-16")]
+    Utf16Be,
+    #[strum(to_string = "latin1", serialize = "iso-8859-1")]
+    Latin1,
+}
+
+/// Everything needed to write text back the way it was read.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    pub encoding: Encoding,
+    /// Whether the file starts with a byte order mark
+    pub bom: bool,
+}
+
+/// Turns the raw bytes of a file into text with `\n` line endings,
+/// along with the format they were stored in.
+pub fn decode(bytes: &[u8]) -> (String, FileFormat) {
+    let (encoding, bom, body) = detect_encoding(bytes);
+    let text = match encoding {
+        Encoding::Utf8 => String::from_utf8_lossy(body).to_string(),
+        Encoding::Utf16Le => decode_utf16(body, u16::from_le_bytes),
+        Encoding::Utf16Be => decode_utf16(body, u16::from_be_bytes),
+        Encoding::Latin1 => body.iter().map(|&byte| byte as char).collect(),
+    };
+    let line_ending = detect_line_ending(&text);
+    let text = match line_ending {
+        LineEnding::Lf => text,
+        LineEnding::CrLf => text.replace("\r\n", "\n"),
+        LineEnding::Cr => text.replace('\r', "\n"),
+    };
+
+    (
+        text,
+        FileFormat {
+            line_ending,
+            encoding,
+            bom,
+        },
+    )
+}
+
+/// Turns text with `\n` line endings back into bytes in the given format.
+pub fn encode(text: &str, format: &FileFormat) -> AppResult<Vec<u8>> {
+    let text = match format.line_ending {
+        LineEnding::Lf => text.to_string(),
+        ending => text.replace('\n', ending.as_str()),
+    };
+
+    let mut bytes = vec![];
+    match format.encoding {
+        Encoding::Utf8 => {
+            if format.bom {
+                bytes.extend_from_slice(UTF8_BOM);
+            }
+            bytes.extend_from_slice(text.as_bytes());
+        }
+        Encoding::Utf16Le => {
+            if format.bom {
+                bytes.extend_from_slice(UTF16LE_BOM);
+            }
+            bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        }
+        Encoding::Utf16Be => {
+            if format.bom {
+                bytes.extend_from_slice(UTF16BE_BOM);
+            }
+            bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        }
+        Encoding::Latin1 => {
+            for ch in text.chars() {
+                let byte = u8::try_from(ch as u32)
+                    .map_err(|_| format!("Cannot encode '{}' as {}", ch, format.encoding))?;
+                bytes.push(byte);
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// Works out the encoding from a byte order mark, whether the data is valid
+/// UTF-8 or looks like UTF-16, falling back to Latin-1 which can't fail.
+///
+/// Data that doesn't decode as its byte order mark says is read as Latin-1,
+/// mark included, so writing it back doesn't change a byte.
+fn detect_encoding(bytes: &[u8]) -> (Encoding, bool, &[u8]) {
+    if let Some(body) = bytes.strip_prefix(UTF8_BOM) {
+        if std::str::from_utf8(body).is_ok() {
+            return (Encoding::Utf8, true, body);
+        }
+    }
+    if let Some(body) = bytes.strip_prefix(UTF16LE_BOM) {
+        if is_utf16(body, u16::from_le_bytes) {
+            return (Encoding::Utf16Le, true, body);
+        }
+    }
+    if let Some(body) = bytes.strip_prefix(UTF16BE_BOM) {
+        if is_utf16(body, u16::from_be_bytes) {
+            return (Encoding::Utf16Be, true, body);
+        }
+    }
+
+    // Mostly ASCII UTF-16 has a zero in every other byte
+    if bytes.len() >= 2 && bytes.len().is_multiple_of(2) {
+        let pairs = bytes.len() / 2;
+        let zero_high = bytes.chunks(2).filter(|pair| pair[1] == 0).count();
+        let zero_low = bytes.chunks(2).filter(|pair| pair[0] == 0).count();
+        if zero_high * 2 > pairs && zero_low == 0 && is_utf16(bytes, u16::from_le_bytes) {
+            return (Encoding::Utf16Le, false, bytes);
+        }
+        if zero_low * 2 > pairs && zero_high == 0 && is_utf16(bytes, u16::from_be_bytes) {
+            return (Encoding::Utf16Be, false, bytes);
+        }
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        (Encoding::Utf8, false, bytes)
+    } else {
+        (Encoding::Latin1, false, bytes)
+    }
+}
+
+/// The first line ending in the text decides for the whole file.
+fn detect_line_ending(text: &str) -> LineEnding {
+    match text.find(['\r', '\n']) {
+        Some(index) if text[index..].starts_with("\r\n") => LineEnding::CrLf,
+        Some(index) if text[index..].starts_with('\r') => LineEnding::Cr,
+        _ => LineEnding::Lf,
+    }
+}
+
+fn utf16_units(bytes: &[u8], to_unit: fn([u8; 2]) -> u16) -> impl Iterator<Item = u16> + '_ {
+    bytes
+        .chunks_exact(2)
+        .map(move |pair| to_unit([pair[0], pair[1]]))
+}
+
+/// Whether `bytes` are whole UTF-16 code units without unpaired surrogates
+fn is_utf16(bytes: &[u8], to_unit: fn([u8; 2]) -> u16) -> bool {
+    bytes.len().is_multiple_of(2)
+        && char::decode_utf16(utf16_units(bytes, to_unit)).all(|ch| ch.is_ok())
+}
+
+fn decode_utf16(bytes: &[u8], to_unit: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = utf16_units(bytes, to_unit).collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod miv_encoding_tests {
+    use super::*;
+
+    fn round_trip(bytes: &[u8]) -> FileFormat {
+        let (text, format) = decode(bytes);
+        assert!(!text.contains('\r'));
+        assert_eq!(encode(&text, &format).unwrap(), bytes);
+        format
+    }
+
+    #[test]
+    fn plain_utf8_unix() {
+        let format = round_trip("fn main() {}\nλ\n".as_bytes());
+        assert_eq!(format, FileFormat::default());
+    }
+
+    #[test]
+    fn crlf_is_normalized() {
+        let (text, format) = decode(b"a\r\nb\r\n");
+        assert_eq!(text, "a\nb\n");
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        round_trip(b"a\r\nb\r\n");
+    }
+
+    #[test]
+    fn old_mac_line_endings() {
+        let format = round_trip(b"a\rb\r");
+        assert_eq!(format.line_ending, LineEnding::Cr);
+    }
+
+    #[test]
+    fn utf8_bom_is_kept() {
+        let format = round_trip(b"\xEF\xBB\xBFhello\n");
+        assert!(format.bom);
+        assert_eq!(format.encoding, Encoding::Utf8);
+    }
+
+    #[test]
+    fn utf16_with_and_without_bom() {
+        let le: Vec<u8> = "hi\r\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let format = round_trip(&[UTF16LE_BOM, &le].concat());
+        assert_eq!(format.encoding, Encoding::Utf16Le);
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+
+        let be: Vec<u8> = "hello\n"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        let format = round_trip(&be);
+        assert_eq!(format.encoding, Encoding::Utf16Be);
+        assert!(!format.bom);
+    }
+
+    #[test]
+    fn invalid_utf8_falls_back_to_latin1() {
+        let (text, format) = decode(b"caf\xE9\n");
+        assert_eq!(text, "café\n");
+        assert_eq!(format.encoding, Encoding::Latin1);
+        assert_eq!(encode(&text, &format).unwrap(), b"caf\xE9\n");
+    }
+
+    #[test]
+    fn undecodable_data_after_a_bom_is_kept() {
+        let format = round_trip(b"\xEF\xBB\xBFcaf\xE9\n");
+        assert_eq!(format.encoding, Encoding::Latin1);
+        assert!(!format.bom);
+
+        let format = round_trip(b"\xFF\xFEh\0i\0\n");
+        assert_eq!(format.encoding, Encoding::Latin1);
+        let format = round_trip(b"\xFE\xFF\xDC\x00");
+        assert_eq!(format.encoding, Encoding::Latin1);
+    }
+
+    #[test]
+    fn latin1_refuses_unencodable_characters() {
+        let format = FileFormat {
+            encoding: Encoding::Latin1,
+            ..FileFormat::default()
+        };
+        assert!(encode("λ", &format).is_err());
+    }
+
+    #[test]
+    fn names_match_vim() {
+        assert_eq!(LineEnding::CrLf.to_string(), "dos");
+        assert_eq!("utf-16le".parse::<Encoding>().unwrap(), Encoding::Utf16Le);
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::hash::Hasher;
+use std::io::{self, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use strum_macros::EnumString;
+
+use crate::app::AppResult;
+use crate::utils::get_data_dir;
+
+/// How a copy of the previous file contents is kept when saving.
+#[derive(Debug, Clone, Copy, PartialEq, Default, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum BackupMode {
+    /// Don't keep a backup.
+    #[default]
+    Off,
+    /// Keep a single `file~` next to the original.
+    Adjacent,
+    /// Keep timestamped copies in the backup directory.
+    Directory,
+}
+
+/// What a file looked like on disk when it was last read or written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileStamp {
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+    pub hash: u64,
+}
+
+impl FileStamp {
+    pub fn new(metadata: &Metadata, contents: &[u8]) -> Self {
+        Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: hash_contents(contents),
+        }
+    }
+
+    /// Whether the metadata still matches, this is cheap enough to poll.
+    pub fn matches_metadata(&self, metadata: &Metadata) -> bool {
+        self.modified == metadata.modified().ok() && self.len == metadata.len()
+    }
+}
+
+/// How the file on disk relates to the last [`FileStamp`] taken of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiskStatus {
+    Unchanged,
+    Changed,
+    Deleted,
+}
+
+pub fn hash_contents(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(contents);
+    hasher.finish()
+}
+
+/// Reads a file along with a stamp describing it.
+pub fn read_with_stamp(path: &Path) -> io::Result<(Vec<u8>, FileStamp)> {
+    let contents = fs::read(path)?;
+    let metadata = fs::metadata(path)?;
+    let stamp = FileStamp::new(&metadata, &contents);
+    Ok((contents, stamp))
+}
+
+/// Directory timestamped backups are written to.
+pub fn get_backup_dir() -> PathBuf {
+    get_data_dir().join("backups")
+}
+
+/// Writes `contents` to `path` without ever leaving a half written file behind.
+///
+/// The data is written to a temporary file in the same directory, synced to
+/// disk and then renamed over the original. Symlinks are followed so the link
+/// itself is kept and its target is replaced. Mode and ownership of an existing
+/// file are carried over to the new one.
+pub fn atomic_write(path: &Path, contents: &[u8], backup: BackupMode) -> AppResult<()> {
+    let target = resolve_symlinks(path)?;
+    let existing = fs::metadata(&target).ok();
+
+    if existing.is_some() {
+        write_backup(&target, backup)?;
+    }
+
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let temp_path = temp_path_for(&target);
+
+    let result = (|| -> AppResult<()> {
+        let mut temp = create_temp(&temp_path)?;
+        temp.write_all(contents)?;
+
+        if let Some(metadata) = &existing {
+            temp.set_permissions(fs::Permissions::from_mode(metadata.mode()))?;
+            // Changing the owner needs privileges we usually don't have,
+            // keeping our own ownership is the best we can do then.
+            let _ = std::os::unix::fs::fchown(&temp, Some(metadata.uid()), Some(metadata.gid()));
+        }
+
+        temp.sync_all()?;
+        fs::rename(&temp_path, &target)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+
+    // Make sure the rename itself survives a crash.
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Follows symlinks until reaching a path that is not one, which may not exist yet.
+fn resolve_symlinks(path: &Path) -> AppResult<PathBuf> {
+    let mut resolved = path.to_path_buf();
+    // Guard against symlink loops
+    for _ in 0..40 {
+        match fs::symlink_metadata(&resolved) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let link = fs::read_link(&resolved)?;
+                resolved = match resolved.parent() {
+                    Some(parent) => parent.join(link),
+                    None => link,
+                };
+            }
+            _ => return Ok(resolved),
+        }
+    }
+    Err(format!("Too many levels of symbolic links: {}", path.display()).into())
+}
+
+fn temp_path_for(target: &Path) -> PathBuf {
+    let file_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    target.with_file_name(format!(".{}.miv-{}.tmp", file_name, std::process::id()))
+}
+
+/// Creates the temporary file, replacing one left behind by an earlier run
+/// that happened to have the same process id.
+fn create_temp(temp_path: &Path) -> io::Result<File> {
+    let open = || {
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(temp_path)
+    };
+    match open() {
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            fs::remove_file(temp_path)?;
+            open()
+        }
+        result => result,
+    }
+}
+
+fn write_backup(target: &Path, backup: BackupMode) -> AppResult<()> {
+    match backup {
+        BackupMode::Off => {}
+        BackupMode::Adjacent => {
+            let mut backup_name = target.as_os_str().to_owned();
+            backup_name.push("~");
+            fs::copy(target, PathBuf::from(backup_name))?;
+        }
+        BackupMode::Directory => {
+            let backup_dir = get_backup_dir();
+            fs::create_dir_all(&backup_dir)?;
+            // Encode the full path into the name so files with the same name
+            // in different directories don't overwrite each other's backups.
+            let encoded = target
+                .to_string_lossy()
+                .replace(std::path::MAIN_SEPARATOR, "%");
+            fs::copy(target, backup_path(&backup_dir, &encoded))?;
+        }
+    }
+    Ok(())
+}
+
+/// A name in `backup_dir` no earlier backup has, from the time down to the
+/// nanosecond and a counter for clocks too coarse to tell saves apart.
+fn backup_path(backup_dir: &Path, encoded: &str) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let stem = format!(
+        "{}.{}.{:09}",
+        encoded,
+        timestamp.as_secs(),
+        timestamp.subsec_nanos()
+    );
+    let mut path = backup_dir.join(format!("{}~", stem));
+    let mut counter = 1;
+    while path.exists() {
+        path = backup_dir.join(format!("{}-{}~", stem, counter));
+        counter += 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod miv_file_io_tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn write_replaces_contents_and_keeps_mode() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("script.sh");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+
+        atomic_write(&path, b"new", BackupMode::Off).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o750);
+        // No temporary files left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn write_goes_through_symlinks() {
+        let dir = tempdir().unwrap();
+        let real = dir.path().join("real.txt");
+        let link = dir.path().join("link.txt");
+        fs::write(&real, "old").unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        atomic_write(&link, b"new", BackupMode::Off).unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&real).unwrap(), "new");
+    }
+
+    #[test]
+    fn adjacent_backup_keeps_previous_contents() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "old").unwrap();
+
+        atomic_write(&path, b"new", BackupMode::Adjacent).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("notes.txt~")).unwrap(),
+            "old"
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+    }
+
+    #[test]
+    fn stale_temp_files_are_replaced() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(temp_path_for(&path), "left over").unwrap();
+
+        atomic_write(&path, b"new", BackupMode::Off).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn backups_in_the_same_second_are_all_kept() {
+        let dir = tempdir().unwrap();
+        let first = backup_path(dir.path(), "notes");
+        fs::write(&first, "one").unwrap();
+        let second = backup_path(dir.path(), "notes");
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn stamp_notices_size_changes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("stamped.txt");
+        fs::write(&path, "old").unwrap();
+        let (_, stamp) = read_with_stamp(&path).unwrap();
+        assert!(stamp.matches_metadata(&fs::metadata(&path).unwrap()));
+
+        fs::write(&path, "changed").unwrap();
+        assert!(!stamp.matches_metadata(&fs::metadata(&path).unwrap()));
+    }
+}
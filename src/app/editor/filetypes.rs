@@ -1,4 +1,355 @@
-#[derive(Debug)]
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::app::config::FileTypeConfig;
+
+use super::highlighting::grammars;
+
+/// The kind of file in a buffer, deciding how it's highlighted.
+///
+/// Written in lowercase, as in `:set filetype=markdown` or the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum FileType {
     Rust,
+    Toml,
+    Markdown,
+    Json,
+    Yaml,
+    Python,
+    Shell,
+    Make,
+    /// Anything else, shown without highlighting
+    Text,
+    /// A language whose grammar was installed into the config directory
+    Installed(&'static str),
+}
+
+const BUILTIN_FILETYPES: &[(&str, FileType)] = &[
+    ("rust", FileType::Rust),
+    ("toml", FileType::Toml),
+    ("markdown", FileType::Markdown),
+    ("json", FileType::Json),
+    ("yaml", FileType::Yaml),
+    ("python", FileType::Python),
+    ("shell", FileType::Shell),
+    ("make", FileType::Make),
+    ("text", FileType::Text),
+];
+
+lazy_static! {
+    /// Names of installed grammars that have been used, kept for the whole
+    /// run so `FileType` can stay `Copy`
+    static ref INSTALLED_NAMES: Mutex<HashSet<&'static str>> = Mutex::new(HashSet::new());
+}
+
+impl FileType {
+    /// Formatter used by `:Format` unless the config names another, reading
+    /// the text on stdin and writing it formatted to stdout. `{file}` stands
+    /// for the buffer's path.
+    pub fn default_formatter(self) -> Option<&'static str> {
+        match self {
+            FileType::Rust => Some("rustfmt --edition 2021"),
+            FileType::Toml => Some("taplo fmt -"),
+            FileType::Markdown | FileType::Json | FileType::Yaml => {
+                Some("prettier --stdin-filepath {file}")
+            }
+            FileType::Python => Some("black --quiet -"),
+            FileType::Shell => Some("shfmt"),
+            FileType::Make | FileType::Text | FileType::Installed(_) => None,
+        }
+    }
+
+    fn installed(name: &str) -> Self {
+        let mut names = INSTALLED_NAMES.lock().unwrap();
+        let name = match names.get(name) {
+            Some(name) => *name,
+            None => {
+                let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+                names.insert(name);
+                name
+            }
+        };
+        FileType::Installed(name)
+    }
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FileType::Installed(name) => name,
+            filetype => BUILTIN_FILETYPES
+                .iter()
+                .find(|(_, builtin)| builtin == filetype)
+                .map(|(name, _)| name)
+                .unwrap(),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for FileType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.to_lowercase();
+        if let Some((_, filetype)) = BUILTIN_FILETYPES
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+        {
+            return Ok(*filetype);
+        }
+        if grammars::is_installed(&name) {
+            return Ok(FileType::installed(&name));
+        }
+        Err(format!("Invalid filetype: {}", name))
+    }
+}
+
+impl<'de> Deserialize<'de> for FileType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Files recognized by their whole name
+const FILENAMES: &[(&str, FileType)] = &[
+    ("Cargo.toml", FileType::Toml),
+    ("Cargo.lock", FileType::Toml),
+    ("Pipfile", FileType::Toml),
+    ("Makefile", FileType::Make),
+    ("makefile", FileType::Make),
+    ("GNUmakefile", FileType::Make),
+    (".bashrc", FileType::Shell),
+    (".bash_profile", FileType::Shell),
+    (".zshrc", FileType::Shell),
+    (".profile", FileType::Shell),
+];
+
+/// Files recognized by their extension, which is compared in lowercase
+const EXTENSIONS: &[(&str, FileType)] = &[
+    ("rs", FileType::Rust),
+    ("toml", FileType::Toml),
+    ("md", FileType::Markdown),
+    ("markdown", FileType::Markdown),
+    ("json", FileType::Json),
+    ("yaml", FileType::Yaml),
+    ("yml", FileType::Yaml),
+    ("py", FileType::Python),
+    ("pyi", FileType::Python),
+    ("sh", FileType::Shell),
+    ("bash", FileType::Shell),
+    ("zsh", FileType::Shell),
+    ("mk", FileType::Make),
+    ("txt", FileType::Text),
+];
+
+/// Scripts recognized by the program named in their `#!` line
+const INTERPRETERS: &[(&str, FileType)] = &[
+    ("python", FileType::Python),
+    ("sh", FileType::Shell),
+    ("bash", FileType::Shell),
+    ("dash", FileType::Shell),
+    ("ksh", FileType::Shell),
+    ("zsh", FileType::Shell),
+    ("make", FileType::Make),
+];
+
+/// Files recognized by how their first line starts
+const FIRST_LINES: &[(&str, FileType)] = &[("#![", FileType::Rust), ("//!", FileType::Rust)];
+
+/// Info strings rustdoc reads as attributes of a Rust code block
+const RUSTDOC_ATTRIBUTES: &[&str] = &["ignore", "no_run", "should_panic", "compile_fail"];
+
+/// Lines at either end of a file searched for a modeline
+const MODELINES: usize = 5;
+
+/// Works out the filetype of a file from, in order of precedence, a
+/// modeline, its name, its extension, its `#!` line and its first line.
+///
+/// The tables in `config` are looked at before the built in ones.
+pub fn detect_filetype(path: Option<&Path>, content: &str, config: &FileTypeConfig) -> FileType {
+    let lookup = |user: &HashMap<String, FileType>, builtin: &[(&str, FileType)], key: &str| {
+        user.get(key).copied().or_else(|| {
+            builtin
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, filetype)| *filetype)
+        })
+    };
+
+    if let Some(filetype) = modeline_filetype(content) {
+        return filetype;
+    }
+    let file_name = path
+        .and_then(Path::file_name)
+        .and_then(|name| name.to_str());
+    if let Some(filetype) = file_name.and_then(|name| lookup(&config.filename, FILENAMES, name)) {
+        return filetype;
+    }
+    let extension = path
+        .and_then(Path::extension)
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase);
+    if let Some(filetype) = extension
+        .as_ref()
+        .and_then(|extension| lookup(&config.extension, EXTENSIONS, extension))
+    {
+        return filetype;
+    }
+    // An installed grammar is used for files with its name as extension
+    if let Some(filetype) = extension
+        .filter(|extension| grammars::is_installed(extension))
+        .map(|extension| FileType::installed(&extension))
+    {
+        return filetype;
+    }
+
+    let first_line = content.lines().next().unwrap_or_default();
+    if let Some(interpreter) = shebang_interpreter(first_line) {
+        // `python3.11` is looked up as itself and then as `python`
+        let unversioned = interpreter.trim_end_matches(|ch: char| ch.is_ascii_digit() || ch == '.');
+        if let Some(filetype) = lookup(&config.interpreter, INTERPRETERS, interpreter)
+            .or_else(|| lookup(&config.interpreter, INTERPRETERS, unversioned))
+        {
+            return filetype;
+        }
+    }
+    let user_first_lines = config
+        .first_line
+        .iter()
+        .map(|(prefix, filetype)| (prefix.as_str(), *filetype));
+    let builtin_first_lines = FIRST_LINES.iter().copied();
+    user_first_lines
+        .chain(builtin_first_lines)
+        .find(|(prefix, _)| first_line.starts_with(prefix))
+        .map(|(_, filetype)| filetype)
+        .unwrap_or(FileType::Text)
+}
+
+/// Filetype for a language as named after a Markdown code fence or in an
+/// injection query, such as `rust`, `py` or `sh`.
+pub fn filetype_for_language(name: &str) -> Option<FileType> {
+    let name = name
+        .split(|ch: char| ch == ',' || ch.is_whitespace())
+        .next()?
+        .to_lowercase();
+    if RUSTDOC_ATTRIBUTES.contains(&name.as_str()) || name.starts_with("edition") {
+        return Some(FileType::Rust);
+    }
+    let known = |table: &[(&str, FileType)]| {
+        table
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, filetype)| *filetype)
+    };
+    name.parse()
+        .ok()
+        .or_else(|| known(EXTENSIONS))
+        .or_else(|| known(INTERPRETERS))
+}
+
+/// Program run by a `#!` line, looking past `env` and its options.
+fn shebang_interpreter(first_line: &str) -> Option<&str> {
+    let command = first_line.strip_prefix("#!")?;
+    // `#![...]` is a Rust attribute rather than a shebang
+    if command.starts_with('[') {
+        return None;
+    }
+    let mut words = command.split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+    if program != "env" {
+        return Some(program);
+    }
+    words.find(|word| !word.starts_with('-') && !word.contains('='))
+}
+
+/// Filetype set by a vim style modeline such as `# vim: set ft=python:`
+/// near the start or end of the file.
+fn modeline_filetype(content: &str) -> Option<FileType> {
+    let lines: Vec<&str> = content.lines().collect();
+    let tail_start = lines.len().saturating_sub(MODELINES).max(MODELINES);
+    let candidates = lines
+        .iter()
+        .take(MODELINES)
+        .chain(lines.iter().skip(tail_start));
+    for line in candidates {
+        // The marker has to start the line or follow a space, so `index:`
+        // isn't taken for `ex:`
+        let Some(settings) = ["vim:", "vi:", "ex:", "miv:"].iter().find_map(|marker| {
+            line.match_indices(marker)
+                .find(|(at, _)| *at == 0 || line[..*at].ends_with(char::is_whitespace))
+                .map(|(at, _)| &line[at + marker.len()..])
+        }) else {
+            continue;
+        };
+        let filetype = settings
+            .split(|ch: char| ch.is_whitespace() || ch == ':')
+            .filter_map(|setting| setting.split_once('='))
+            .find(|(name, _)| matches!(*name, "ft" | "filetype"))
+            .and_then(|(_, value)| value.parse().ok());
+        if filetype.is_some() {
+            return filetype;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod miv_filetypes_tests {
+    use super::*;
+
+    fn detect(path: &str, content: &str) -> FileType {
+        detect_filetype(Some(Path::new(path)), content, &FileTypeConfig::default())
+    }
+
+    #[test]
+    fn names_and_extensions() {
+        assert_eq!(detect("src/main.rs", ""), FileType::Rust);
+        assert_eq!(detect("README.MD", ""), FileType::Markdown);
+        assert_eq!(detect("Cargo.toml", ""), FileType::Toml);
+        assert_eq!(detect("Makefile", ""), FileType::Make);
+        assert_eq!(detect("notes", "hello"), FileType::Text);
+    }
+
+    #[test]
+    fn shebangs_modelines_and_first_lines() {
+        assert_eq!(detect("run", "#!/usr/bin/env python3\n"), FileType::Python);
+        assert_eq!(
+            detect("run", "#!/usr/bin/env -S python3.11 -u\n"),
+            FileType::Python
+        );
+        assert_eq!(detect("run", "#!/bin/bash\n"), FileType::Shell);
+        assert_eq!(detect("lib", "#![no_std]\n"), FileType::Rust);
+        assert_eq!(
+            detect("notes.txt", "# vim: set ft=markdown:\n"),
+            FileType::Markdown
+        );
+        let long = format!("{}// vim: filetype=rust\n", "x\n".repeat(20));
+        assert_eq!(detect("notes", &long), FileType::Rust);
+        assert_eq!(detect("notes", "vim: ft=nonsense"), FileType::Text);
+        assert_eq!(detect("notes", "index: ft=rust"), FileType::Text);
+    }
+
+    #[test]
+    fn config_tables_come_first() {
+        let mut config = FileTypeConfig::default();
+        config.extension.insert("rs".into(), FileType::Text);
+        config.filename.insert("Justfile".into(), FileType::Make);
+        config.interpreter.insert("node".into(), FileType::Text);
+        config.first_line.insert("%YAML".into(), FileType::Yaml);
+        let detect =
+            |path: &str, content: &str| detect_filetype(Some(Path::new(path)), content, &config);
+        assert_eq!(detect("main.rs", ""), FileType::Text);
+        assert_eq!(detect("Justfile", ""), FileType::Make);
+        assert_eq!(detect("x", "%YAML 1.2\n"), FileType::Yaml);
+        assert_eq!(detect("x", "#!/usr/bin/env node\n"), FileType::Text);
+    }
 }
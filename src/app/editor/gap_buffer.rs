@@ -1,9 +1,16 @@
 use std::{cmp::max, fmt, str};
 
+/// Extra room allocated when an insert doesn't fit in the gap, see the
+/// `gapgrowth` option.
+pub const DEFAULT_GROWTH: usize = 1024;
+
 pub struct GapBuffer {
     pub buffer: Vec<char>,
     pub gap_start: usize,
     pub gap_length: usize,
+    pub growth: usize,
+    /// Where each line starts, the first always at 0
+    line_starts: Vec<usize>,
 }
 
 impl GapBuffer {
@@ -29,7 +36,10 @@ impl GapBuffer {
             buffer,
             gap_start,
             gap_length,
+            growth: DEFAULT_GROWTH,
+            line_starts: vec![0],
         };
+        gb.line_starts.extend(newlines(data, 0));
         gb.move_gap(0);
         gb
     }
@@ -58,7 +68,15 @@ impl GapBuffer {
 
     pub fn insert_at(&mut self, data: &str, at: usize) {
         self.move_gap(at);
-        self.insert(data)
+        self.insert(data);
+
+        let line = self.line_of(at);
+        let inserted = data.chars().count();
+        for start in &mut self.line_starts[line + 1..] {
+            *start += inserted;
+        }
+        self.line_starts
+            .splice(line + 1..line + 1, newlines(data, at));
     }
 
     pub fn delete_at(&mut self, num_to_delete: usize, at: usize) {
@@ -68,6 +86,15 @@ impl GapBuffer {
 
         self.move_gap(at);
         self.gap_length += num_to_delete;
+
+        let first = self.line_starts.partition_point(|&start| start <= at);
+        let last = self
+            .line_starts
+            .partition_point(|&start| start <= at + num_to_delete);
+        self.line_starts.drain(first..last);
+        for start in &mut self.line_starts[first..] {
+            *start -= num_to_delete;
+        }
     }
 
     pub fn get_at(&self, at: usize) -> char {
@@ -78,6 +105,32 @@ impl GapBuffer {
         }
     }
 
+    /// Number of lines, text after the last newline counting as a line
+    /// even when there is none.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Line the character at `at` is on, counting from 0
+    pub fn line_of(&self, at: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= at) - 1
+    }
+
+    /// Index of the first character of `line`, if there is such a line
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.line_starts.get(line).copied()
+    }
+
+    /// Text of `line`, without the newline ending it
+    pub fn line(&self, line: usize) -> String {
+        let start = self.line_starts[line];
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map_or(self.data_length(), |next| next - 1);
+        (start..end).map(|at| self.get_at(at)).collect()
+    }
+
     fn move_gap(&mut self, pos: usize) {
         // Assert that we are only moving the gap within the data
         // of our buffer excluding the gap.
@@ -110,7 +163,7 @@ impl GapBuffer {
         let slice_to_insert: Vec<char> = data.chars().collect();
 
         if slice_to_insert.len() > self.gap_length {
-            self.grow(slice_to_insert.len() + 1024);
+            self.grow(slice_to_insert.len() + self.growth);
         }
 
         let body_slice: &mut [char] =
@@ -138,6 +191,15 @@ impl GapBuffer {
     }
 }
 
+/// Where the lines following the newlines in `data` start, `data` being
+/// at `at`
+fn newlines(data: &str, at: usize) -> impl Iterator<Item = usize> + '_ {
+    data.chars()
+        .enumerate()
+        .filter(|(_, ch)| *ch == '\n')
+        .map(move |(offset, _)| at + offset + 1)
+}
+
 impl fmt::Debug for GapBuffer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "    ")?;
@@ -289,4 +351,26 @@ mod miv_gap_buffer_tests {
         assert_eq!(gb.gap_length, 10);
         assert_eq!(gb.gap_start, 0);
     }
+
+    #[test]
+    fn line_index_follows_edits() {
+        let mut gb = GapBuffer::with_data("one\ntwo\nthree");
+        assert_eq!(gb.line_count(), 3);
+        assert_eq!(gb.line_of(4), 1);
+        assert_eq!(gb.line(2), "three");
+
+        gb.insert_at("x\ny\n", 5);
+        assert_eq!(&gb.get_text_as_string(), "one\ntx\ny\nwo\nthree");
+        assert_eq!(gb.line_count(), 5);
+        assert_eq!(gb.line(2), "y");
+        assert_eq!(gb.line_start(4), Some(12));
+
+        gb.delete_at(6, 2);
+        assert_eq!(&gb.get_text_as_string(), "on\nwo\nthree");
+        assert_eq!(gb.line_count(), 3);
+        assert_eq!(gb.line_of(3), 1);
+        assert_eq!(gb.line(1), "wo");
+        assert_eq!(gb.line_start(2), Some(6));
+        assert_eq!(gb.line_start(3), None);
+    }
 }
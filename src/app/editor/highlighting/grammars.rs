@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::env::consts::DLL_EXTENSION;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use libloading::{Library, Symbol};
+use serde::Deserialize;
+use tree_sitter::{Language, LANGUAGE_VERSION, MIN_COMPATIBLE_LANGUAGE_VERSION};
+use tree_sitter_language::LanguageFn;
+
+use crate::app::config::config_dir;
+use crate::app::jobs::{run_process, JobContext};
+
+/// Query files a language may have, each replacing the bundled one
+pub const QUERY_FILES: [&str; 3] = ["highlights.scm", "injections.scm", "locals.scm"];
+
+lazy_static! {
+    /// Grammars loaded so far, their libraries are never unloaded
+    static ref LOADED: Mutex<HashMap<String, Language>> = Mutex::new(HashMap::new());
+}
+
+/// Where compiled grammars are installed, as `<lang>.so`
+pub fn grammars_dir() -> PathBuf {
+    config_dir().join("grammars")
+}
+
+/// Where query files are looked for, as `<lang>/highlights.scm`
+pub fn queries_dir() -> PathBuf {
+    config_dir().join("queries")
+}
+
+/// Grammar names end up in file and symbol names, so only plain ones are allowed
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '_')
+}
+
+pub fn grammar_path(name: &str) -> PathBuf {
+    grammars_dir().join(format!("{}.{}", name, DLL_EXTENSION))
+}
+
+pub fn is_installed(name: &str) -> bool {
+    is_valid_name(name) && grammar_path(name).is_file()
+}
+
+/// Loads the installed grammar for `name`, once per run.
+pub fn load_language(name: &str) -> Result<Language, String> {
+    let mut loaded = LOADED.lock().unwrap();
+    if let Some(language) = loaded.get(name) {
+        return Ok(language.clone());
+    }
+    let language = load_library(&grammar_path(name), name)?;
+    loaded.insert(name.into(), language.clone());
+    Ok(language)
+}
+
+fn load_library(path: &Path, name: &str) -> Result<Language, String> {
+    let error = |err: String| format!("Grammar {}: {}", name, err);
+    // SAFETY: the library is a tree-sitter grammar, whose initialisers do
+    // nothing but set up static tables
+    let library = unsafe { Library::new(path) }.map_err(|err| error(err.to_string()))?;
+    let symbol_name = format!("tree_sitter_{}", name);
+    // SAFETY: every tree-sitter grammar exports this function with this signature
+    let language = unsafe {
+        let symbol: Symbol<unsafe extern "C" fn() -> *const ()> = library
+            .get(symbol_name.as_bytes())
+            .map_err(|err| error(err.to_string()))?;
+        Language::new(LanguageFn::from_raw(*symbol))
+    };
+    let version = language.abi_version();
+    if !(MIN_COMPATIBLE_LANGUAGE_VERSION..=LANGUAGE_VERSION).contains(&version) {
+        return Err(error(format!(
+            "built for tree-sitter ABI {}, miv supports {} to {}",
+            version, MIN_COMPATIBLE_LANGUAGE_VERSION, LANGUAGE_VERSION
+        )));
+    }
+    // The language points into the library, which has to stay loaded
+    std::mem::forget(library);
+    Ok(language)
+}
+
+/// The user's version of a query file for `name`, if they have one.
+pub fn query_override(name: &str, file: &str) -> Result<Option<String>, String> {
+    read_query(&queries_dir(), name, file)
+}
+
+fn read_query(dir: &Path, name: &str, file: &str) -> Result<Option<String>, String> {
+    let path = dir.join(name).join(file);
+    match fs::read_to_string(&path) {
+        Ok(query) => Ok(Some(query)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("{}: {}", path.display(), err)),
+    }
+}
+
+/// Compiles the grammar whose sources are in `source_dir` into the
+/// grammars directory, copying its queries along if there aren't any yet.
+/// Runs as a job, the compiler being killed if it's cancelled.
+///
+/// Returns the name of the grammar.
+pub fn install_local(source_dir: &Path, context: &JobContext) -> Result<String, String> {
+    let name = grammar_name(source_dir)?;
+    fs::create_dir_all(grammars_dir()).map_err(|err| err.to_string())?;
+    context.progress(format!("compiling {}", name));
+    let output = run_process(
+        compile_command(source_dir, &grammar_path(&name)),
+        None,
+        context,
+    )?;
+    if !output.success() {
+        return Err(format!(
+            "Compiling {} failed: {}",
+            name,
+            output.stderr.trim()
+        ));
+    }
+
+    context.progress(format!("copying {} queries", name));
+    let queries = queries_dir().join(&name);
+    for file in QUERY_FILES {
+        let source = source_dir.join("queries").join(file);
+        let target = queries.join(file);
+        if source.is_file() && !target.exists() {
+            fs::create_dir_all(&queries).map_err(|err| err.to_string())?;
+            fs::copy(&source, &target).map_err(|err| err.to_string())?;
+        }
+    }
+    // A reinstalled grammar is picked up on the next run
+    Ok(name)
+}
+
+/// The part of a generated `grammar.json` that's needed here
+#[derive(Deserialize)]
+struct GrammarJson {
+    name: String,
+}
+
+/// Name of the grammar in `source_dir`, from its generated `grammar.json`
+/// or failing that the directory name without `tree-sitter-`.
+fn grammar_name(source_dir: &Path) -> Result<String, String> {
+    if !source_dir.join("src").join("parser.c").is_file() {
+        return Err(format!("No src/parser.c in {}", source_dir.display()));
+    }
+    let from_json = fs::read_to_string(source_dir.join("src").join("grammar.json"))
+        .ok()
+        .and_then(|json| serde_json::from_str::<GrammarJson>(&json).ok());
+    let name = match from_json {
+        Some(grammar) => grammar.name,
+        None => source_dir
+            .canonicalize()
+            .ok()
+            .and_then(|dir| Some(dir.file_name()?.to_str()?.to_string()))
+            .map(|dir| dir.trim_start_matches("tree-sitter-").replace('-', "_"))
+            .unwrap_or_default(),
+    };
+    if !is_valid_name(&name) {
+        return Err(format!("Unusable grammar name: {:?}", name));
+    }
+    Ok(name)
+}
+
+/// Builds the grammar as a shared library with the system compiler, `CC`
+/// or `CXX` when the external scanner is C++.
+fn compile_command(source_dir: &Path, output_path: &Path) -> Command {
+    let src = source_dir.join("src");
+    let cpp_scanner = src.join("scanner.cc");
+    let c_scanner = src.join("scanner.c");
+    let is_cpp = cpp_scanner.is_file();
+    let compiler = if is_cpp {
+        std::env::var("CXX").unwrap_or_else(|_| "c++".into())
+    } else {
+        std::env::var("CC").unwrap_or_else(|_| "cc".into())
+    };
+
+    let mut command = Command::new(compiler);
+    command
+        .args(["-shared", "-fPIC", "-O2", "-I"])
+        .arg(&src)
+        .arg("-o")
+        .arg(output_path);
+    if is_cpp {
+        command.args(["-x", "c"]).arg(src.join("parser.c"));
+        command.args(["-x", "c++"]).arg(cpp_scanner);
+    } else {
+        command.arg(src.join("parser.c"));
+        if c_scanner.is_file() {
+            command.arg(c_scanner);
+        }
+    }
+    command
+}
+
+#[cfg(test)]
+mod miv_grammars_tests {
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    /// A directory holding an empty `src/parser.c`, like a grammar checkout
+    fn grammar_dir() -> TempDir {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src").join("parser.c"), "").unwrap();
+        dir
+    }
+
+    #[test]
+    fn grammar_names_come_from_grammar_json() {
+        let checkout = grammar_dir();
+        let dir = checkout.path();
+        fs::write(
+            dir.join("src").join("grammar.json"),
+            r#"{"rules": {"x": {"type": "SYMBOL", "name": "y"}}, "name": "go"}"#,
+        )
+        .unwrap();
+        assert_eq!(grammar_name(dir).unwrap(), "go");
+
+        fs::remove_file(dir.join("src").join("grammar.json")).unwrap();
+        let named = dir.join("tree-sitter-ocaml");
+        fs::create_dir_all(named.join("src")).unwrap();
+        fs::write(named.join("src").join("parser.c"), "").unwrap();
+        assert_eq!(grammar_name(&named).unwrap(), "ocaml");
+
+        assert!(grammar_name(Path::new("/nonexistent")).is_err());
+    }
+
+    #[test]
+    fn compile_command_picks_the_compiler_for_the_scanner() {
+        let checkout = grammar_dir();
+        let dir = checkout.path();
+        let command = compile_command(dir, Path::new("out.so"));
+        assert_eq!(command.get_args().count(), 8);
+
+        fs::write(dir.join("src").join("scanner.cc"), "").unwrap();
+        let command = compile_command(dir, Path::new("out.so"));
+        let args: Vec<_> = command.get_args().collect();
+        assert!(args.contains(&std::ffi::OsStr::new("c++")));
+    }
+
+    #[test]
+    fn queries_are_read_when_present() {
+        let queries = tempdir().unwrap();
+        let dir = queries.path();
+        fs::create_dir_all(dir.join("rust")).unwrap();
+        fs::write(
+            dir.join("rust").join("highlights.scm"),
+            "(identifier) @type",
+        )
+        .unwrap();
+        assert_eq!(
+            read_query(dir, "rust", "highlights.scm")
+                .unwrap()
+                .as_deref(),
+            Some("(identifier) @type")
+        );
+        assert_eq!(read_query(dir, "rust", "locals.scm").unwrap(), None);
+        assert!(load_library(&dir.join("missing.so"), "missing").is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use tree_sitter_highlight::HighlightConfiguration;
+use tree_sitter_md::{HIGHLIGHT_QUERY_BLOCK, LANGUAGE};
+
+use super::new_highlight_config;
+
+/// Code blocks are highlighted in the language after their opening fence.
+///
+/// The grammar's own injections query also hands every paragraph to its
+/// separate inline grammar, which isn't highlighted here, so it's written
+/// out here instead.
+const MARKDOWN_INJECTIONS_QUERY: &str = r#"
+(fenced_code_block
+  (info_string (language) @injection.language)
+  (code_fence_content) @injection.content)
+"#;
+
+/// In doc comments, code blocks without a language are Rust like rustdoc
+/// treats them.
+const RUSTDOC_INJECTIONS_QUERY: &str = r#"
+((fenced_code_block
+  . (fenced_code_block_delimiter)
+  . (block_continuation)?
+  . (code_fence_content) @injection.content)
+ (#set! injection.language "rust"))
+"#;
+
+pub fn get_markdown_highlight_config() -> Result<HighlightConfiguration, String> {
+    new_highlight_config(
+        "markdown",
+        LANGUAGE.into(),
+        HIGHLIGHT_QUERY_BLOCK,
+        MARKDOWN_INJECTIONS_QUERY,
+        "",
+    )
+}
+
+/// Markdown as written in Rust doc comments, its queries can be
+/// overridden apart from those of Markdown files
+pub fn get_rustdoc_highlight_config() -> Result<HighlightConfiguration, String> {
+    let injections_query = format!("{}{}", MARKDOWN_INJECTIONS_QUERY, RUSTDOC_INJECTIONS_QUERY);
+    new_highlight_config(
+        "rustdoc",
+        LANGUAGE.into(),
+        HIGHLIGHT_QUERY_BLOCK,
+        &injections_query,
+        "",
+    )
+}
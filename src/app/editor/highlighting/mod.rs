@@ -1,21 +1,36 @@
+pub mod grammars;
+#[cfg(feature = "grammar-json")]
+pub mod json_highlight_config;
+#[cfg(feature = "grammar-markdown")]
+pub mod markdown_highlight_config;
+#[cfg(feature = "grammar-python")]
+pub mod python_highlight_config;
 pub mod rust_highlight_config;
+#[cfg(feature = "grammar-shell")]
+pub mod shell_highlight_config;
+#[cfg(feature = "grammar-toml")]
+pub mod toml_highlight_config;
+#[cfg(feature = "grammar-yaml")]
+pub mod yaml_highlight_config;
 
-use tree_sitter_highlight::{HighlightConfiguration, Highlighter};
+use std::cell::RefCell;
+use std::collections::HashMap;
 
-use self::rust_highlight_config::{calculate_rust_highlights, get_rust_highlight_config};
+use tracing::error;
+use tree_sitter::Language;
+use tree_sitter_highlight::{HighlightConfiguration, HighlightEvent, Highlighter};
 
-use super::filetypes::FileType;
+use super::filetypes::{filetype_for_language, FileType};
 
-pub type HighlightingFn =
-    fn(&[u8], &mut Highlighter, &HighlightConfiguration) -> Vec<HighlightSpan>;
-
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum HighlightGroup {
     Attribute,
+    Boolean,
     Comment,
     Constant,
     ConstantBuiltin,
     Constructor,
+    Embedded,
     Escape,
     Function,
     FunctionBuiltin,
@@ -23,14 +38,23 @@ pub enum HighlightGroup {
     FunctionMacro,
     Keyword,
     Label,
+    Number,
     Operator,
     Property,
     Punctuation,
     PunctuationBracket,
     PunctuationDelimiter,
+    PunctuationSpecial,
     String,
+    StringEscape,
     StringSpecial,
     Tag,
+    TextEmphasis,
+    TextLiteral,
+    TextReference,
+    TextStrong,
+    TextTitle,
+    TextUri,
     Type,
     TypeBuiltin,
     Variable,
@@ -39,6 +63,16 @@ pub enum HighlightGroup {
     None,
 }
 
+impl HighlightGroup {
+    /// Capture name the group is styled by in themes, `None` for plain text
+    pub fn name(self) -> Option<&'static str> {
+        HIGHLIGHT_NAMES
+            .iter()
+            .find(|(_, group)| *group == self)
+            .map(|(name, _)| *name)
+    }
+}
+
 #[derive(Debug)]
 pub struct HighlightSpan {
     pub group: HighlightGroup,
@@ -46,14 +80,320 @@ pub struct HighlightSpan {
     pub end: usize,
 }
 
-pub fn get_highlighting_function(filetype: FileType) -> HighlightingFn {
-    match filetype {
-        FileType::Rust => calculate_rust_highlights,
+/// Capture names the highlight queries use, along with the group each is
+/// drawn with.
+///
+/// A capture takes the longest name here that its leading dotted parts
+/// match, so `function.method.call` is drawn as `function.method` and
+/// `comment.documentation` as `comment`.
+const HIGHLIGHT_NAMES: &[(&str, HighlightGroup)] = &[
+    ("attribute", HighlightGroup::Attribute),
+    ("boolean", HighlightGroup::Boolean),
+    ("comment", HighlightGroup::Comment),
+    ("constant", HighlightGroup::Constant),
+    ("constant.builtin", HighlightGroup::ConstantBuiltin),
+    ("constructor", HighlightGroup::Constructor),
+    ("embedded", HighlightGroup::Embedded),
+    ("escape", HighlightGroup::Escape),
+    ("function", HighlightGroup::Function),
+    ("function.builtin", HighlightGroup::FunctionBuiltin),
+    ("function.method", HighlightGroup::FunctionMethod),
+    ("function.macro", HighlightGroup::FunctionMacro),
+    ("keyword", HighlightGroup::Keyword),
+    ("label", HighlightGroup::Label),
+    ("number", HighlightGroup::Number),
+    ("operator", HighlightGroup::Operator),
+    ("property", HighlightGroup::Property),
+    ("punctuation", HighlightGroup::Punctuation),
+    ("punctuation.bracket", HighlightGroup::PunctuationBracket),
+    (
+        "punctuation.delimiter",
+        HighlightGroup::PunctuationDelimiter,
+    ),
+    ("punctuation.special", HighlightGroup::PunctuationSpecial),
+    ("string", HighlightGroup::String),
+    ("string.escape", HighlightGroup::StringEscape),
+    ("string.special", HighlightGroup::StringSpecial),
+    ("tag", HighlightGroup::Tag),
+    ("text.emphasis", HighlightGroup::TextEmphasis),
+    ("text.literal", HighlightGroup::TextLiteral),
+    ("text.reference", HighlightGroup::TextReference),
+    ("text.strong", HighlightGroup::TextStrong),
+    ("text.title", HighlightGroup::TextTitle),
+    ("text.uri", HighlightGroup::TextUri),
+    ("type", HighlightGroup::Type),
+    ("type.builtin", HighlightGroup::TypeBuiltin),
+    ("variable", HighlightGroup::Variable),
+    ("variable.builtin", HighlightGroup::VariableBuiltin),
+    ("variable.parameter", HighlightGroup::VariableParameter),
+];
+
+/// Highlighting for the filetype, `None` for those without a grammar or
+/// whose grammar was left out of the build
+pub fn get_highlighting_config(
+    filetype: FileType,
+) -> Result<Option<HighlightConfiguration>, String> {
+    let config = match filetype {
+        FileType::Rust => rust_highlight_config::get_rust_highlight_config(),
+        #[cfg(feature = "grammar-toml")]
+        FileType::Toml => toml_highlight_config::get_toml_highlight_config(),
+        #[cfg(feature = "grammar-markdown")]
+        FileType::Markdown => markdown_highlight_config::get_markdown_highlight_config(),
+        #[cfg(feature = "grammar-json")]
+        FileType::Json => json_highlight_config::get_json_highlight_config(),
+        #[cfg(feature = "grammar-yaml")]
+        FileType::Yaml => yaml_highlight_config::get_yaml_highlight_config(),
+        #[cfg(feature = "grammar-python")]
+        FileType::Python => python_highlight_config::get_python_highlight_config(),
+        #[cfg(feature = "grammar-shell")]
+        FileType::Shell => shell_highlight_config::get_shell_highlight_config(),
+        FileType::Installed(name) => get_installed_highlight_config(name),
+        _ => return Ok(None),
+    };
+    config.map(Some)
+}
+
+/// Highlighting for a grammar from the grammars directory, which brings
+/// its own queries
+fn get_installed_highlight_config(name: &str) -> Result<HighlightConfiguration, String> {
+    let language = grammars::load_language(name)?;
+    if grammars::query_override(name, "highlights.scm")?.is_none() {
+        return Err(format!(
+            "No highlights.scm for {} in {}",
+            name,
+            grammars::queries_dir().display()
+        ));
+    }
+    new_highlight_config(name, language, "", "", "")
+}
+
+/// Highlighting of the languages embedded in others, such as the code
+/// blocks in Markdown, each loaded the first time it's asked for.
+#[derive(Default)]
+pub struct InjectionConfigs {
+    /// By the name the injecting query used, `None` for unknown languages
+    configs: HashMap<String, Option<HighlightConfiguration>>,
+}
+
+impl InjectionConfigs {
+    fn load(&mut self, name: &str) {
+        if !self.configs.contains_key(name) {
+            self.configs.insert(name.into(), injection_config(name));
+        }
     }
 }
 
-pub fn get_highlighting_config(filetype: FileType) -> HighlightConfiguration {
-    match filetype {
-        FileType::Rust => get_rust_highlight_config(),
+/// Highlighting for a language name used by an injection
+fn injection_config(name: &str) -> Option<HighlightConfiguration> {
+    let config = match name {
+        #[cfg(feature = "grammar-markdown")]
+        "rustdoc" => markdown_highlight_config::get_rustdoc_highlight_config().map(Some),
+        name => get_highlighting_config(filetype_for_language(name)?),
+    };
+    // There's nowhere to show the error in the middle of highlighting
+    config.unwrap_or_else(|err| {
+        error!("Highlighting injected {}: {}", name, err);
+        None
+    })
+}
+
+/// Builds the highlighting for the grammar of language `name` from its
+/// queries, set up to report the captures in [`HIGHLIGHT_NAMES`].
+///
+/// Query files the user put in the queries directory replace the ones given.
+pub fn new_highlight_config(
+    name: &str,
+    language: Language,
+    highlights_query: &str,
+    injections_query: &str,
+    locals_query: &str,
+) -> Result<HighlightConfiguration, String> {
+    let [highlights_query, injections_query, locals_query] = [
+        (grammars::QUERY_FILES[0], highlights_query),
+        (grammars::QUERY_FILES[1], injections_query),
+        (grammars::QUERY_FILES[2], locals_query),
+    ]
+    .map(|(file, query)| {
+        grammars::query_override(name, file).map(|user| user.unwrap_or_else(|| query.into()))
+    });
+    let mut config = HighlightConfiguration::new(
+        language,
+        name,
+        &highlights_query?,
+        &injections_query?,
+        &locals_query?,
+    )
+    .map_err(|err| format!("Queries for {}: {}", name, err))?;
+    let names: Vec<&str> = HIGHLIGHT_NAMES.iter().map(|(name, _)| *name).collect();
+    config.configure(&names);
+    Ok(config)
+}
+
+/// Highlights `content`, splitting it into spans of one group each.
+///
+/// Embedded languages are loaded into `injections` as they're found, which
+/// takes another pass over the content for each level of nesting.
+pub fn calculate_highlights(
+    content: &[u8],
+    highlighter: &mut Highlighter,
+    highlighter_config: &HighlightConfiguration,
+    injections: &mut InjectionConfigs,
+) -> Vec<HighlightSpan> {
+    loop {
+        let missing = RefCell::new(vec![]);
+        let spans = highlight_pass(
+            content,
+            highlighter,
+            highlighter_config,
+            |name| match injections.configs.get(name) {
+                Some(config) => config.as_ref(),
+                None => {
+                    missing.borrow_mut().push(name.to_string());
+                    None
+                }
+            },
+        );
+        let missing = missing.into_inner();
+        if missing.is_empty() {
+            return spans;
+        }
+        for name in missing {
+            injections.load(&name);
+        }
+    }
+}
+
+fn highlight_pass<'a>(
+    content: &'a [u8],
+    highlighter: &'a mut Highlighter,
+    highlighter_config: &'a HighlightConfiguration,
+    injection_callback: impl FnMut(&str) -> Option<&'a HighlightConfiguration> + 'a,
+) -> Vec<HighlightSpan> {
+    let Ok(highlights) =
+        highlighter.highlight(highlighter_config, content, None, injection_callback)
+    else {
+        return vec![];
+    };
+
+    // Injected highlights sit inside the ones around them, and hand the
+    // text back to those when they end
+    let mut open_groups = vec![];
+    let mut highlit_groups = vec![];
+    for event in highlights {
+        let Ok(event) = event else {
+            break;
+        };
+        match event {
+            HighlightEvent::Source { start, end } => highlit_groups.push(HighlightSpan {
+                group: open_groups.last().copied().unwrap_or(HighlightGroup::None),
+                start,
+                end,
+            }),
+            HighlightEvent::HighlightStart(highlight) => open_groups.push(
+                HIGHLIGHT_NAMES
+                    .get(highlight.0)
+                    .map_or(HighlightGroup::None, |(_, group)| *group),
+            ),
+            HighlightEvent::HighlightEnd => {
+                open_groups.pop();
+            }
+        }
+    }
+    highlit_groups
+}
+
+// The tests use every bundled grammar
+#[cfg(all(
+    test,
+    feature = "grammar-json",
+    feature = "grammar-markdown",
+    feature = "grammar-python",
+    feature = "grammar-shell",
+    feature = "grammar-toml",
+    feature = "grammar-yaml"
+))]
+mod miv_highlighting_tests {
+    use super::*;
+
+    /// Group of the span the first `text` in `content` starts in
+    fn group_of(filetype: FileType, content: &str, text: &str) -> HighlightGroup {
+        let at = content.find(text).unwrap();
+        let config = get_highlighting_config(filetype).unwrap().unwrap();
+        let spans = calculate_highlights(
+            content.as_bytes(),
+            &mut Highlighter::new(),
+            &config,
+            &mut InjectionConfigs::default(),
+        );
+        spans
+            .iter()
+            .find(|span| (span.start..span.end).contains(&at))
+            .map(|span| span.group)
+            .unwrap()
+    }
+
+    #[test]
+    fn every_bundled_grammar_loads() {
+        for filetype in [
+            FileType::Rust,
+            FileType::Toml,
+            FileType::Markdown,
+            FileType::Json,
+            FileType::Yaml,
+            FileType::Python,
+            FileType::Shell,
+        ] {
+            assert!(
+                get_highlighting_config(filetype).unwrap().is_some(),
+                "{}",
+                filetype
+            );
+        }
+        assert!(get_highlighting_config(FileType::Text).unwrap().is_none());
+    }
+
+    #[test]
+    fn captures_are_mapped_by_name() {
+        assert!(matches!(
+            group_of(FileType::Rust, "fn main() {}", "fn"),
+            HighlightGroup::Keyword
+        ));
+        assert!(matches!(
+            group_of(FileType::Toml, "name = \"miv\"\n", "name"),
+            HighlightGroup::Property
+        ));
+        assert!(matches!(
+            group_of(FileType::Python, "def f():\n    return 1\n", "def"),
+            HighlightGroup::Keyword
+        ));
+        assert!(matches!(
+            group_of(FileType::Yaml, "on: true\n", "true"),
+            HighlightGroup::Boolean
+        ));
+        assert!(matches!(
+            group_of(FileType::Shell, "echo hi # there\n", "# there"),
+            HighlightGroup::Comment
+        ));
+    }
+
+    #[test]
+    fn injected_languages_are_highlighted() {
+        let markdown = "# Title\n\n```python\nreturn None\n```\n";
+        assert!(matches!(
+            group_of(FileType::Markdown, markdown, "return"),
+            HighlightGroup::Keyword
+        ));
+
+        // Untagged code blocks in doc comments are Rust, the comment text
+        // around them keeps its colour
+        let rust = "/// Adds\n/// ```\n/// let x = 1;\n/// ```\nfn f() {}\n";
+        assert!(matches!(
+            group_of(FileType::Rust, rust, "let"),
+            HighlightGroup::Keyword
+        ));
+        assert!(matches!(
+            group_of(FileType::Rust, rust, "/// Adds\n"),
+            HighlightGroup::Comment
+        ));
     }
 }
@@ -0,0 +1,8 @@
+use tree_sitter_highlight::HighlightConfiguration;
+use tree_sitter_python::{HIGHLIGHTS_QUERY, LANGUAGE};
+
+use super::new_highlight_config;
+
+pub fn get_python_highlight_config() -> Result<HighlightConfiguration, String> {
+    new_highlight_config("python", LANGUAGE.into(), HIGHLIGHTS_QUERY, "", "")
+}
@@ -0,0 +1,8 @@
+use tree_sitter_bash::{HIGHLIGHT_QUERY, LANGUAGE};
+use tree_sitter_highlight::HighlightConfiguration;
+
+use super::new_highlight_config;
+
+pub fn get_shell_highlight_config() -> Result<HighlightConfiguration, String> {
+    new_highlight_config("shell", LANGUAGE.into(), HIGHLIGHT_QUERY, "", "")
+}
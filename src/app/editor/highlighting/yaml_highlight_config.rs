@@ -0,0 +1,8 @@
+use tree_sitter_highlight::HighlightConfiguration;
+use tree_sitter_yaml::{HIGHLIGHTS_QUERY, LANGUAGE};
+
+use super::new_highlight_config;
+
+pub fn get_yaml_highlight_config() -> Result<HighlightConfiguration, String> {
+    new_highlight_config("yaml", LANGUAGE.into(), HIGHLIGHTS_QUERY, "", "")
+}
@@ -1,24 +1,45 @@
+pub mod encoding;
+pub mod file_io;
 pub mod filetypes;
 pub mod gap_buffer;
 pub mod highlighting;
 pub mod motions;
+pub mod style_cache;
+pub mod swap;
+pub mod transforms;
+pub mod undo;
+pub mod viewport;
 
 use std::fmt::{self, Debug};
-use std::fs::write;
-use std::{env, fs, path::PathBuf};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use std::{env, fs};
+
+use similar::{capture_diff_slices, Algorithm, DiffTag, TextDiff};
+use tracing::error;
 
-use tracing::info;
 use tree_sitter_highlight::{HighlightConfiguration, Highlighter};
 
+use self::encoding::{decode, encode, FileFormat};
+use self::file_io::{atomic_write, read_with_stamp, BackupMode, DiskStatus, FileStamp};
 use self::highlighting::HighlightSpan;
 use self::motions::Motion;
+use self::swap::{read_swap, record_dirty_buffer, remove_swap, write_swap, SwapState};
+use self::transforms::{
+    changed_lines, fix_end_of_line, retab, trim_trailing_whitespace, SaveTransforms, TrimMode,
+};
 use self::{
-    filetypes::FileType,
+    filetypes::{detect_filetype, FileType},
     gap_buffer::GapBuffer,
-    highlighting::{get_highlighting_config, get_highlighting_function, HighlightingFn},
+    highlighting::{calculate_highlights, get_highlighting_config, InjectionConfigs},
+    style_cache::StyleCache,
+    undo::{Edit, UndoHistory},
+    viewport::{RowPos, Viewport},
 };
 
-use super::{AppResult, InputMode};
+use super::shell::{self, ShellError};
+use super::{config::FileTypeConfig, AppResult, InputMode};
 
 pub struct EditorBuffer {
     /// Position of cursor in the editor area.
@@ -27,16 +48,50 @@ pub struct EditorBuffer {
     pub cursor_line: usize,
     /// Current cursor column
     pub cursor_col: usize,
+    /// First screen row shown in the window
+    pub top: RowPos,
+    /// First screen column shown when lines aren't wrapped
+    pub left_col: usize,
+    /// Where the buffer was last drawn
+    pub viewport: Viewport,
+    /// Other end of the selection, the cursor being one end
+    pub selection: Option<usize>,
     /// Gap buffer storing text
     pub gap_buffer: GapBuffer,
     /// Gap buffer storing text
     pub path: Option<PathBuf>,
     pub filetype: FileType,
+    /// Line ending and encoding the file is written back with
+    pub file_format: FileFormat,
+    /// Whether and where to keep a copy of the file before saving
+    pub backup: BackupMode,
+    /// Command reading the text on stdin and writing it formatted to stdout
+    pub formatter: Option<String>,
+    /// Run the formatter before every write
+    pub format_on_save: bool,
+    /// Clean ups run before every write
+    pub save_transforms: SaveTransforms,
+    /// Incremented on every change to the text
+    change_tick: usize,
+    /// Changes that `u` takes back
+    pub undo: UndoHistory,
+    /// Value of `change_tick` when the buffer was last written
+    saved_tick: usize,
+    /// State of the file on disk when it was last read or written
+    pub disk_stamp: Option<FileStamp>,
+    /// Refuse to write without !
+    pub read_only: bool,
+    /// Crash recovery bookkeeping
+    pub swap: SwapState,
     /// Highlighting utilities
     pub highlighter: Highlighter,
-    pub highlighter_config: HighlightConfiguration,
+    pub highlighter_config: Option<HighlightConfiguration>,
+    pub injection_configs: InjectionConfigs,
     pub highlight_groups: Vec<HighlightSpan>,
-    highlighting_function: HighlightingFn,
+    /// Whether the text changed since the highlights were worked out
+    highlights_stale: bool,
+    /// Styled lines, kept between frames
+    pub style_cache: StyleCache,
 }
 
 impl Debug for EditorBuffer {
@@ -53,53 +108,340 @@ impl Debug for EditorBuffer {
 impl Default for EditorBuffer {
     fn default() -> Self {
         let highlighter = Highlighter::new();
-        let highlighter_config = get_highlighting_config(FileType::Rust);
-        let highlighter_function = get_highlighting_function(FileType::Rust);
+        let highlighter_config = None;
 
         EditorBuffer {
             cursor_index: 0,
             cursor_line: 0,
             cursor_col: 0,
+            top: RowPos::default(),
+            left_col: 0,
+            viewport: Viewport::default(),
+            selection: None,
             gap_buffer: GapBuffer::with_data(""),
             path: None,
-            filetype: FileType::Rust,
+            filetype: FileType::Text,
+            file_format: FileFormat::default(),
+            backup: BackupMode::default(),
+            formatter: None,
+            format_on_save: false,
+            save_transforms: SaveTransforms::default(),
+            change_tick: 0,
+            undo: UndoHistory::default(),
+            saved_tick: 0,
+            disk_stamp: None,
+            read_only: false,
+            swap: SwapState::default(),
             highlighter,
             highlighter_config,
+            injection_configs: InjectionConfigs::default(),
             highlight_groups: vec![],
-            highlighting_function: highlighter_function,
+            highlights_stale: true,
+            style_cache: StyleCache::default(),
         }
     }
 }
 
 impl EditorBuffer {
     pub fn from_file(file: String) -> Self {
-        let cwd_path = env::current_dir().unwrap();
-        let full_path = cwd_path.join(file);
-        let source =
-            fs::read_to_string(&full_path).expect("Should have been able to read the file");
+        Self::open(&file).expect("Should have been able to read the file")
+    }
+
+    /// Reads a file, relative to the working directory, into a new buffer.
+    pub fn open(file: impl AsRef<Path>) -> AppResult<Self> {
+        let full_path = env::current_dir()?.join(file);
+        let (source, stamp) = read_with_stamp(&full_path)
+            .map_err(|err| format!("Can't open {}: {}", full_path.display(), err))?;
+        let (source, file_format) = decode(&source);
 
         let mut eb = EditorBuffer {
             gap_buffer: GapBuffer::with_data(&source),
             path: Some(full_path),
+            file_format,
+            disk_stamp: Some(stamp),
             ..Self::default()
         };
-        eb.calculate_highlights();
-        eb
+        // Reported once the config is read and detection runs again
+        let _ = eb.detect_filetype(&FileTypeConfig::default());
+        Ok(eb)
+    }
+
+    /// Sets the filetype from the file's name and contents.
+    pub fn detect_filetype(&mut self, config: &FileTypeConfig) -> AppResult<()> {
+        let content = self.gap_buffer.get_text_as_string();
+        self.set_filetype(detect_filetype(self.path.as_deref(), &content, config))
     }
 
-    pub fn save(&self) -> AppResult<()> {
+    /// Changes the filetype and with it the highlighting.
+    ///
+    /// A grammar or query that fails to load leaves the buffer without
+    /// highlighting.
+    pub fn set_filetype(&mut self, filetype: FileType) -> AppResult<()> {
+        if filetype == self.filetype && self.highlighter_config.is_some() {
+            return Ok(());
+        }
+        self.filetype = filetype;
+        self.highlighter_config = None;
+        let loaded =
+            get_highlighting_config(filetype).map(|config| self.highlighter_config = config);
+        self.invalidate_highlights();
+        Ok(loaded?)
+    }
+
+    /// Writes the buffer to its file, after running the save transforms.
+    pub fn save(&mut self, force: bool) -> AppResult<()> {
+        self.check_writable(force)?;
+        if self.path.is_some() {
+            self.apply_save_transforms();
+        }
+        self.write()
+    }
+
+    /// Refuses a write if the file is read-only or was changed by something
+    /// else since we last read or wrote it, unless `force` is set.
+    pub fn check_writable(&mut self, force: bool) -> AppResult<()> {
+        if !force && self.read_only {
+            return Err("File is read-only (add ! to override)".into());
+        }
+        if !force && self.disk_status() == DiskStatus::Changed {
+            return Err("File changed on disk since reading it (add ! to override)".into());
+        }
+        Ok(())
+    }
+
+    /// Whether saving runs the formatter before writing.
+    pub fn formats_on_save(&self) -> bool {
+        self.path.is_some() && self.format_on_save && self.formatter.is_some()
+    }
+
+    /// Writes the text to the file as it is.
+    pub fn write(&mut self) -> AppResult<()> {
         if let Some(path) = &self.path {
-            write(path, self.gap_buffer.get_text_as_string())?;
+            let contents = encode(&self.gap_buffer.get_text_as_string(), &self.file_format)?;
+            atomic_write(path, &contents, self.backup)?;
+            self.disk_stamp = Some(FileStamp::new(&fs::metadata(path)?, &contents));
+            self.saved_tick = self.change_tick;
+            if self.swap.owned {
+                // Nothing left to recover
+                self.write_swap();
+            }
             return Ok(());
         }
         Err("Failed to save".into())
     }
 
+    /// Changes the format the file is written with, which counts as a change.
+    pub fn set_file_format(&mut self, file_format: FileFormat) {
+        if file_format != self.file_format {
+            self.file_format = file_format;
+            self.change_tick += 1;
+        }
+    }
+
+    /// Looks for a swap file left behind by a crash or another instance.
+    ///
+    /// If there is nothing to ask the user about the swap is claimed right
+    /// away, otherwise it is kept in `swap.found` until they decide.
+    pub fn check_swap(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let text = self.gap_buffer.get_text_as_string();
+        match read_swap(path) {
+            Some(found) if found.in_use => self.swap.found = Some(found),
+            Some(found) if found.contents.as_ref().is_some_and(|c| *c != text) => {
+                self.swap.found = Some(found)
+            }
+            _ => self.claim_swap(),
+        }
+    }
+
+    /// Makes this instance the one keeping the swap file up to date.
+    pub fn claim_swap(&mut self) {
+        self.swap.found = None;
+        self.swap.owned = true;
+        self.write_swap();
+    }
+
+    /// Replaces the buffer with the unsaved contents from the found swap file.
+    pub fn recover_swap(&mut self) -> AppResult<()> {
+        let Some(contents) = self.swap.found.as_ref().and_then(|f| f.contents.clone()) else {
+            return Err("Nothing to recover".into());
+        };
+        self.gap_buffer = GapBuffer::with_data(&contents);
+        self.cursor_index = self.cursor_index.min(self.gap_buffer.data_length());
+        self.change_tick += 1;
+        self.undo.clear();
+        self.style_cache.clear();
+        self.invalidate_highlights();
+        self.claim_swap();
+        Ok(())
+    }
+
+    /// Throws away the found swap file and takes over.
+    pub fn delete_swap(&mut self) -> AppResult<()> {
+        if let Some(path) = &self.path {
+            remove_swap(path)?;
+        }
+        self.claim_swap();
+        Ok(())
+    }
+
+    /// Leaves the found swap file alone and edits without writing.
+    pub fn open_read_only(&mut self) {
+        self.swap.found = None;
+        self.read_only = true;
+    }
+
+    /// Writes the swap file if the buffer changed since it was last written.
+    pub fn update_swap(&mut self) {
+        if !self.swap.owned || self.swap.written_tick == self.change_tick {
+            return;
+        }
+        if let Some(last_write) = self.swap.last_write {
+            if last_write.elapsed() < swap::SWAP_WRITE_INTERVAL {
+                return;
+            }
+        }
+        self.write_swap();
+    }
+
+    /// Keeps the contents the panic hook dumps up to date.
+    pub fn record_dirty(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if self.is_modified() {
+            if self.swap.recorded_tick != Some(self.change_tick) {
+                record_dirty_buffer(path, Some(self.gap_buffer.get_text_as_string()));
+                self.swap.recorded_tick = Some(self.change_tick);
+            }
+        } else if self.swap.recorded_tick.is_some() {
+            record_dirty_buffer(path, None);
+            self.swap.recorded_tick = None;
+        }
+    }
+
+    /// Removes the swap file when done editing.
+    pub fn release_swap(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        record_dirty_buffer(path, None);
+        if self.swap.owned {
+            if let Err(err) = remove_swap(path) {
+                error!("Unable to remove swap file: {}", err);
+            }
+            self.swap.owned = false;
+        }
+    }
+
+    fn write_swap(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let contents = self
+            .is_modified()
+            .then(|| self.gap_buffer.get_text_as_string());
+        if let Err(err) = write_swap(path, contents.as_deref()) {
+            error!("Unable to write swap file: {}", err);
+        }
+        self.swap.written_tick = self.change_tick;
+        self.swap.last_write = Some(Instant::now());
+    }
+
+    /// Replaces the buffer contents with what is currently on disk.
+    pub fn reload(&mut self) -> AppResult<()> {
+        let Some(path) = &self.path else {
+            return Err("No file name".into());
+        };
+        let (source, stamp) = read_with_stamp(path)?;
+        let (source, file_format) = decode(&source);
+        self.gap_buffer = GapBuffer::with_data(&source);
+        self.file_format = file_format;
+        self.disk_stamp = Some(stamp);
+        self.cursor_index = self.cursor_index.min(self.gap_buffer.data_length());
+        self.change_tick += 1;
+        self.saved_tick = self.change_tick;
+        self.undo.clear();
+        self.style_cache.clear();
+        self.invalidate_highlights();
+        Ok(())
+    }
+
+    /// Checks whether the file on disk still matches what we last read or wrote.
+    ///
+    /// Only the metadata is looked at unless it changed, in which case the
+    /// contents are hashed so a touched but identical file isn't reported.
+    pub fn disk_status(&mut self) -> DiskStatus {
+        let (Some(path), Some(stamp)) = (&self.path, &self.disk_stamp) else {
+            return DiskStatus::Unchanged;
+        };
+        let Ok(metadata) = fs::metadata(path) else {
+            return DiskStatus::Deleted;
+        };
+        if stamp.matches_metadata(&metadata) {
+            return DiskStatus::Unchanged;
+        }
+        match read_with_stamp(path) {
+            Ok((_, new_stamp)) if new_stamp.hash == stamp.hash => {
+                self.disk_stamp = Some(new_stamp);
+                DiskStatus::Unchanged
+            }
+            Ok(_) => DiskStatus::Changed,
+            Err(_) => DiskStatus::Deleted,
+        }
+    }
+
+    /// Accepts whatever is on disk as the new baseline without reloading,
+    /// so the next write replaces it.
+    pub fn acknowledge_disk_state(&mut self) {
+        self.disk_stamp = self
+            .path
+            .as_ref()
+            .and_then(|path| read_with_stamp(path).ok())
+            .map(|(_, stamp)| stamp);
+    }
+
+    /// Unified diff going from the file on disk to the buffer contents.
+    pub fn disk_diff(&self) -> AppResult<Vec<String>> {
+        let Some(path) = &self.path else {
+            return Err("No file name".into());
+        };
+        let (on_disk, _) = decode(&fs::read(path)?);
+        let in_buffer = self.gap_buffer.get_text_as_string();
+        let diff = TextDiff::from_lines(&on_disk, &in_buffer)
+            .unified_diff()
+            .header("on disk", "buffer")
+            .to_string();
+        Ok(diff.lines().map(String::from).collect())
+    }
+
+    /// Goes up with every change to the text, to tell whether it changed
+    /// while waiting on something
+    pub fn change_tick(&self) -> usize {
+        self.change_tick
+    }
+
+    /// Whether the buffer has changes that haven't been written yet
+    pub fn is_modified(&self) -> bool {
+        self.change_tick != self.saved_tick
+    }
+
     pub fn insert(&mut self, to_insert: String, mode: InputMode) {
-        self.gap_buffer.insert_at(&to_insert, self.cursor_index);
+        let inserted = to_insert.chars().count();
+        if inserted == 0 {
+            return;
+        }
+        self.splice(self.cursor_index, 0, &to_insert, self.cursor_index);
+        self.change_tick += 1;
 
-        self.move_cursor(&Motion::CharForward, mode);
-        self.calculate_highlights();
+        // Insert mode leaves the cursor after the text, normal mode on its last character
+        self.cursor_index += inserted;
+        if let InputMode::Normal = mode {
+            self.cursor_index -= 1;
+        }
+        self.invalidate_highlights();
     }
 
     pub fn move_cursor(&mut self, motion: &Motion, mode: InputMode) {
@@ -119,19 +461,108 @@ impl EditorBuffer {
             Motion::NextLineStart => self.move_to_next_line_start(),
         }
 
-        let final_char = self.gap_buffer.get_at(self.cursor_index);
+        // The cursor may sit just past the last character
+        let on_char = self.cursor_index < self.gap_buffer.data_length();
         if let InputMode::Normal = mode {
-            if final_char == '\n' {
+            if on_char && self.gap_buffer.get_at(self.cursor_index) == '\n' {
                 self.cursor_index -= 1
             }
         };
     }
 
+    /// Moves the cursor to a character index, dropping any selection.
+    pub fn set_cursor(&mut self, index: usize, mode: InputMode) {
+        self.selection = None;
+        let data_length = self.gap_buffer.data_length();
+        self.cursor_index = index.min(data_length);
+        // Normal mode keeps the cursor on the last character rather than the line end
+        if let InputMode::Normal = mode {
+            let on_line_end = self.cursor_index < data_length
+                && self.gap_buffer.get_at(self.cursor_index) == '\n';
+            if on_line_end
+                && self.cursor_index > 0
+                && self.gap_buffer.get_at(self.cursor_index - 1) != '\n'
+            {
+                self.cursor_index -= 1;
+            }
+        }
+    }
+
+    /// Selects from `anchor` to `cursor`, both included.
+    pub fn select(&mut self, anchor: usize, cursor: usize) {
+        let data_length = self.gap_buffer.data_length();
+        self.selection = Some(anchor.min(data_length));
+        self.cursor_index = cursor.min(data_length);
+    }
+
+    /// Selects the word, or run of whitespace or punctuation, at `index`.
+    pub fn select_word(&mut self, index: usize, mode: InputMode) {
+        let chars = self.gap_buffer.get_text_as_chars();
+        if index >= chars.len() || chars[index] == '\n' {
+            self.set_cursor(index, mode);
+            return;
+        }
+        let class = |ch: char| {
+            if ch.is_whitespace() {
+                0
+            } else if ch.is_alphanumeric() || ch == '_' {
+                1
+            } else {
+                2
+            }
+        };
+        let word_class = class(chars[index]);
+        let in_word = |i: usize| chars[i] != '\n' && class(chars[i]) == word_class;
+
+        let mut start = index;
+        while start > 0 && in_word(start - 1) {
+            start -= 1;
+        }
+        let mut end = index;
+        while end + 1 < chars.len() && in_word(end + 1) {
+            end += 1;
+        }
+        self.select(start, end);
+    }
+
+    /// Characters covered by the selection, if there is one.
+    pub fn selection_range(&self) -> Option<Range<usize>> {
+        let anchor = self.selection?;
+        let start = anchor.min(self.cursor_index);
+        let end = anchor.max(self.cursor_index);
+        Some(start..(end + 1).min(self.gap_buffer.data_length()))
+    }
+
+    /// Scrolls by a number of screen rows, taking the cursor along when it
+    /// would otherwise leave the window.
+    pub fn scroll(&mut self, rows: isize, scrolloff: usize) {
+        let buffer = &self.gap_buffer;
+        let layout = self.viewport.layout;
+        self.top = if rows < 0 {
+            layout.rows_up(buffer, self.top, rows.unsigned_abs())
+        } else {
+            layout.rows_down(buffer, self.top, rows as usize)
+        };
+
+        let cursor = layout.position_of(buffer, self.cursor_index);
+        let height = self.viewport.height as usize;
+        let scrolloff = scrolloff.min(height.saturating_sub(1) / 2);
+        let first = layout.rows_down(buffer, self.top, scrolloff);
+        let last = layout
+            .rows_down(buffer, self.top, height.saturating_sub(scrolloff + 1))
+            .max(first);
+        let target = cursor.row_pos().clamp(first, last);
+        if target != cursor.row_pos() {
+            let line_start = buffer.line_start(target.line).unwrap_or_default();
+            let rows = layout.rows(&buffer.line(target.line), line_start);
+            let row = &rows[target.row.min(rows.len() - 1)];
+            self.cursor_index = row.cells.get(cursor.column).copied().unwrap_or(row.end);
+        }
+    }
+
     pub fn delete(&mut self, motion: Motion, mode: InputMode) {
         let delete_start = self.cursor_index;
         self.move_cursor(&motion, mode);
-        let candidate_char = self.gap_buffer.get_at(self.cursor_index);
-        info!("{:?}", candidate_char);
         let delete_end = self.cursor_index;
         let (amount_to_delete, at) = if delete_end < delete_start {
             (delete_start - delete_end, delete_end)
@@ -139,15 +570,265 @@ impl EditorBuffer {
             (delete_end - delete_start, delete_start)
         };
 
-        self.gap_buffer.delete_at(amount_to_delete, at);
+        if amount_to_delete > 0 {
+            self.splice(at, amount_to_delete, "", delete_start);
+            self.change_tick += 1;
+        }
         self.cursor_index = at;
-        self.calculate_highlights();
+        self.invalidate_highlights();
+    }
+
+    /// Number of lines, a final newline ending the last line rather than
+    /// starting another.
+    pub fn line_count(&self) -> usize {
+        let length = self.gap_buffer.data_length();
+        let ends_with_newline = length > 0 && self.gap_buffer.get_at(length - 1) == '\n';
+        (self.gap_buffer.line_count() - usize::from(ends_with_newline)).max(1)
+    }
+
+    /// Line a character index is on, counting from 0
+    pub fn line_of(&self, index: usize) -> usize {
+        self.gap_buffer.line_of(index)
+    }
+
+    /// Characters of the lines `first..=last`, counting from 0, including
+    /// the newline ending the last of them.
+    pub fn line_span(&self, first: usize, last: usize) -> Range<usize> {
+        let length = self.gap_buffer.data_length();
+        let start = self.gap_buffer.line_start(first).unwrap_or(length);
+        let end = self.gap_buffer.line_start(last + 1).unwrap_or(length);
+        start..end.max(start)
+    }
+
+    /// Replaces the characters in `range` with `text` as a single change,
+    /// leaving the cursor at its start.
+    pub fn replace(&mut self, range: Range<usize>, text: &str) {
+        if range.is_empty() && text.is_empty() {
+            return;
+        }
+        self.splice(range.start, range.len(), text, self.cursor_index);
+        self.change_tick += 1;
+        self.set_cursor(range.start, InputMode::Normal);
+        self.invalidate_highlights();
+    }
+
+    /// Runs the transforms that are turned on, each as a change and undo
+    /// step of its own.
+    pub fn apply_save_transforms(&mut self) {
+        let transforms = self.save_transforms;
+        let text = self.gap_buffer.get_text_as_string();
+        match transforms.trim_whitespace {
+            TrimMode::Off => {}
+            TrimMode::All => {
+                self.apply_step(&trim_trailing_whitespace(&text, None));
+            }
+            TrimMode::Modified => {
+                // Everything counts as modified in a file that isn't on disk yet
+                let on_disk = self
+                    .path
+                    .as_deref()
+                    .and_then(|path| fs::read(path).ok())
+                    .map(|contents| decode(&contents).0);
+                let changed = on_disk.map(|on_disk| changed_lines(&on_disk, &text));
+                self.apply_step(&trim_trailing_whitespace(&text, changed.as_ref()));
+            }
+        }
+        if transforms.retab {
+            self.retab();
+        }
+        if transforms.fix_end_of_line {
+            let text = self.gap_buffer.get_text_as_string();
+            self.apply_step(&fix_end_of_line(&text));
+        }
+    }
+
+    /// Converts the indentation to tabs or spaces following `expandtab`,
+    /// returning whether anything changed.
+    pub fn retab(&mut self) -> bool {
+        let text = self.gap_buffer.get_text_as_string();
+        let SaveTransforms {
+            tabstop,
+            expand_tab,
+            ..
+        } = self.save_transforms;
+        self.apply_step(&retab(&text, tabstop, expand_tab))
+    }
+
+    /// The shell command running the formatter over this buffer.
+    pub fn format_command(&self) -> Result<String, ShellError> {
+        let Some(formatter) = &self.formatter else {
+            return Err(ShellError::new(format!(
+                "No formatter for {}",
+                self.filetype
+            )));
+        };
+        let file = self
+            .path
+            .as_deref()
+            .map(|path| path.to_string_lossy())
+            .unwrap_or_default();
+        Ok(formatter.replace("{file}", &shell::quote(&file)))
+    }
+
+    /// Like [`EditorBuffer::apply_text`], in an undo step of its own.
+    pub fn apply_step(&mut self, text: &str) -> bool {
+        self.undo.close_step();
+        let changed = self.apply_text(text);
+        self.undo.close_step();
+        changed
+    }
+
+    /// Changes the text to `text` as a single change, rewriting only the
+    /// lines that differ and keeping the cursor on the same line of text.
+    ///
+    /// Returns whether anything changed.
+    pub fn apply_text(&mut self, text: &str) -> bool {
+        let old = self.gap_buffer.get_text_as_string();
+        if old == text {
+            return false;
+        }
+        let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+        let new_lines: Vec<&str> = text.split_inclusive('\n').collect();
+        // Where each old line starts, along with the end of the text
+        let mut old_starts = vec![0];
+        for line in &old_lines {
+            old_starts.push(old_starts.last().unwrap() + line.chars().count());
+        }
+        let cursor_line = old_starts
+            .partition_point(|start| *start <= self.cursor_index)
+            .saturating_sub(1)
+            .min(old_lines.len().saturating_sub(1));
+        let cursor_col = self.cursor_index - old_starts[cursor_line];
+
+        let ops = capture_diff_slices(Algorithm::Myers, &old_lines, &new_lines);
+        let mut new_cursor_line = 0;
+        for op in &ops {
+            let (tag, old_range, new_range) = op.as_tag_tuple();
+            if old_range.contains(&cursor_line) {
+                let offset = cursor_line - old_range.start;
+                new_cursor_line = match tag {
+                    DiffTag::Equal => new_range.start + offset,
+                    _ => new_range.start + offset.min(new_range.len().saturating_sub(1)),
+                };
+                break;
+            }
+        }
+        let new_cursor_line = new_cursor_line.min(new_lines.len().saturating_sub(1));
+
+        // Back to front, so the old offsets of what's left stay right
+        for op in ops.iter().rev() {
+            let (tag, old_range, new_range) = op.as_tag_tuple();
+            if tag == DiffTag::Equal {
+                continue;
+            }
+            let start = old_starts[old_range.start];
+            let end = old_starts[old_range.end];
+            let inserted = new_lines[new_range].concat();
+            self.splice(start, end - start, &inserted, self.cursor_index);
+        }
+        self.change_tick += 1;
+        self.invalidate_highlights();
+
+        let line_start: usize = new_lines[..new_cursor_line]
+            .iter()
+            .map(|line| line.chars().count())
+            .sum();
+        let line_length = new_lines
+            .get(new_cursor_line)
+            .map(|line| line.trim_end_matches('\n').chars().count())
+            .unwrap_or_default();
+        self.set_cursor(line_start + cursor_col.min(line_length), InputMode::Normal);
+        true
+    }
+
+    /// Replaces `len` characters at `at` with `text`, remembering it for
+    /// undo along with `cursor`, where the cursor was before the change.
+    fn splice(&mut self, at: usize, len: usize, text: &str, cursor: usize) {
+        let removed: String = (at..at + len).map(|i| self.gap_buffer.get_at(i)).collect();
+        self.replace_text(at, &removed, text);
+        self.undo.record(
+            Edit {
+                at,
+                removed,
+                inserted: text.into(),
+            },
+            cursor,
+        );
+    }
+
+    /// Replaces `removed`, the text at `at`, with `inserted`, dropping the
+    /// styles of the lines it touched.
+    fn replace_text(&mut self, at: usize, removed: &str, inserted: &str) {
+        let line = self.gap_buffer.line_of(at);
+        let newlines = |text: &str| text.matches('\n').count();
+        self.style_cache
+            .edit(line, newlines(removed), newlines(inserted));
+        let len = removed.chars().count();
+        if len > 0 {
+            self.gap_buffer.delete_at(len, at);
+        }
+        if !inserted.is_empty() {
+            self.gap_buffer.insert_at(inserted, at);
+        }
+    }
+
+    /// Takes back the last undo step, returning whether there was one.
+    pub fn undo(&mut self) -> bool {
+        let Some((edits, cursor)) = self.undo.undo() else {
+            return false;
+        };
+        self.apply_edits(edits, cursor);
+        true
+    }
+
+    /// Makes the last undone step again, returning whether there was one.
+    pub fn redo(&mut self) -> bool {
+        let Some((edits, cursor)) = self.undo.redo() else {
+            return false;
+        };
+        self.apply_edits(edits, cursor);
+        true
+    }
+
+    /// Applies edits from the undo history as a single change, without
+    /// recording them again.
+    fn apply_edits(&mut self, edits: Vec<Edit>, cursor: usize) {
+        for edit in edits {
+            self.replace_text(edit.at, &edit.removed, &edit.inserted);
+        }
+        self.change_tick += 1;
+        self.invalidate_highlights();
+        let last = self.gap_buffer.data_length().saturating_sub(1);
+        self.set_cursor(cursor.min(last), InputMode::Normal);
+    }
+
+    /// Marks the highlights as out of date, they're worked out again when
+    /// the buffer is next drawn so a burst of edits is only parsed once.
+    pub fn invalidate_highlights(&mut self) {
+        self.highlights_stale = true;
+    }
+
+    /// Works the highlights out again if the text changed since last time.
+    pub fn update_highlights(&mut self) {
+        if self.highlights_stale {
+            self.calculate_highlights();
+        }
     }
 
     pub fn calculate_highlights(&mut self) {
+        self.highlights_stale = false;
         let content = self.gap_buffer.get_text_as_bytes();
-        self.highlight_groups =
-            (self.highlighting_function)(&content, &mut self.highlighter, &self.highlighter_config);
+        self.highlight_groups = match &self.highlighter_config {
+            Some(highlighter_config) => calculate_highlights(
+                &content,
+                &mut self.highlighter,
+                highlighter_config,
+                &mut self.injection_configs,
+            ),
+            None => vec![],
+        };
+        self.style_cache
+            .set_highlights(&content, &self.highlight_groups);
     }
 
     fn move_forward_char(&mut self) {
@@ -396,7 +1077,8 @@ impl EditorBuffer {
     }
 
     fn move_to_line_end(&mut self) {
-        let mut candidate_index = self.cursor_index + 1;
+        // Starting on the cursor keeps it in place when already at the end
+        let mut candidate_index = self.cursor_index;
         let data_length = self.gap_buffer.data_length();
 
         while candidate_index < data_length {
@@ -408,6 +1090,125 @@ impl EditorBuffer {
             candidate_index += 1
         }
 
-        self.cursor_index = data_length - 1;
+        self.cursor_index = data_length.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod miv_editor_tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn editor(text: &str) -> EditorBuffer {
+        let mut editor = EditorBuffer::default();
+        editor.insert(text.into(), InputMode::Insert);
+        editor
+    }
+
+    #[test]
+    fn double_clicks_select_words() {
+        let mut editor = editor("let foo_bar = 1;\n");
+        editor.select_word(6, InputMode::Normal);
+        assert_eq!(editor.selection_range(), Some(4..11));
+        editor.select_word(12, InputMode::Normal);
+        assert_eq!(editor.selection_range(), Some(12..13));
+        editor.select_word(16, InputMode::Normal);
+        assert_eq!(editor.selection_range(), None);
+    }
+
+    #[test]
+    fn lines_can_be_replaced_whole() {
+        let mut editor = editor("one\ntwo\nthree");
+        assert_eq!(editor.line_count(), 3);
+        assert_eq!(editor.line_of(5), 1);
+        assert_eq!(editor.line_span(0, 1), 0..8);
+        assert_eq!(editor.line_span(2, 2), 8..13);
+
+        editor.replace(editor.line_span(1, 2), "2\n");
+        assert_eq!(editor.gap_buffer.get_text_as_string(), "one\n2\n");
+        assert_eq!(editor.cursor_index, 4);
+    }
+
+    #[test]
+    fn applied_text_keeps_the_cursor_on_its_line() {
+        let mut editor = editor("fn  main(){\nlet x=1;\n}\n");
+        editor.set_cursor(14, InputMode::Normal);
+        assert!(editor.apply_text("fn main() {\n    let x = 1;\n}\n"));
+        assert_eq!(
+            editor.gap_buffer.get_text_as_string(),
+            "fn main() {\n    let x = 1;\n}\n"
+        );
+        assert_eq!(editor.line_of(editor.cursor_index), 1);
+
+        editor.set_cursor(16, InputMode::Normal);
+        assert!(editor.apply_text("use std;\nfn main() {\n    let x = 1;\n}\n"));
+        assert_eq!(editor.cursor_index, 25);
+        assert!(!editor.apply_text("use std;\nfn main() {\n    let x = 1;\n}\n"));
+    }
+
+    #[test]
+    fn format_command_names_the_file() {
+        let mut editor = editor("");
+        assert!(editor.format_command().is_err());
+        editor.path = Some("it's.rs".into());
+        editor.formatter = Some("rustfmt --stdin-filepath {file}".into());
+        assert_eq!(
+            editor.format_command(),
+            Ok(r"rustfmt --stdin-filepath 'it'\''s.rs'".into())
+        );
+    }
+
+    #[test]
+    fn save_transforms_clean_up_changed_lines() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("transforms.txt");
+        fs::write(&path, "keep  \n").unwrap();
+        let mut editor = EditorBuffer {
+            path: Some(path.clone()),
+            save_transforms: SaveTransforms {
+                trim_whitespace: TrimMode::Modified,
+                fix_end_of_line: true,
+                retab: true,
+                tabstop: 4,
+                expand_tab: true,
+            },
+            ..editor("keep  \n\tnew  \n\n\n")
+        };
+        editor.save(false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep  \n    new\n");
+
+        // Each transform is undone on its own, the final newlines first
+        editor.undo();
+        assert_eq!(
+            editor.gap_buffer.get_text_as_string(),
+            "keep  \n    new\n\n\n"
+        );
+        editor.undo();
+        assert_eq!(
+            editor.gap_buffer.get_text_as_string(),
+            "keep  \n\tnew\n\n\n"
+        );
+        editor.undo();
+        assert_eq!(
+            editor.gap_buffer.get_text_as_string(),
+            "keep  \n\tnew  \n\n\n"
+        );
+    }
+
+    #[test]
+    fn scrolling_takes_the_cursor_along() {
+        let mut editor = editor(&format!("{}x", "x\n".repeat(9)));
+        editor.viewport.height = 3;
+        editor.set_cursor(0, InputMode::Normal);
+
+        editor.scroll(3, 0);
+        assert_eq!(editor.top, RowPos { line: 3, row: 0 });
+        assert_eq!(editor.cursor_index, 6);
+        editor.scroll(-2, 0);
+        assert_eq!(editor.cursor_index, 6);
+        editor.scroll(100, 1);
+        assert_eq!(editor.top, RowPos { line: 9, row: 0 });
+        assert_eq!(editor.cursor_index, 18);
     }
 }
@@ -0,0 +1,211 @@
+use ratatui::style::Style;
+
+use crate::app::theme::{colors::ColorMode, Theme};
+
+use super::highlighting::{HighlightGroup, HighlightSpan};
+
+/// A single character along with the style it's drawn with
+pub type Cell = (char, Style);
+
+/// What the lines were styled with besides their text and highlights, any
+/// change means restyling all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StyleKey {
+    /// `Theme::id` of the theme they were styled with
+    pub theme_id: u64,
+    pub color_mode: ColorMode,
+}
+
+/// Byte offset into a line where a highlight group starts
+type Run = (usize, HighlightGroup);
+
+#[derive(Debug, Clone, Default)]
+struct CachedLine {
+    /// Groups the line is highlighted with, plain text before the first
+    runs: Vec<Run>,
+    /// The line styled, `None` until it's next drawn
+    cells: Option<Vec<Cell>>,
+}
+
+/// The buffer's lines as styled characters, kept between frames so only
+/// the lines an edit or a change in highlighting touched are restyled, and
+/// only once they're drawn.
+#[derive(Debug, Default)]
+pub struct StyleCache {
+    key: Option<StyleKey>,
+    lines: Vec<CachedLine>,
+}
+
+impl StyleCache {
+    /// Forgets every line, for when the text is replaced wholesale.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Drops the lines an edit starting on `line` touched, `removed` and
+    /// `inserted` being the newlines it took out and put in.
+    pub fn edit(&mut self, line: usize, removed: usize, inserted: usize) {
+        let end = (line + removed + 1).min(self.lines.len());
+        if line < end {
+            self.lines.splice(
+                line..end,
+                std::iter::repeat_with(CachedLine::default).take(inserted + 1),
+            );
+        }
+    }
+
+    /// Takes the highlights worked out for `content`, dropping the lines
+    /// whose highlighting changed.
+    pub fn set_highlights(&mut self, content: &[u8], highlights: &[HighlightSpan]) {
+        let runs = line_runs(content, highlights);
+        // Only a change that bypassed `edit` leaves the lines out of step
+        if runs.len() != self.lines.len() {
+            self.lines = runs
+                .into_iter()
+                .map(|runs| CachedLine { runs, cells: None })
+                .collect();
+            return;
+        }
+        for (line, runs) in self.lines.iter_mut().zip(runs) {
+            if line.runs != runs {
+                line.runs = runs;
+                line.cells = None;
+            }
+        }
+    }
+
+    /// Line `index` styled with `theme`, styling it from `text` if it
+    /// changed since it was last drawn.
+    pub fn line(&mut self, index: usize, text: &str, theme: &Theme) -> &[Cell] {
+        let key = StyleKey {
+            theme_id: theme.id,
+            color_mode: theme.color_mode,
+        };
+        if self.key != Some(key) {
+            for line in &mut self.lines {
+                line.cells = None;
+            }
+            self.key = Some(key);
+        }
+        if index >= self.lines.len() {
+            self.lines.resize_with(index + 1, CachedLine::default);
+        }
+        let line = &mut self.lines[index];
+        line.cells
+            .get_or_insert_with(|| style_line(text, &line.runs, theme))
+    }
+}
+
+/// The highlight groups of each line of `content`, where they start.
+fn line_runs(content: &[u8], highlights: &[HighlightSpan]) -> Vec<Vec<Run>> {
+    let newlines = content.iter().filter(|&&byte| byte == b'\n').count();
+    let mut lines = vec![vec![]; newlines + 1];
+    let (mut line, mut line_start) = (0, 0);
+    for span in highlights {
+        let mut start = span.start;
+        loop {
+            let runs: &mut Vec<Run> = &mut lines[line];
+            let current = runs
+                .last()
+                .map_or(HighlightGroup::None, |(_, group)| *group);
+            if span.group != current && start < span.end {
+                runs.push((start - line_start, span.group));
+            }
+            let Some(newline) = content[start..span.end].iter().position(|&b| b == b'\n') else {
+                break;
+            };
+            line += 1;
+            line_start = start + newline + 1;
+            start = line_start;
+        }
+    }
+    lines
+}
+
+/// Styles the characters of `text` by the groups in `runs`.
+fn style_line(text: &str, runs: &[Run], theme: &Theme) -> Vec<Cell> {
+    let normal_style = theme.ui("normal");
+    let style_of = |group: HighlightGroup| match group.name() {
+        Some(name) => normal_style.patch(theme.highlight(name)),
+        None => normal_style,
+    };
+    let mut runs = runs.iter().peekable();
+    let mut style = normal_style;
+    text.char_indices()
+        .map(|(offset, ch)| {
+            while let Some((_, group)) = runs.next_if(|(start, _)| *start <= offset) {
+                style = style_of(*group);
+            }
+            (ch, style)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod miv_style_cache_tests {
+    use super::*;
+
+    fn span(group: HighlightGroup, start: usize, end: usize) -> HighlightSpan {
+        HighlightSpan { group, start, end }
+    }
+
+    #[test]
+    fn highlights_are_split_into_lines() {
+        let content = b"fn a\n/* x\ny */\n";
+        let highlights = [
+            span(HighlightGroup::Keyword, 0, 2),
+            span(HighlightGroup::None, 2, 5),
+            span(HighlightGroup::Comment, 5, 14),
+            span(HighlightGroup::None, 14, 15),
+        ];
+        assert_eq!(
+            line_runs(content, &highlights),
+            vec![
+                vec![(0, HighlightGroup::Keyword), (2, HighlightGroup::None)],
+                vec![(0, HighlightGroup::Comment)],
+                vec![(0, HighlightGroup::Comment), (4, HighlightGroup::None)],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn only_changed_lines_are_restyled() {
+        let theme = Theme::default();
+        let mut cache = StyleCache::default();
+        let content = b"let a\nlet b\nlet c";
+        let keyword = |start| span(HighlightGroup::Keyword, start, start + 3);
+        let highlights = [keyword(0), keyword(6), keyword(12)];
+        cache.set_highlights(content, &highlights);
+        for line in 0..3 {
+            cache.line(line, "let x", &theme);
+        }
+        let styled = |cache: &StyleCache| {
+            cache
+                .lines
+                .iter()
+                .map(|line| line.cells.is_some())
+                .collect::<Vec<_>>()
+        };
+
+        // Typing on the middle line
+        cache.edit(1, 0, 0);
+        cache.set_highlights(
+            b"let a\nlet bb\nlet c",
+            &[keyword(0), keyword(6), keyword(13)],
+        );
+        assert_eq!(styled(&cache), [true, false, true]);
+
+        // Opening a comment restyles the lines it covers
+        cache.line(1, "let bb", &theme);
+        cache.set_highlights(
+            b"/*t a\nlet bb\nlet c",
+            &[span(HighlightGroup::Comment, 0, 18)],
+        );
+        assert_eq!(styled(&cache), [false, false, false]);
+
+        // Joining lines
+        cache.edit(0, 1, 0);
+        assert_eq!(cache.lines.len(), 2);
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+use crate::app::AppResult;
+use crate::utils::get_data_dir;
+
+use super::file_io::{atomic_write, BackupMode};
+
+/// Minimum time between two writes of the same swap file.
+pub const SWAP_WRITE_INTERVAL: Duration = Duration::from_secs(2);
+
+lazy_static! {
+    /// Latest contents of every buffer with unsaved changes, keyed by file path.
+    ///
+    /// The panic hook can't reach the [`crate::app::App`], so this is what it
+    /// dumps to swap files before the process exits.
+    static ref DIRTY_BUFFERS: Mutex<HashMap<PathBuf, String>> = Mutex::new(HashMap::new());
+}
+
+/// A swap file left behind for the file being opened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoundSwap {
+    /// Process that wrote the swap file
+    pub pid: u32,
+    /// Whether that process is still running, i.e. editing the file right now
+    pub in_use: bool,
+    /// Unsaved contents, if the buffer was modified when the swap was written
+    pub contents: Option<String>,
+}
+
+/// Bookkeeping for the swap file of a single buffer.
+#[derive(Debug, Default)]
+pub struct SwapState {
+    /// Swap file from another or a crashed instance, waiting on the user
+    pub found: Option<FoundSwap>,
+    /// Whether this instance owns the swap file and keeps it up to date
+    pub owned: bool,
+    /// Change tick of the buffer when the swap was last written
+    pub written_tick: usize,
+    /// When the swap was last written
+    pub last_write: Option<Instant>,
+    /// Change tick last handed to the panic hook registry
+    pub recorded_tick: Option<usize>,
+}
+
+/// Directory swap files are kept in.
+pub fn get_swap_dir() -> PathBuf {
+    get_data_dir().join("swap")
+}
+
+/// Swap file location for `path`.
+///
+/// The full path is encoded into the name so files with the same name in
+/// different directories get their own swap.
+pub fn swap_path_for(path: &Path) -> PathBuf {
+    let encoded = path
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "%");
+    get_swap_dir().join(format!("{}.swp", encoded))
+}
+
+/// Writes a swap file owned by this process.
+///
+/// Passing `None` for the contents only marks the file as being edited.
+pub fn write_swap(path: &Path, contents: Option<&str>) -> AppResult<()> {
+    fs::create_dir_all(get_swap_dir())?;
+    let swap = format_swap(std::process::id(), contents);
+    atomic_write(&swap_path_for(path), swap.as_bytes(), BackupMode::Off)
+}
+
+/// Reads the swap file for `path`, if there is one.
+pub fn read_swap(path: &Path) -> Option<FoundSwap> {
+    let swap = fs::read_to_string(swap_path_for(path)).ok()?;
+    parse_swap(&swap)
+}
+
+pub fn remove_swap(path: &Path) -> io::Result<()> {
+    match fs::remove_file(swap_path_for(path)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Remembers the contents of a modified buffer for [`dump_dirty_buffers`],
+/// `None` forgets it again.
+pub fn record_dirty_buffer(path: &Path, contents: Option<String>) {
+    if let Ok(mut dirty) = DIRTY_BUFFERS.lock() {
+        match contents {
+            Some(contents) => dirty.insert(path.to_path_buf(), contents),
+            None => dirty.remove(path),
+        };
+    }
+}
+
+/// Writes every modified buffer to its swap file. Meant for the panic hook.
+pub fn dump_dirty_buffers() {
+    // The panic may have happened while the lock was held
+    let Ok(dirty) = DIRTY_BUFFERS.try_lock() else {
+        return;
+    };
+    for (path, contents) in dirty.iter() {
+        if let Err(err) = write_swap(path, Some(contents)) {
+            log::error!("Unable to write swap for {}: {}", path.display(), err);
+        }
+    }
+}
+
+/// Whether a process with the given id exists.
+pub fn process_is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks whether the process could be signalled
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+fn format_swap(pid: u32, contents: Option<&str>) -> String {
+    match contents {
+        Some(contents) => format!("miv-swap {} modified\n{}", pid, contents),
+        None => format!("miv-swap {} clean\n", pid),
+    }
+}
+
+fn parse_swap(swap: &str) -> Option<FoundSwap> {
+    let (header, contents) = swap.split_once('\n')?;
+    let mut fields = header.split(' ');
+    if fields.next()? != "miv-swap" {
+        return None;
+    }
+    let pid = fields.next()?.parse().ok()?;
+    let contents = match fields.next()? {
+        "modified" => Some(contents.to_string()),
+        _ => None,
+    };
+    Some(FoundSwap {
+        pid,
+        in_use: pid != std::process::id() && process_is_running(pid),
+        contents,
+    })
+}
+
+#[cfg(test)]
+mod miv_swap_tests {
+    use super::*;
+
+    #[test]
+    fn modified_swap_round_trips() {
+        let swap = format_swap(std::process::id(), Some("fn main() {}\n"));
+        let found = parse_swap(&swap).unwrap();
+        assert_eq!(found.pid, std::process::id());
+        assert!(!found.in_use);
+        assert_eq!(found.contents.as_deref(), Some("fn main() {}\n"));
+    }
+
+    #[test]
+    fn clean_swap_has_no_contents() {
+        let found = parse_swap(&format_swap(1, None)).unwrap();
+        assert_eq!(found.contents, None);
+    }
+
+    #[test]
+    fn garbage_is_not_a_swap() {
+        assert_eq!(parse_swap("hello\nworld"), None);
+    }
+
+    #[test]
+    fn own_process_is_running() {
+        assert!(process_is_running(std::process::id()));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+use strum_macros::{Display, EnumString};
+
+/// Which lines lose their trailing whitespace when writing, the
+/// `trimwhitespace` option.
+#[derive(Display, Debug, EnumString, PartialEq, Eq, Clone, Copy, Default)]
+#[strum(serialize_all = "lowercase")]
+pub enum TrimMode {
+    #[default]
+    Off,
+    All,
+    /// Only lines that differ from the file on disk
+    Modified,
+}
+
+/// What happens to the text before it's written, from the buffer's options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SaveTransforms {
+    pub trim_whitespace: TrimMode,
+    pub fix_end_of_line: bool,
+    pub retab: bool,
+    /// Columns a tab stands for when retabbing
+    pub tabstop: usize,
+    /// Whether retabbing indents with spaces
+    pub expand_tab: bool,
+}
+
+/// Strips spaces and tabs from the end of lines, all of them or only those
+/// in `only`, counting from 0.
+pub fn trim_trailing_whitespace(text: &str, only: Option<&HashSet<usize>>) -> String {
+    text.split_inclusive('\n')
+        .enumerate()
+        .map(|(index, line)| {
+            if only.is_some_and(|only| !only.contains(&index)) {
+                return line.to_string();
+            }
+            let (content, newline) = match line.strip_suffix('\n') {
+                Some(content) => (content, "\n"),
+                None => (line, ""),
+            };
+            format!("{}{}", content.trim_end_matches([' ', '\t']), newline)
+        })
+        .collect()
+}
+
+/// Ends the text with exactly one newline, leaving an empty text empty.
+pub fn fix_end_of_line(text: &str) -> String {
+    let content = text.trim_end_matches('\n');
+    if content.is_empty() {
+        return String::new();
+    }
+    format!("{}\n", content)
+}
+
+/// Rewrites the indentation of every line with tabs, or with spaces when
+/// `expand_tab` is set, keeping it just as wide.
+pub fn retab(text: &str, tabstop: usize, expand_tab: bool) -> String {
+    let tabstop = tabstop.max(1);
+    text.split_inclusive('\n')
+        .map(|line| {
+            let content = line.trim_start_matches([' ', '\t']);
+            let indent = &line[..line.len() - content.len()];
+            let width = indent.chars().fold(0, |width, ch| match ch {
+                '\t' => (width / tabstop + 1) * tabstop,
+                _ => width + 1,
+            });
+            let indent = if expand_tab {
+                " ".repeat(width)
+            } else {
+                "\t".repeat(width / tabstop) + &" ".repeat(width % tabstop)
+            };
+            // Blank lines keep what they have, that's for trimming to deal with
+            if content.trim().is_empty() {
+                line.to_string()
+            } else {
+                indent + content
+            }
+        })
+        .collect()
+}
+
+/// Lines of `new`, counting from 0, that aren't in `old`.
+pub fn changed_lines(old: &str, new: &str) -> HashSet<usize> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    capture_diff_slices(Algorithm::Myers, &old_lines, &new_lines)
+        .iter()
+        .map(|op| op.as_tag_tuple())
+        .filter(|(tag, _, _)| *tag != DiffTag::Equal)
+        .flat_map(|(_, _, new_range)| new_range)
+        .collect()
+}
+
+#[cfg(test)]
+mod miv_transforms_tests {
+    use super::*;
+
+    #[test]
+    fn trailing_whitespace_is_trimmed() {
+        let text = "a  \nb\t\n  \nc ";
+        assert_eq!(trim_trailing_whitespace(text, None), "a\nb\n\nc");
+        let only = HashSet::from([1]);
+        assert_eq!(
+            trim_trailing_whitespace(text, Some(&only)),
+            "a  \nb\n  \nc "
+        );
+    }
+
+    #[test]
+    fn files_end_with_one_newline() {
+        assert_eq!(fix_end_of_line("a"), "a\n");
+        assert_eq!(fix_end_of_line("a\n\n\n"), "a\n");
+        assert_eq!(fix_end_of_line("\n\n"), "");
+    }
+
+    #[test]
+    fn indentation_is_converted() {
+        let text = "\tif x:\n  \t  y\n    z\n";
+        assert_eq!(retab(text, 4, true), "    if x:\n      y\n    z\n");
+        assert_eq!(retab(text, 4, false), "\tif x:\n\t  y\n\tz\n");
+    }
+
+    #[test]
+    fn changed_lines_are_found() {
+        assert_eq!(
+            changed_lines("a\nb\nc\n", "a\nB\nc\nd\n"),
+            HashSet::from([1, 3])
+        );
+    }
+}
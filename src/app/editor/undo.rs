@@ -0,0 +1,172 @@
+/// One change to the text: `removed` replaced by `inserted` at character
+/// index `at`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub at: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+/// Edits undone and redone together, such as everything typed in one go
+/// in insert mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndoStep {
+    pub edits: Vec<Edit>,
+    /// Where the cursor was before the first edit
+    pub cursor: usize,
+}
+
+/// The changes made to a buffer, for `u` and `<C-r>`.
+///
+/// Edits collect in an open step until [`UndoHistory::close_step`] ends it,
+/// so the caller decides what makes up a step.
+#[derive(Debug, Default)]
+pub struct UndoHistory {
+    done: Vec<UndoStep>,
+    undone: Vec<UndoStep>,
+    open: Option<UndoStep>,
+}
+
+impl Edit {
+    /// The edit that takes this one back
+    fn inverse(&self) -> Self {
+        Self {
+            at: self.at,
+            removed: self.inserted.clone(),
+            inserted: self.removed.clone(),
+        }
+    }
+
+    /// Extends this edit with `next` when it carries on typing or deleting
+    /// where this one left off.
+    fn merge(&mut self, next: &Edit) -> bool {
+        let end = self.at + self.inserted.chars().count();
+        if next.removed.is_empty() && self.removed.is_empty() && next.at == end {
+            self.inserted.push_str(&next.inserted);
+            return true;
+        }
+        // Backspacing over what was just typed
+        if next.inserted.is_empty()
+            && self.removed.is_empty()
+            && next.at >= self.at
+            && next.at + next.removed.chars().count() == end
+            && self.inserted.ends_with(&next.removed)
+        {
+            self.inserted
+                .truncate(self.inserted.len() - next.removed.len());
+            return true;
+        }
+        if next.inserted.is_empty() && self.inserted.is_empty() {
+            // Backspacing, each deletion just before the last
+            if next.at + next.removed.chars().count() == self.at {
+                self.removed.insert_str(0, &next.removed);
+                self.at = next.at;
+                return true;
+            }
+            // Deleting forwards from the same spot
+            if next.at == self.at {
+                self.removed.push_str(&next.removed);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl UndoHistory {
+    /// Adds an edit to the open step, starting one with the cursor at
+    /// `cursor` if there is none. Anything undone can't be redone after.
+    pub fn record(&mut self, edit: Edit, cursor: usize) {
+        self.undone.clear();
+        let step = self.open.get_or_insert_with(|| UndoStep {
+            edits: vec![],
+            cursor,
+        });
+        let merged = step.edits.last_mut().is_some_and(|last| last.merge(&edit));
+        if !merged {
+            step.edits.push(edit);
+        }
+    }
+
+    /// Ends the open step, the next edit starting a new one.
+    pub fn close_step(&mut self) {
+        if let Some(step) = self.open.take() {
+            self.done.push(step);
+        }
+    }
+
+    /// The edits that take back the last step, in the order to apply them,
+    /// and where the cursor goes.
+    pub fn undo(&mut self) -> Option<(Vec<Edit>, usize)> {
+        self.close_step();
+        let step = self.done.pop()?;
+        let edits = step.edits.iter().rev().map(Edit::inverse).collect();
+        let cursor = step.cursor;
+        self.undone.push(step);
+        Some((edits, cursor))
+    }
+
+    /// The edits that make the last undone step again, in the order to
+    /// apply them, and where the cursor goes.
+    pub fn redo(&mut self) -> Option<(Vec<Edit>, usize)> {
+        self.close_step();
+        let step = self.undone.pop()?;
+        let edits = step.edits.clone();
+        let cursor = step.cursor;
+        self.done.push(step);
+        Some((edits, cursor))
+    }
+
+    /// Forgets everything, for when the text is replaced wholesale.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod miv_undo_tests {
+    use super::*;
+
+    fn edit(at: usize, removed: &str, inserted: &str) -> Edit {
+        Edit {
+            at,
+            removed: removed.into(),
+            inserted: inserted.into(),
+        }
+    }
+
+    #[test]
+    fn typing_merges_into_one_edit() {
+        let mut history = UndoHistory::default();
+        history.record(edit(0, "", "a"), 0);
+        history.record(edit(1, "", "b"), 1);
+        history.record(edit(1, "b", ""), 2);
+        history.close_step();
+        assert_eq!(history.done[0].edits, vec![edit(0, "", "a")]);
+
+        history.record(edit(5, "x", ""), 5);
+        history.record(edit(4, "y", ""), 5);
+        history.close_step();
+        assert_eq!(history.done[1].edits, vec![edit(4, "yx", "")]);
+    }
+
+    #[test]
+    fn steps_are_undone_and_redone() {
+        let mut history = UndoHistory::default();
+        history.record(edit(0, "", "one "), 0);
+        history.close_step();
+        history.record(edit(4, "", "two"), 4);
+        history.record(edit(0, "one", "1"), 7);
+
+        let (edits, cursor) = history.undo().unwrap();
+        assert_eq!(edits, vec![edit(0, "1", "one"), edit(4, "two", "")]);
+        assert_eq!(cursor, 4);
+        let (edits, _) = history.redo().unwrap();
+        assert_eq!(edits, vec![edit(4, "", "two"), edit(0, "one", "1")]);
+        assert!(history.redo().is_none());
+
+        history.undo().unwrap();
+        history.record(edit(0, "", "x"), 0);
+        assert!(history.redo().is_none());
+    }
+}
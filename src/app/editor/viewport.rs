@@ -0,0 +1,336 @@
+use super::gap_buffer::GapBuffer;
+
+/// Where the buffer was last drawn, so screen positions can be mapped back
+/// to positions in the text.
+#[derive(Debug, Clone, Default)]
+pub struct Viewport {
+    /// Screen position and size of the editor area, gutter included
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    /// Columns taken up by the line numbers
+    pub gutter_width: u16,
+    /// How lines were broken into rows
+    pub layout: LineLayout,
+    /// The screen rows shown, from the top of the window
+    pub rows: Vec<ViewRow>,
+}
+
+/// A single screen row of text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ViewRow {
+    /// Character index under each column, tabs covering several columns
+    pub cells: Vec<usize>,
+    /// Character index just past the row, where clicks past its end land
+    pub end: usize,
+}
+
+/// How lines are broken into screen rows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineLayout {
+    pub tabstop: usize,
+    /// Columns left for the text next to the gutter
+    pub text_width: usize,
+    /// Whether long lines carry on in the rows below
+    pub wrap: bool,
+}
+
+/// Where a character ends up once the text is laid out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    /// Screen row counting from the first row of the line
+    pub row: usize,
+    /// Screen column, tabs expanded
+    pub column: usize,
+}
+
+/// A screen row, as a line and which of its rows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RowPos {
+    pub line: usize,
+    pub row: usize,
+}
+
+impl Position {
+    pub fn row_pos(&self) -> RowPos {
+        RowPos {
+            line: self.line,
+            row: self.row,
+        }
+    }
+}
+
+impl Default for LineLayout {
+    fn default() -> Self {
+        Self {
+            tabstop: 8,
+            text_width: 1,
+            wrap: false,
+        }
+    }
+}
+
+impl Viewport {
+    pub fn contains(&self, column: u16, row: u16) -> bool {
+        (self.x..self.x + self.width).contains(&column)
+            && (self.y..self.y + self.height).contains(&row)
+    }
+
+    /// Character index at a screen position. Positions past the end of a
+    /// line or of the buffer land on the closest character.
+    pub fn index_at(&self, column: u16, row: u16, left_col: usize) -> usize {
+        let Some(last_row) = self.rows.len().checked_sub(1) else {
+            return 0;
+        };
+        let row = (row.saturating_sub(self.y) as usize).min(last_row);
+        let column = left_col
+            + column
+                .saturating_sub(self.x)
+                .saturating_sub(self.gutter_width) as usize;
+        let view_row = &self.rows[row];
+        view_row.cells.get(column).copied().unwrap_or(view_row.end)
+    }
+}
+
+impl LineLayout {
+    /// Screen column the character at `col` of `line` starts in
+    pub fn display_col(&self, line: &str, col: usize) -> usize {
+        line.chars().take(col).fold(0, |display, ch| {
+            if ch == '\t' {
+                display + self.tabstop - display % self.tabstop
+            } else {
+                display + 1
+            }
+        })
+    }
+
+    /// Screen rows `line` takes up
+    pub fn row_count(&self, line: &str) -> usize {
+        if !self.wrap {
+            return 1;
+        }
+        let width = self.display_col(line, usize::MAX);
+        width.div_ceil(self.text_width.max(1)).max(1)
+    }
+
+    /// The screen rows of `line`, whose first character is at `start`.
+    pub fn rows(&self, line: &str, start: usize) -> Vec<ViewRow> {
+        let mut cells = vec![];
+        for (offset, ch) in line.chars().enumerate() {
+            let width = if ch == '\t' {
+                self.tabstop - cells.len() % self.tabstop
+            } else {
+                1
+            };
+            cells.extend(std::iter::repeat_n(start + offset, width));
+        }
+        let end = start + line.chars().count();
+        if !self.wrap || cells.is_empty() {
+            return vec![ViewRow { cells, end }];
+        }
+        let chunks: Vec<&[usize]> = cells.chunks(self.text_width.max(1)).collect();
+        let last_chunk = chunks.len() - 1;
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| ViewRow {
+                cells: chunk.to_vec(),
+                // Only the last row of a line can be clicked past its end
+                end: match chunk.last() {
+                    Some(&last) if index < last_chunk => last,
+                    _ => end,
+                },
+            })
+            .collect()
+    }
+
+    /// Where the character at `index` of `buffer` is drawn. The end of a
+    /// line is drawn just past its last character.
+    pub fn position_of(&self, buffer: &GapBuffer, index: usize) -> Position {
+        let line = buffer.line_of(index);
+        let text = buffer.line(line);
+        let line_start = buffer.line_start(line).unwrap_or_default();
+        let display = self.display_col(&text, index - line_start);
+        let (row, column) = if self.wrap {
+            let width = self.text_width.max(1);
+            (
+                (display / width).min(self.row_count(&text)),
+                display % width,
+            )
+        } else {
+            (0, display)
+        };
+        Position { line, row, column }
+    }
+
+    /// Screen rows `line` of `buffer` takes up
+    fn line_rows(&self, buffer: &GapBuffer, line: usize) -> usize {
+        if self.wrap {
+            self.row_count(&buffer.line(line))
+        } else {
+            1
+        }
+    }
+
+    /// The row `rows` screen rows above `from`, stopping at the first.
+    pub fn rows_up(&self, buffer: &GapBuffer, from: RowPos, rows: usize) -> RowPos {
+        let RowPos { mut line, mut row } = from;
+        let mut rows = rows;
+        while rows > row {
+            if line == 0 {
+                return RowPos::default();
+            }
+            rows -= row + 1;
+            line -= 1;
+            row = self.line_rows(buffer, line) - 1;
+        }
+        RowPos {
+            line,
+            row: row - rows,
+        }
+    }
+
+    /// The row `rows` screen rows below `from`, stopping at the last.
+    pub fn rows_down(&self, buffer: &GapBuffer, from: RowPos, rows: usize) -> RowPos {
+        let last_line = buffer.line_count() - 1;
+        let RowPos { mut line, mut row } = from;
+        let mut rows = rows;
+        loop {
+            let count = self.line_rows(buffer, line);
+            if row + rows < count {
+                return RowPos {
+                    line,
+                    row: row + rows,
+                };
+            }
+            if line >= last_line {
+                return RowPos {
+                    line,
+                    row: count - 1,
+                };
+            }
+            rows -= count.saturating_sub(row).min(rows);
+            line += 1;
+            row = 0;
+        }
+    }
+
+    /// Screen rows from `from` down to `to`, or `None` when that is more
+    /// than `limit`. Only the lines in between are looked at.
+    pub fn rows_between(
+        &self,
+        buffer: &GapBuffer,
+        from: RowPos,
+        to: RowPos,
+        limit: usize,
+    ) -> Option<usize> {
+        let mut rows = 0;
+        for line in from.line..to.line {
+            rows += self.line_rows(buffer, line);
+            if rows > limit + from.row {
+                return None;
+            }
+        }
+        (rows + to.row)
+            .checked_sub(from.row)
+            .filter(|&rows| rows <= limit)
+    }
+}
+
+#[cfg(test)]
+mod miv_viewport_tests {
+    use super::*;
+
+    /// "ab\n\tc\n" with a tab stop of 2 and a gutter of 3
+    fn viewport() -> Viewport {
+        Viewport {
+            x: 0,
+            y: 0,
+            width: 20,
+            height: 10,
+            gutter_width: 3,
+            layout: layout(false),
+            rows: vec![
+                ViewRow {
+                    cells: vec![0, 1],
+                    end: 2,
+                },
+                ViewRow {
+                    cells: vec![3, 3, 4],
+                    end: 5,
+                },
+                ViewRow {
+                    cells: vec![],
+                    end: 6,
+                },
+            ],
+        }
+    }
+
+    fn layout(wrap: bool) -> LineLayout {
+        LineLayout {
+            tabstop: 2,
+            text_width: 2,
+            wrap,
+        }
+    }
+
+    #[test]
+    fn clicks_map_to_characters() {
+        let viewport = viewport();
+        assert_eq!(viewport.index_at(4, 0, 0), 1);
+        // In the gutter, the start of the line
+        assert_eq!(viewport.index_at(0, 1, 0), 3);
+        // On the tab and past the end of the line
+        assert_eq!(viewport.index_at(4, 1, 0), 3);
+        assert_eq!(viewport.index_at(15, 1, 0), 5);
+        // Below the last line
+        assert_eq!(viewport.index_at(3, 9, 0), 6);
+        // Scrolled sideways
+        assert_eq!(viewport.index_at(3, 1, 2), 4);
+    }
+
+    #[test]
+    fn characters_map_to_rows() {
+        let layout = layout(false);
+        let buffer = GapBuffer::with_data("ab\n\tc\n");
+        let position = |index| {
+            let position = layout.position_of(&buffer, index);
+            (position.line, position.column)
+        };
+        assert_eq!(position(1), (0, 1));
+        assert_eq!(position(4), (1, 2));
+        assert_eq!(position(6), (2, 0));
+        assert_eq!(layout.rows("\tc", 3), viewport().rows[1..2]);
+    }
+
+    #[test]
+    fn wrapped_lines_take_several_rows() {
+        let layout = layout(true);
+        let buffer = GapBuffer::with_data("abc\n\tc\nd");
+        let at = |line, row| RowPos { line, row };
+        assert_eq!(
+            layout.rows("abc", 0),
+            vec![
+                ViewRow {
+                    cells: vec![0, 1],
+                    end: 1,
+                },
+                ViewRow {
+                    cells: vec![2],
+                    end: 3,
+                },
+            ]
+        );
+        assert_eq!(layout.position_of(&buffer, 2).row_pos(), at(0, 1));
+        assert_eq!(layout.position_of(&buffer, 5).row_pos(), at(1, 1));
+        assert_eq!(layout.rows_down(&buffer, at(0, 0), 3), at(1, 1));
+        assert_eq!(layout.rows_down(&buffer, at(0, 1), 9), at(2, 0));
+        assert_eq!(layout.rows_up(&buffer, at(2, 0), 2), at(1, 0));
+        assert_eq!(layout.rows_up(&buffer, at(0, 1), 5), at(0, 0));
+        assert_eq!(layout.rows_between(&buffer, at(0, 1), at(2, 0), 9), Some(3));
+        assert_eq!(layout.rows_between(&buffer, at(0, 1), at(2, 0), 2), None);
+    }
+}
@@ -0,0 +1,464 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::event::Event;
+
+use super::{App, AppResult, StatusMessage};
+
+/// How often a job running a process checks whether it was cancelled
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long quitting waits for cancelled jobs to wind down
+const STOP_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub type JobId = u64;
+
+/// Runs on the main thread with what a job returned
+type OnDone = Box<dyn FnOnce(&mut App, Box<dyn Any + Send>) -> AppResult<()>>;
+
+/// A job that finished, sent back to the main loop as [`Event::JobDone`].
+pub struct JobDone {
+    pub id: JobId,
+    /// What the job returned, downcast back to its type by the job's callback
+    pub result: Result<Box<dyn Any + Send>, String>,
+}
+
+impl fmt::Debug for JobDone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobDone")
+            .field("id", &self.id)
+            .field("ok", &self.result.is_ok())
+            .finish()
+    }
+}
+
+/// What a job's worker thread gets to report progress and check whether
+/// it should stop.
+pub struct JobContext {
+    id: JobId,
+    sender: mpsc::Sender<Event>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl JobContext {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Shows `message` next to the job in the status line.
+    pub fn progress(&self, message: impl Into<String>) {
+        let _ = self.sender.send(Event::JobProgress {
+            id: self.id,
+            message: message.into(),
+        });
+    }
+}
+
+/// What a child process printed and how it exited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl ProcessOutput {
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+}
+
+struct RunningJob {
+    name: String,
+    progress: Option<String>,
+    cancelled: Arc<AtomicBool>,
+    on_done: OnDone,
+    worker: JoinHandle<()>,
+}
+
+/// Work running on worker threads, whose results come back through the
+/// event channel to be handled on the main thread.
+#[derive(Default)]
+pub struct Jobs {
+    sender: Option<mpsc::Sender<Event>>,
+    next_id: JobId,
+    running: BTreeMap<JobId, RunningJob>,
+}
+
+impl fmt::Debug for Jobs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Jobs")
+            .field("running", &self.running.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Jobs {
+    /// Sends finished jobs to `sender`, the event channel of the main loop.
+    pub fn connect(&mut self, sender: mpsc::Sender<Event>) {
+        self.sender = Some(sender);
+    }
+
+    /// Runs `work` on a worker thread, then `on_done` on the main thread
+    /// with what it returned. Neither runs `on_done` if the job fails or
+    /// is cancelled, that's reported in the status line instead.
+    pub fn spawn<T, W, D>(
+        &mut self,
+        name: impl Into<String>,
+        work: W,
+        on_done: D,
+    ) -> AppResult<JobId>
+    where
+        T: Send + 'static,
+        W: FnOnce(&JobContext) -> Result<T, String> + Send + 'static,
+        D: FnOnce(&mut App, T) -> AppResult<()> + 'static,
+    {
+        let Some(sender) = self.sender.clone() else {
+            return Err("Jobs can't run before the event loop starts".into());
+        };
+        self.next_id += 1;
+        let id = self.next_id;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let context = JobContext {
+            id,
+            sender: sender.clone(),
+            cancelled: cancelled.clone(),
+        };
+        let worker = thread::Builder::new()
+            .name(format!("miv-job-{}", id))
+            .spawn(move || {
+                let result = work(&context).map(|value| Box::new(value) as Box<dyn Any + Send>);
+                let _ = sender.send(Event::JobDone(JobDone { id, result }));
+            })?;
+
+        let on_done: OnDone = Box::new(move |app, value| match value.downcast::<T>() {
+            Ok(value) => on_done(app, *value),
+            Err(_) => Err("Job returned the wrong type".into()),
+        });
+        self.running.insert(
+            id,
+            RunningJob {
+                name: name.into(),
+                progress: None,
+                cancelled,
+                on_done,
+                worker,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Runs `command` on a worker thread with `stdin` as its input, then
+    /// `on_done` with its output whether or not it succeeded.
+    pub fn spawn_process<D>(
+        &mut self,
+        name: impl Into<String>,
+        command: Command,
+        stdin: Option<String>,
+        on_done: D,
+    ) -> AppResult<JobId>
+    where
+        D: FnOnce(&mut App, ProcessOutput) -> AppResult<()> + 'static,
+    {
+        self.spawn(
+            name,
+            move |context| run_process(command, stdin, context),
+            on_done,
+        )
+    }
+
+    /// Asks a job to stop, killing its process if it has one.
+    ///
+    /// Returns false if there is no such job.
+    pub fn cancel(&mut self, id: JobId) -> bool {
+        match self.running.get(&id) {
+            Some(job) => {
+                job.cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn cancel_all(&mut self) {
+        for job in self.running.values() {
+            job.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Cancels every job and waits a little for their workers to finish,
+    /// so no process they started outlives the editor. Workers that don't
+    /// notice being cancelled are left behind rather than holding up a quit.
+    pub fn stop_all(&mut self) {
+        self.cancel_all();
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        for (_, job) in std::mem::take(&mut self.running) {
+            while !job.worker.is_finished() && Instant::now() < deadline {
+                thread::sleep(POLL_INTERVAL);
+            }
+            if job.worker.is_finished() {
+                let _ = job.worker.join();
+            }
+        }
+    }
+
+    pub fn set_progress(&mut self, id: JobId, message: String) {
+        if let Some(job) = self.running.get_mut(&id) {
+            job.progress = Some(message);
+        }
+    }
+
+    /// Running jobs as id, name and latest progress, oldest first.
+    pub fn running(&self) -> impl Iterator<Item = (JobId, &str, Option<&str>)> {
+        self.running
+            .iter()
+            .map(|(id, job)| (*id, job.name.as_str(), job.progress.as_deref()))
+    }
+
+    pub fn is_running(&self, id: JobId) -> bool {
+        self.running.contains_key(&id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.running.is_empty()
+    }
+}
+
+impl App {
+    /// Hands a finished job's result to its callback.
+    pub fn job_done(&mut self, done: JobDone) -> AppResult<()> {
+        self.needs_redraw = true;
+        let Some(job) = self.jobs.running.remove(&done.id) else {
+            return Ok(());
+        };
+        if job.cancelled.load(Ordering::Relaxed) {
+            self.message = Some(StatusMessage::Info(format!("Cancelled {}", job.name)));
+            return Ok(());
+        }
+        match done.result {
+            Ok(value) => (job.on_done)(self, value),
+            Err(err) => Err(format!("{}: {}", job.name, err).into()),
+        }
+    }
+
+    pub fn job_progress(&mut self, id: JobId, message: String) {
+        self.jobs.set_progress(id, message);
+        self.needs_redraw = true;
+    }
+}
+
+/// Runs a child process to completion unless the job is cancelled, feeding
+/// it `stdin` and collecting its output.
+pub fn run_process(
+    mut command: Command,
+    stdin: Option<String>,
+    context: &JobContext,
+) -> Result<ProcessOutput, String> {
+    let program = command.get_program().to_string_lossy().to_string();
+    command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // Its own group, so what a shell started is killed along with it
+        .process_group(0);
+    let mut child = command
+        .spawn()
+        .map_err(|err| format!("Couldn't run {}: {}", program, err))?;
+
+    // Writing and reading happen on their own threads so a child filling
+    // up one pipe while we wait on another can't deadlock
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        thread::spawn(move || {
+            let _ = pipe.write_all(input.as_bytes());
+        });
+    }
+    let read = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut output = vec![];
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut output);
+            }
+            String::from_utf8_lossy(&output).to_string()
+        })
+    };
+    let stdout = read(child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    let stderr = read(child.stderr.take().map(|pipe| Box::new(pipe) as _));
+
+    let status = loop {
+        if context.is_cancelled() {
+            kill_group(&mut child);
+            return Err("cancelled".into());
+        }
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(err) => return Err(err.to_string()),
+        }
+    };
+    Ok(ProcessOutput {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Kills the child along with everything else in its process group.
+fn kill_group(child: &mut Child) {
+    // SAFETY: kill has no memory safety requirements, the child having
+    // made its own group the id can only name that group
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.wait();
+}
+
+/// Runs the main loop's part of jobs until none are left
+#[cfg(test)]
+pub fn run_jobs(app: &mut App, receiver: &mpsc::Receiver<Event>) {
+    while !app.jobs.is_empty() {
+        match receiver.recv_timeout(Duration::from_secs(10)).unwrap() {
+            Event::JobDone(done) => {
+                if let Err(err) = app.job_done(done) {
+                    app.message = Some(StatusMessage::Error(err.to_string()));
+                }
+            }
+            Event::JobProgress { id, message } => app.job_progress(id, message),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod miv_jobs_tests {
+    use super::*;
+
+    fn connected_app() -> (App, mpsc::Receiver<Event>) {
+        let (sender, receiver) = mpsc::channel();
+        let mut app = App::default();
+        app.jobs.connect(sender);
+        (app, receiver)
+    }
+
+    #[test]
+    fn closures_hand_their_result_to_the_main_thread() {
+        let (mut app, receiver) = connected_app();
+        app.jobs
+            .spawn(
+                "count",
+                |context| {
+                    context.progress("halfway");
+                    Ok(21 * 2)
+                },
+                |app, answer: i32| {
+                    app.command_line.value = answer.to_string();
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(app.jobs.running().count(), 1);
+        run_jobs(&mut app, &receiver);
+        assert_eq!(app.command_line.value, "42");
+    }
+
+    #[test]
+    fn processes_get_stdin_and_can_be_cancelled() {
+        let (mut app, receiver) = connected_app();
+        app.jobs
+            .spawn_process(
+                "cat",
+                Command::new("cat"),
+                Some("hello".into()),
+                |app, output| {
+                    assert!(output.success());
+                    app.command_line.value = output.stdout;
+                    Ok(())
+                },
+            )
+            .unwrap();
+        run_jobs(&mut app, &receiver);
+        assert_eq!(app.command_line.value, "hello");
+
+        let mut sleep = Command::new("sleep");
+        sleep.arg("30");
+        let id = app
+            .jobs
+            .spawn_process("sleep", sleep, None, |_, _| panic!("not cancelled"))
+            .unwrap();
+        assert!(app.jobs.cancel(id));
+        run_jobs(&mut app, &receiver);
+        assert_eq!(
+            app.message,
+            Some(StatusMessage::Info("Cancelled sleep".into()))
+        );
+    }
+
+    #[test]
+    fn stopping_kills_what_a_shell_started() {
+        let (mut app, _receiver) = connected_app();
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("pid");
+        let mut shell = Command::new("sh");
+        shell
+            .arg("-c")
+            .arg(format!("sleep 30 & echo $! > {}; wait", pid_file.display()));
+        app.jobs
+            .spawn_process("make", shell, None, |_, _| Ok(()))
+            .unwrap();
+        let pid = loop {
+            match std::fs::read_to_string(&pid_file) {
+                Ok(pid) if pid.ends_with('\n') => break pid.trim().to_string(),
+                _ => thread::sleep(POLL_INTERVAL),
+            }
+        };
+
+        app.jobs.stop_all();
+        assert!(app.jobs.is_empty());
+        // The kill lands asynchronously, and the process may not be reaped yet
+        let dead = || {
+            let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+            stat.is_empty() || stat.contains(") Z ")
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !dead() && Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+        }
+        assert!(dead());
+    }
+
+    #[test]
+    fn stopping_does_not_wait_for_jobs_ignoring_cancel() {
+        let (mut app, _receiver) = connected_app();
+        app.jobs
+            .spawn(
+                "stuck",
+                |_| {
+                    thread::sleep(Duration::from_secs(60));
+                    Ok(())
+                },
+                |_, _| Ok(()),
+            )
+            .unwrap();
+        let started = Instant::now();
+        app.jobs.stop_all();
+        assert!(started.elapsed() < STOP_TIMEOUT * 2);
+        assert!(app.jobs.is_empty());
+    }
+
+    #[test]
+    fn jobs_need_the_event_loop() {
+        let mut jobs = Jobs::default();
+        assert!(jobs.spawn("x", |_| Ok(()), |_, _| Ok(())).is_err());
+    }
+}
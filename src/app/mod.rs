@@ -1,24 +1,61 @@
 pub mod command_line;
+pub mod config;
 pub mod editor;
+pub mod jobs;
+pub mod options;
+pub mod popup;
+pub mod quickfix;
+pub mod shell;
 pub mod theme;
 
-use std::error;
+use std::path::{Path, PathBuf};
+use std::{env, error, fs};
 
+use serde::Deserialize;
 use strum_macros::{Display, EnumString};
 
-use crate::commands::Command;
+use crate::{
+    commands::Command,
+    input_handling::keymaps::{Binding, Keymaps},
+};
 
-use self::{command_line::CommandLine, editor::EditorBuffer, theme::Theme};
+use self::{
+    command_line::CommandLine,
+    config::{config_path, Config},
+    editor::{
+        encoding::FileFormat, file_io::DiskStatus, filetypes::FileType, highlighting::grammars,
+        transforms::SaveTransforms, EditorBuffer,
+    },
+    jobs::{JobId, Jobs},
+    options::Options,
+    popup::Popup,
+    quickfix::Quickfix,
+    theme::{colors::ColorMode, Theme},
+};
 
 /// Application result type.
 pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
 
 /// The editing mode of the application
-#[derive(Display, Debug, EnumString, PartialEq, Clone, Copy)]
+#[derive(Display, Debug, EnumString, PartialEq, Eq, Hash, Clone, Copy, Deserialize)]
 pub enum InputMode {
     Normal,
     Insert,
     Command,
+    /// The file changed on disk while the buffer has unsaved changes
+    Conflict,
+    /// A swap file was found when opening the file
+    Recovery,
+    /// Moving through the entries of the quickfix panel
+    Quickfix,
+}
+
+/// A message shown to the user in the status line
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatusMessage {
+    Info(String),
+    Warning(String),
+    Error(String),
 }
 
 /// Application.
@@ -30,10 +67,36 @@ pub struct App {
     pub mode: InputMode,
     /// Data for maintaining command input
     pub command_line: CommandLine,
+    /// Settings from the config file
+    pub config: Config,
+    /// Current value of every option, see `:set`
+    pub options: Options,
+    /// Key bindings of every mode, see `:map`
+    pub keymaps: Keymaps,
     /// Colors for display
     pub theme: Theme,
     /// The buffer being edited
     pub editor: EditorBuffer,
+    /// Feedback from the last command
+    pub message: Option<StatusMessage>,
+    /// Floating window shown above the editor
+    pub popup: Option<Popup>,
+    /// Keys that may follow an unfinished key sequence
+    pub which_key: Option<Popup>,
+    /// Work running in the background
+    pub jobs: Jobs,
+    /// Locations from the last `:make`
+    pub quickfix: Quickfix,
+    /// Whether anything changed since the screen was last drawn
+    pub needs_redraw: bool,
+    /// A `:!` command waiting for the main loop to hand it the terminal
+    pub pending_shell: Option<String>,
+    /// Screen row the status line was last drawn on
+    pub status_row: u16,
+    /// The job formatting the buffer before it's written
+    saving: Option<JobId>,
+    /// Whether to quit once that job has written the file
+    quit_after_save: bool,
 }
 
 impl Default for App {
@@ -42,8 +105,21 @@ impl Default for App {
             running: true,
             mode: InputMode::Normal,
             command_line: CommandLine::default(),
+            config: Config::default(),
+            options: Options::default(),
+            keymaps: Keymaps::default(),
             theme: Theme::default(),
             editor: EditorBuffer::default(),
+            message: None,
+            popup: None,
+            which_key: None,
+            jobs: Jobs::default(),
+            quickfix: Quickfix::default(),
+            needs_redraw: true,
+            pending_shell: None,
+            status_row: 0,
+            saving: None,
+            quit_after_save: false,
         }
     }
 }
@@ -51,30 +127,158 @@ impl Default for App {
 impl App {
     /// Constructs a new instance of [`App`].
     pub fn new(file: String) -> Self {
-        Self {
+        let mut editor = EditorBuffer::from_file(file);
+        editor.check_swap();
+        let mode = if editor.swap.found.is_some() {
+            InputMode::Recovery
+        } else {
+            InputMode::Normal
+        };
+
+        let mut app = Self {
             running: true,
-            mode: InputMode::Normal,
+            mode,
             command_line: CommandLine::default(),
+            config: Config::default(),
+            options: Options::default(),
+            keymaps: Keymaps::default(),
             theme: Theme::default(),
-            editor: EditorBuffer::from_file(file),
+            editor,
+            message: None,
+            popup: None,
+            which_key: None,
+            jobs: Jobs::default(),
+            quickfix: Quickfix::default(),
+            needs_redraw: true,
+            pending_shell: None,
+            status_row: 0,
+            saving: None,
+            quit_after_save: false,
+        };
+        // A broken config shouldn't stop anyone from editing
+        if let Err(err) = app.source_config(None) {
+            app.message = Some(StatusMessage::Error(err.to_string()));
         }
+        if let Err(err) = app.editor.detect_filetype(&app.config.filetype) {
+            app.message = Some(StatusMessage::Error(err.to_string()));
+        }
+        app.apply_options();
+        app
     }
 
     /// Handles the tick event of the terminal.
-    pub fn tick(&self) {}
+    pub fn tick(&mut self) {
+        self.check_disk_changes();
+        self.editor.update_swap();
+        // Once a tick rather than after every command, copying the text
+        // on each keystroke adds up in large files
+        self.editor.record_dirty();
+    }
+
+    /// Handles the terminal regaining focus.
+    pub fn focus_gained(&mut self) {
+        self.check_disk_changes();
+    }
+
+    /// Reloads the file if something else changed it, or asks what to do
+    /// when that would throw away unsaved changes.
+    fn check_disk_changes(&mut self) {
+        if let InputMode::Conflict | InputMode::Recovery = self.mode {
+            return;
+        }
+        let status = self.editor.disk_status();
+        if status != DiskStatus::Unchanged {
+            self.needs_redraw = true;
+        }
+        match status {
+            DiskStatus::Unchanged => {}
+            DiskStatus::Deleted => {
+                self.editor.acknowledge_disk_state();
+                self.message = Some(StatusMessage::Error("File was deleted on disk".into()));
+            }
+            DiskStatus::Changed if !self.editor.is_modified() => match self.editor.reload() {
+                Ok(()) => {
+                    self.message =
+                        Some(StatusMessage::Info("File changed on disk, reloaded".into()))
+                }
+                Err(err) => self.message = Some(StatusMessage::Error(err.to_string())),
+            },
+            DiskStatus::Changed => self.change_input_mode(InputMode::Conflict),
+        }
+    }
 
     /// Execute collection of commands
+    ///
+    /// A failing command is reported in the status line and stops the
+    /// remaining commands from running, so `:wq` won't quit if the write fails.
     pub fn execute(&mut self, commands: Vec<Command>) -> AppResult<()> {
+        if !commands.is_empty() {
+            self.needs_redraw = true;
+        }
         for command in commands {
-            self.execute_single_command(command)?;
+            if let Err(err) = self.execute_single_command(command) {
+                self.message = Some(StatusMessage::Error(err.to_string()));
+                break;
+            }
+        }
+        // Everything typed in one go in insert mode is undone together,
+        // in other modes each batch of commands is a step of its own
+        if self.mode != InputMode::Insert {
+            self.editor.undo.close_step();
         }
+        self.apply_options();
         Ok(())
     }
 
+    /// Hands the options to the parts of the editor that keep their own copy
+    fn apply_options(&mut self) {
+        let ftoptions = self.config.ftoptions.get(&self.editor.filetype);
+        self.options
+            .set_filetype_values(ftoptions.map(Vec::as_slice).unwrap_or_default());
+        self.editor.gap_buffer.growth = self.options.get_usize("gapgrowth");
+        self.editor.backup = self.options.get_str("backup").parse().unwrap_or_default();
+        self.theme.color_mode = ColorMode::from_option(self.options.get_str("termcolors"));
+        let formatter = match self.options.get_str("formatprg") {
+            "" => self
+                .config
+                .formatter
+                .get(&self.editor.filetype)
+                .map(String::as_str)
+                .or(self.editor.filetype.default_formatter()),
+            formatprg => Some(formatprg),
+        };
+        self.editor.formatter = formatter
+            .filter(|formatter| !formatter.is_empty())
+            .map(String::from);
+        self.editor.format_on_save = self.options.get_bool("formatonsave");
+        self.editor.save_transforms = SaveTransforms {
+            trim_whitespace: self
+                .options
+                .get_str("trimwhitespace")
+                .parse()
+                .unwrap_or_default(),
+            fix_end_of_line: self.options.get_bool("fixendofline"),
+            retab: self.options.get_bool("retabonsave"),
+            tabstop: self.options.get_usize("tabstop"),
+            expand_tab: self.options.get_bool("expandtab"),
+        };
+    }
+
     /// An internal function to run a single command
     fn execute_single_command(&mut self, command: Command) -> AppResult<()> {
+        // A selection only lasts until the next edit, motion or mode change
+        if matches!(
+            command,
+            Command::EditorInsert(_)
+                | Command::EditorDelete(_)
+                | Command::EditorMove(_)
+                | Command::ChangeInputMode(_)
+        ) {
+            self.editor.selection = None;
+        }
         match command {
-            Command::Quit => self.quit(),
+            Command::Quit | Command::QuitAll => self.quit(false)?,
+            Command::ForceQuit | Command::ForceQuitAll => self.quit(true)?,
             Command::CommandLineStop => self.command_line.deactivate(),
             Command::CommandLineInsertChar(ch) => self.command_line.enter_char(ch),
             Command::CommandLineDelete => self.command_line.delete_char(),
@@ -87,18 +291,505 @@ impl App {
             Command::EditorInsert(to_insert) => self.editor.insert(to_insert, self.mode),
             Command::EditorDelete(motion) => self.editor.delete(motion, self.mode),
             Command::EditorMove(motion) => self.editor.move_cursor(&motion, self.mode),
-            Command::EditorSave => self.editor.save()?,
+            Command::EditorSetCursor(index) => self.editor.set_cursor(index, self.mode),
+            Command::EditorSelect { anchor, cursor } => self.editor.select(anchor, cursor),
+            Command::EditorSelectWord(index) => self.editor.select_word(index, self.mode),
+            Command::EditorScroll(rows) => self
+                .editor
+                .scroll(rows, self.options.get_usize("scrolloff")),
+            Command::EditorFilterPrompt(motion) => self.filter_prompt(motion),
+            Command::EditorSave => self.save(false)?,
+            Command::EditorSaveIfModified | Command::EditorSaveAll => {
+                if self.editor.is_modified() {
+                    self.save(false)?
+                }
+            }
+            Command::EditorForceSave => self.save(true)?,
+            Command::EditorReload => self.editor.reload()?,
+            Command::EditorUndo => {
+                if !self.editor.undo() {
+                    return Err("Already at oldest change".into());
+                }
+            }
+            Command::EditorRedo => {
+                if !self.editor.redo() {
+                    return Err("Already at newest change".into());
+                }
+            }
+            Command::EditorUndoBreak => self.editor.undo.close_step(),
+            Command::EditorFormat => self.format_buffer()?,
+            Command::EditorRetab => {
+                self.editor.retab();
+            }
+            Command::EditorKeepLocal => self.editor.acknowledge_disk_state(),
+            Command::EditorShowDiskDiff => {
+                self.popup = Some(Popup::new("Changes since disk", self.editor.disk_diff()?))
+            }
+            Command::SwapRecover => self.editor.recover_swap()?,
+            Command::SwapDelete => self.editor.delete_swap()?,
+            Command::SwapOpenReadOnly => self.editor.open_read_only(),
+            Command::SetOption(args) => self.set_option(&args, false)?,
+            Command::SetLocalOption(args) => self.set_option(&args, true)?,
+            Command::SourceConfig(path) => self.source_config(path)?,
+            Command::ColorScheme(name) => self.color_scheme(name)?,
+            Command::InstallGrammar(path) => self.install_grammar(&path)?,
+            Command::Map {
+                mode,
+                lhs,
+                rhs,
+                remap,
+            } => {
+                let binding = Binding::Keys {
+                    keys: rhs,
+                    noremap: !remap,
+                    desc: None,
+                };
+                let leader = self.options.get_str("mapleader");
+                self.keymaps.map(mode, &lhs, binding, leader)?
+            }
+            Command::Unmap { mode, lhs } => {
+                let leader = self.options.get_str("mapleader");
+                self.keymaps.unmap(mode, &lhs, leader)?
+            }
+            Command::ListMappings => {
+                let mappings = self.keymaps.list();
+                if mappings.is_empty() {
+                    self.message = Some(StatusMessage::Info("No mapping found".into()));
+                } else {
+                    self.popup = Some(Popup::new("Mappings", mappings));
+                }
+            }
+            Command::ListJobs => {
+                let jobs: Vec<String> = self
+                    .jobs
+                    .running()
+                    .map(|(id, name, progress)| match progress {
+                        Some(progress) => format!("{:>3}  {}: {}", id, name, progress),
+                        None => format!("{:>3}  {}", id, name),
+                    })
+                    .collect();
+                if jobs.is_empty() {
+                    self.message = Some(StatusMessage::Info("No jobs running".into()));
+                } else {
+                    self.popup = Some(Popup::new("Jobs", jobs));
+                }
+            }
+            Command::CancelJob(Some(id)) => {
+                if !self.jobs.cancel(id) {
+                    return Err(format!("No job {}", id).into());
+                }
+            }
+            Command::CancelJob(None) => self.jobs.cancel_all(),
+            Command::Make(args) => self.make(args)?,
+            Command::QuickfixOpen => {
+                self.quickfix.open = true;
+                self.change_input_mode(InputMode::Quickfix);
+            }
+            Command::QuickfixClose => {
+                self.quickfix.open = false;
+                if self.mode == InputMode::Quickfix {
+                    self.change_input_mode(InputMode::Normal);
+                }
+            }
+            Command::QuickfixMoveSelection(step) => self.quickfix.move_selection(step),
+            Command::QuickfixJumpSelected => self.quickfix_jump(self.quickfix.selected)?,
+            Command::QuickfixNext => self.quickfix_step(1)?,
+            Command::QuickfixPrev => self.quickfix_step(-1)?,
+            Command::QuickfixSelect(number) => {
+                let index = number
+                    .map(|number| number - 1)
+                    .or(self.quickfix.current)
+                    .unwrap_or(0);
+                self.quickfix_jump(index)?
+            }
+            Command::Shell(command) => self.pending_shell = Some(command),
+            Command::FilterLines { range, command } => self.filter_lines(range, &command)?,
+            Command::ReadShell { range, command } => self.read_shell(range, &command)?,
+            Command::ShowError(message) => self.message = Some(StatusMessage::Error(message)),
+            Command::ClearMessage => self.message = None,
         };
         Ok(())
     }
 
+    /// Handles the arguments of `:set`, or `:setlocal` when `local` is set
+    fn set_option(&mut self, args: &str, local: bool) -> AppResult<()> {
+        let mut shown = vec![];
+        for arg in args.split_whitespace() {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.trim_end_matches('?'), None),
+            };
+            match (name, value) {
+                ("fileformat" | "ff", Some(value)) => {
+                    let line_ending = value
+                        .parse()
+                        .map_err(|_| format!("Invalid fileformat: {}", value))?;
+                    self.editor.set_file_format(FileFormat {
+                        line_ending,
+                        ..self.editor.file_format
+                    });
+                }
+                ("fileencoding" | "fenc", Some(value)) => {
+                    let encoding = value
+                        .parse()
+                        .map_err(|_| format!("Invalid fileencoding: {}", value))?;
+                    self.editor.set_file_format(FileFormat {
+                        encoding,
+                        ..self.editor.file_format
+                    });
+                }
+                ("filetype" | "ft", Some(value)) => {
+                    self.editor.set_filetype(value.parse()?)?;
+                }
+                ("filetype" | "ft", None) => {
+                    shown.push(format!("filetype={}", self.editor.filetype))
+                }
+                ("fileformat" | "ff", None) => shown.push(format!(
+                    "fileformat={}",
+                    self.editor.file_format.line_ending
+                )),
+                ("fileencoding" | "fenc", None) => {
+                    shown.push(format!("fileencoding={}", self.editor.file_format.encoding))
+                }
+                _ => shown.extend(self.options.apply(arg, local)?),
+            }
+        }
+        if !shown.is_empty() {
+            self.message = Some(StatusMessage::Info(shown.join("  ")));
+        }
+        Ok(())
+    }
+
+    /// Loads the config file, or the one at `path`, replacing the current settings
+    fn source_config(&mut self, path: Option<String>) -> AppResult<()> {
+        let path = path.map(PathBuf::from).unwrap_or_else(config_path);
+        let config = Config::load(&path)?;
+        let theme = Theme::load(&config.theme)?;
+        let mut options = Options::default();
+        for (name, value) in &config.options {
+            options.set(name, value.clone(), false)?;
+        }
+        let mut keymaps = Keymaps::default();
+        let leader = options.get_str("mapleader");
+        for (mode, bindings) in [
+            (InputMode::Normal, &config.keymap.normal),
+            (InputMode::Insert, &config.keymap.insert),
+            (InputMode::Command, &config.keymap.command),
+        ] {
+            for (lhs, binding) in bindings {
+                keymaps
+                    .map(mode, lhs, binding.clone(), leader)
+                    .map_err(|err| {
+                        format!("keymap.{}: {}", mode.to_string().to_lowercase(), err)
+                    })?;
+            }
+        }
+        self.options = options;
+        self.keymaps = keymaps;
+        self.config = config;
+        self.theme = theme;
+        Ok(())
+    }
+
+    /// Switches to the theme called `name`, showing the current one without a name
+    fn color_scheme(&mut self, name: Option<String>) -> AppResult<()> {
+        match name {
+            Some(name) => self.theme = Theme::load(&name)?,
+            None => self.message = Some(StatusMessage::Info(self.theme.name.clone())),
+        }
+        Ok(())
+    }
+
+    /// Builds a grammar from source in the background, using it for the
+    /// buffer if it's for a file of that language.
+    fn install_grammar(&mut self, path: &str) -> AppResult<()> {
+        let source_dir = PathBuf::from(path);
+        self.jobs.spawn(
+            "TSInstallLocal",
+            move |context| grammars::install_local(&source_dir, context),
+            |app, name: String| {
+                if app.editor.filetype == FileType::Text {
+                    app.editor.detect_filetype(&app.config.filetype)?;
+                }
+                app.message = Some(StatusMessage::Info(format!(
+                    "Installed grammar {}, use it with :set filetype={}",
+                    name, name
+                )));
+                Ok(())
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Switches the buffer to the file at `path`, unless it's the one
+    /// being edited already.
+    fn open_file(&mut self, path: &Path) -> AppResult<()> {
+        let full_path = env::current_dir()?.join(path);
+        let same_file = |a: &Path, b: &Path| match (fs::canonicalize(a), fs::canonicalize(b)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => a == b,
+        };
+        if let Some(current) = &self.editor.path {
+            if same_file(current, &full_path) {
+                return Ok(());
+            }
+        }
+        if self.editor.is_modified() {
+            return Err("No write since last change (add ! to override)".into());
+        }
+        let mut editor = EditorBuffer::open(&full_path)?;
+        editor.check_swap();
+        // Recovering would need the recovery prompt, which only shows at startup
+        if editor.swap.found.is_some() {
+            return Err(format!(
+                "Found a swap file for {}, open it with miv to recover",
+                path.display()
+            )
+            .into());
+        }
+        self.editor.release_swap();
+        self.editor = editor;
+        self.editor.detect_filetype(&self.config.filetype)?;
+        self.apply_options();
+        Ok(())
+    }
+
     /// Set running to false to quit the application.
-    fn quit(&mut self) {
+    ///
+    /// Refuses while there are unsaved changes unless `force` is set.
+    fn quit(&mut self, force: bool) -> AppResult<()> {
+        if !force && self.is_saving() {
+            self.quit_after_save = true;
+            return Ok(());
+        }
+        if !force && self.editor.is_modified() {
+            return Err("No write since last change (add ! to override)".into());
+        }
+        self.jobs.stop_all();
+        self.editor.release_swap();
         self.running = false;
+        Ok(())
     }
 
     /// Change input mode
     fn change_input_mode(&mut self, input_mode: InputMode) {
+        if input_mode == InputMode::Command {
+            self.message = None;
+        }
+        if self.mode == InputMode::Conflict {
+            self.popup = None;
+        }
         self.mode = input_mode
     }
 }
+
+#[cfg(test)]
+mod miv_app_tests {
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+    use crate::input_handling::InputStack;
+
+    #[test]
+    fn quit_is_refused_with_unsaved_changes() {
+        let mut app = App::default();
+        app.execute(vec![Command::EditorInsert("a".into()), Command::Quit])
+            .unwrap();
+        assert!(app.running);
+        assert!(matches!(app.message, Some(StatusMessage::Error(_))));
+    }
+
+    #[test]
+    fn force_quit_skips_the_modified_check() {
+        let mut app = App::default();
+        app.execute(vec![Command::EditorInsert("a".into()), Command::ForceQuit])
+            .unwrap();
+        assert!(!app.running);
+    }
+
+    #[test]
+    fn exit_does_not_write_an_unmodified_buffer() {
+        // The default buffer has no path, so writing it would fail
+        let mut app = App::default();
+        app.execute(vec![Command::EditorSaveIfModified, Command::Quit])
+            .unwrap();
+        assert!(!app.running);
+        assert_eq!(app.message, None);
+    }
+
+    #[test]
+    fn colorscheme_switches_the_theme() {
+        let mut app = App::default();
+        app.execute(vec![Command::ColorScheme(Some("catppuccin-latte".into()))])
+            .unwrap();
+        assert_eq!(app.theme.name, "catppuccin-latte");
+        app.execute(vec![Command::ColorScheme(Some("nonsense".into()))])
+            .unwrap();
+        assert_eq!(app.theme.name, "catppuccin-latte");
+        assert!(matches!(app.message, Some(StatusMessage::Error(_))));
+    }
+
+    #[test]
+    fn only_commands_ask_for_a_redraw() {
+        let mut app = App {
+            needs_redraw: false,
+            ..App::default()
+        };
+        app.execute(vec![]).unwrap();
+        assert!(!app.needs_redraw);
+        app.execute(vec![Command::EditorInsert("a".into())])
+            .unwrap();
+        assert!(app.needs_redraw);
+    }
+
+    fn scratch_file(dir: &TempDir, contents: &str) -> String {
+        let path = dir.path().join("main.rs");
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn unmodified_buffer_reloads_external_changes() {
+        let dir = tempdir().unwrap();
+        let path = scratch_file(&dir, "fn main() {}\n");
+        let mut app = App::new(path.clone());
+        std::fs::write(&path, "fn main() { todo!() }\n").unwrap();
+
+        app.tick();
+
+        assert_eq!(
+            app.editor.gap_buffer.get_text_as_string(),
+            "fn main() { todo!() }\n"
+        );
+        assert!(!app.editor.is_modified());
+        app.execute(vec![Command::Quit]).unwrap();
+    }
+
+    #[test]
+    fn modified_buffer_prompts_on_external_changes() {
+        let dir = tempdir().unwrap();
+        let path = scratch_file(&dir, "fn main() {}\n");
+        let mut app = App::new(path.clone());
+        app.execute(vec![Command::EditorInsert("x".into())])
+            .unwrap();
+        std::fs::write(&path, "fn main() { todo!() }\n").unwrap();
+
+        app.tick();
+        assert_eq!(app.mode, InputMode::Conflict);
+
+        // Writing without ! must not clobber the external change
+        app.execute(vec![Command::EditorSave]).unwrap();
+        assert!(matches!(app.message, Some(StatusMessage::Error(_))));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "fn main() { todo!() }\n"
+        );
+        app.execute(vec![Command::ForceQuit]).unwrap();
+    }
+
+    #[test]
+    fn swap_with_unsaved_changes_can_be_recovered() {
+        let dir = tempdir().unwrap();
+        let path = scratch_file(&dir, "fn main() {}\n");
+        let full_path = std::path::PathBuf::from(&path);
+        editor::swap::write_swap(&full_path, Some("fn main() { recovered() }\n")).unwrap();
+
+        let mut app = App::new(path);
+        assert_eq!(app.mode, InputMode::Recovery);
+
+        app.execute(vec![
+            Command::SwapRecover,
+            Command::ChangeInputMode(InputMode::Normal),
+        ])
+        .unwrap();
+        assert_eq!(
+            app.editor.gap_buffer.get_text_as_string(),
+            "fn main() { recovered() }\n"
+        );
+        assert!(app.editor.is_modified());
+
+        app.execute(vec![Command::ForceQuit]).unwrap();
+        assert_eq!(editor::swap::read_swap(&full_path), None);
+    }
+
+    #[test]
+    fn set_changes_and_shows_options() {
+        let mut app = App::default();
+        app.execute(vec![Command::SetOption("ts=8 nowrap gapgrowth=64".into())])
+            .unwrap();
+        assert_eq!(app.options.get_int("tabstop"), 8);
+        assert_eq!(app.editor.gap_buffer.growth, 64);
+
+        app.execute(vec![Command::SetOption("ts? ff?".into())])
+            .unwrap();
+        assert_eq!(
+            app.message,
+            Some(StatusMessage::Info("tabstop=8  fileformat=unix".into()))
+        );
+
+        app.execute(vec![Command::SetOption("ts=0".into())])
+            .unwrap();
+        assert!(matches!(app.message, Some(StatusMessage::Error(_))));
+        assert_eq!(app.options.get_int("tabstop"), 8);
+
+        app.execute(vec![Command::SetOption("ft=markdown".into())])
+            .unwrap();
+        assert_eq!(app.editor.filetype, FileType::Markdown);
+        app.execute(vec![Command::SetOption("ft=cobol".into())])
+            .unwrap();
+        assert_eq!(
+            app.message,
+            Some(StatusMessage::Error("Invalid filetype: cobol".into()))
+        );
+    }
+
+    #[test]
+    fn pasted_text_leaves_the_cursor_after_it() {
+        let mut app = App::default();
+        app.execute(vec![
+            Command::ChangeInputMode(InputMode::Insert),
+            Command::EditorInsert("fn main() {\n}\n".into()),
+            Command::EditorInsert("x".into()),
+        ])
+        .unwrap();
+        assert_eq!(
+            app.editor.gap_buffer.get_text_as_string(),
+            "fn main() {\n}\nx"
+        );
+        assert_eq!(app.editor.cursor_index, 15);
+        app.execute(vec![Command::ForceQuit]).unwrap();
+    }
+
+    #[test]
+    fn a_paste_is_undone_on_its_own() {
+        let mut app = App::default();
+        let mut input = InputStack::new();
+        app.execute(vec![
+            Command::ChangeInputMode(InputMode::Insert),
+            Command::EditorInsert("a".into()),
+            Command::EditorInsert("b".into()),
+        ])
+        .unwrap();
+        app.execute(input.handle_paste("one\ntwo\n".into(), &app.mode))
+            .unwrap();
+        app.execute(vec![Command::EditorInsert("c".into())])
+            .unwrap();
+        app.execute(vec![Command::ChangeInputMode(InputMode::Normal)])
+            .unwrap();
+
+        app.execute(vec![Command::EditorUndo]).unwrap();
+        assert_eq!(app.editor.gap_buffer.get_text_as_string(), "abone\ntwo\n");
+        app.execute(vec![Command::EditorUndo]).unwrap();
+        assert_eq!(app.editor.gap_buffer.get_text_as_string(), "ab");
+        app.execute(vec![Command::EditorRedo]).unwrap();
+        assert_eq!(app.editor.gap_buffer.get_text_as_string(), "abone\ntwo\n");
+        app.execute(vec![Command::EditorUndo, Command::EditorUndo])
+            .unwrap();
+        assert_eq!(app.editor.gap_buffer.get_text_as_string(), "");
+        app.execute(vec![Command::EditorUndo]).unwrap();
+        assert_eq!(
+            app.message,
+            Some(StatusMessage::Error("Already at oldest change".into()))
+        );
+    }
+}
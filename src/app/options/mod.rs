@@ -0,0 +1,577 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use lazy_static::lazy_static;
+
+/// Where the value of an option lives.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptionScope {
+    /// One value for the whole editor
+    Global,
+    /// Each buffer may have its own value
+    Buffer,
+    /// Each window may have its own value
+    Window,
+}
+
+/// The type of an option along with what values it accepts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptionKind {
+    Bool,
+    Int { min: i64, max: i64 },
+    String,
+    Enum(&'static [&'static str]),
+    List,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionValue {
+    Bool(bool),
+    Int(i64),
+    String(String),
+    Enum(String),
+    List(Vec<String>),
+}
+
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionValue::Bool(value) => write!(f, "{}", value),
+            OptionValue::Int(value) => write!(f, "{}", value),
+            OptionValue::String(value) | OptionValue::Enum(value) => write!(f, "{}", value),
+            OptionValue::List(values) => write!(f, "{}", values.join(",")),
+        }
+    }
+}
+
+/// Everything known about an option.
+#[derive(Debug)]
+pub struct OptionDef {
+    pub name: &'static str,
+    /// Abbreviation accepted by `:set`, may be empty
+    pub short: &'static str,
+    pub scope: OptionScope,
+    pub kind: OptionKind,
+    pub default: OptionValue,
+    pub description: &'static str,
+}
+
+impl OptionKind {
+    /// Parses a value as typed after `:set name=`.
+    pub fn parse(&self, value: &str) -> Result<OptionValue, String> {
+        let parsed = match self {
+            OptionKind::Bool => match value {
+                "true" | "on" | "1" => OptionValue::Bool(true),
+                "false" | "off" | "0" => OptionValue::Bool(false),
+                _ => return Err(format!("Boolean required: {}", value)),
+            },
+            OptionKind::Int { .. } => OptionValue::Int(
+                value
+                    .parse()
+                    .map_err(|_| format!("Number required: {}", value))?,
+            ),
+            OptionKind::String => OptionValue::String(value.into()),
+            OptionKind::Enum(_) => OptionValue::Enum(value.into()),
+            OptionKind::List => OptionValue::List(
+                value
+                    .split(',')
+                    .filter(|item| !item.is_empty())
+                    .map(String::from)
+                    .collect(),
+            ),
+        };
+        self.validate(parsed)
+    }
+
+    /// Checks a value has the right type and is within range.
+    pub fn validate(&self, value: OptionValue) -> Result<OptionValue, String> {
+        match (self, value) {
+            (OptionKind::Bool, value @ OptionValue::Bool(_)) => Ok(value),
+            (OptionKind::Int { min, max }, OptionValue::Int(value)) => {
+                if value < *min || value > *max {
+                    Err(format!("Value must be between {} and {}", min, max))
+                } else {
+                    Ok(OptionValue::Int(value))
+                }
+            }
+            (OptionKind::String, value @ OptionValue::String(_)) => Ok(value),
+            (OptionKind::Enum(choices), OptionValue::Enum(value) | OptionValue::String(value)) => {
+                if choices.contains(&value.as_str()) {
+                    Ok(OptionValue::Enum(value))
+                } else {
+                    Err(format!("Expected one of {}", choices.join(", ")))
+                }
+            }
+            (OptionKind::List, value @ OptionValue::List(_)) => Ok(value),
+            (kind, value) => Err(format!("Expected {}, got {}", kind.type_name(), value)),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            OptionKind::Bool => "a boolean",
+            OptionKind::Int { .. } => "a number",
+            OptionKind::String => "a string",
+            OptionKind::Enum(_) => "a string",
+            OptionKind::List => "a list",
+        }
+    }
+}
+
+lazy_static! {
+    /// Every option miv knows about.
+    pub static ref OPTION_DEFS: Vec<OptionDef> = vec![
+        OptionDef {
+            name: "tabstop",
+            short: "ts",
+            scope: OptionScope::Buffer,
+            kind: OptionKind::Int { min: 1, max: 64 },
+            default: OptionValue::Int(4),
+            description: "Number of columns a tab is displayed as",
+        },
+        OptionDef {
+            name: "scrolloff",
+            short: "so",
+            scope: OptionScope::Window,
+            kind: OptionKind::Int { min: 0, max: 999 },
+            default: OptionValue::Int(0),
+            description: "Minimum number of lines kept above and below the cursor",
+        },
+        OptionDef {
+            name: "number",
+            short: "nu",
+            scope: OptionScope::Window,
+            kind: OptionKind::Bool,
+            default: OptionValue::Bool(true),
+            description: "Show line numbers in the gutter",
+        },
+        OptionDef {
+            name: "numberwidth",
+            short: "nuw",
+            scope: OptionScope::Window,
+            kind: OptionKind::Int { min: 1, max: 20 },
+            default: OptionValue::Int(1),
+            description: "Minimum number of digits the line numbers take up",
+        },
+        OptionDef {
+            name: "gutterpadding",
+            short: "",
+            scope: OptionScope::Window,
+            kind: OptionKind::Int { min: 0, max: 20 },
+            default: OptionValue::Int(3),
+            description: "Spaces between the line numbers and the text",
+        },
+        OptionDef {
+            name: "wrap",
+            short: "",
+            scope: OptionScope::Window,
+            kind: OptionKind::Bool,
+            default: OptionValue::Bool(false),
+            description: "Wrap lines longer than the window",
+        },
+        OptionDef {
+            name: "cursorline",
+            short: "cul",
+            scope: OptionScope::Window,
+            kind: OptionKind::Bool,
+            default: OptionValue::Bool(false),
+            description: "Highlight the line the cursor is on",
+        },
+        OptionDef {
+            name: "termcolors",
+            short: "",
+            scope: OptionScope::Global,
+            kind: OptionKind::Enum(&["auto", "truecolor", "256", "16", "none"]),
+            default: OptionValue::Enum("auto".into()),
+            description: "Colours the terminal can show, `auto` works them out",
+        },
+        OptionDef {
+            name: "mapleader",
+            short: "",
+            scope: OptionScope::Global,
+            kind: OptionKind::String,
+            default: OptionValue::String("\\".into()),
+            description: "Keys `<leader>` stands for in mappings",
+        },
+        OptionDef {
+            name: "timeout",
+            short: "to",
+            scope: OptionScope::Global,
+            kind: OptionKind::Bool,
+            default: OptionValue::Bool(true),
+            description: "Give up on unfinished key sequences after `timeoutlen`",
+        },
+        OptionDef {
+            name: "timeoutlen",
+            short: "tm",
+            scope: OptionScope::Global,
+            kind: OptionKind::Int { min: 0, max: 60_000 },
+            default: OptionValue::Int(1000),
+            description: "Milliseconds to wait for the next key of a sequence",
+        },
+        OptionDef {
+            name: "whichkeydelay",
+            short: "",
+            scope: OptionScope::Global,
+            kind: OptionKind::Int { min: 0, max: 60_000 },
+            default: OptionValue::Int(400),
+            description: "Milliseconds before listing the keys that may follow",
+        },
+        OptionDef {
+            name: "tickrate",
+            short: "",
+            scope: OptionScope::Global,
+            kind: OptionKind::Int { min: 10, max: 10_000 },
+            default: OptionValue::Int(250),
+            description: "Milliseconds between ticks of the event loop",
+        },
+        OptionDef {
+            name: "mouse",
+            short: "",
+            scope: OptionScope::Global,
+            kind: OptionKind::Bool,
+            default: OptionValue::Bool(true),
+            description: "Use the mouse to place the cursor, select and scroll",
+        },
+        OptionDef {
+            name: "gapgrowth",
+            short: "",
+            scope: OptionScope::Global,
+            kind: OptionKind::Int { min: 1, max: 1 << 20 },
+            default: OptionValue::Int(1024),
+            description: "Extra characters allocated when the gap buffer grows",
+        },
+        OptionDef {
+            name: "backup",
+            short: "bk",
+            scope: OptionScope::Global,
+            kind: OptionKind::Enum(&["off", "adjacent", "directory"]),
+            default: OptionValue::Enum("off".into()),
+            description: "Keep a copy of the previous contents when writing",
+        },
+        OptionDef {
+            name: "formatprg",
+            short: "fp",
+            scope: OptionScope::Buffer,
+            kind: OptionKind::String,
+            default: OptionValue::String("".into()),
+            description: "Command `:Format` runs, empty for the filetype's formatter",
+        },
+        OptionDef {
+            name: "formatonsave",
+            short: "fos",
+            scope: OptionScope::Buffer,
+            kind: OptionKind::Bool,
+            default: OptionValue::Bool(false),
+            description: "Run the formatter before writing the buffer",
+        },
+        OptionDef {
+            name: "compiler",
+            short: "",
+            scope: OptionScope::Buffer,
+            kind: OptionKind::String,
+            default: OptionValue::String("cargo".into()),
+            description: "Compiler `:make` runs and reads the messages of",
+        },
+        OptionDef {
+            name: "makeprg",
+            short: "mp",
+            scope: OptionScope::Buffer,
+            kind: OptionKind::String,
+            default: OptionValue::String("".into()),
+            description: "Command `:make` runs, empty for the compiler's own",
+        },
+        OptionDef {
+            name: "expandtab",
+            short: "et",
+            scope: OptionScope::Buffer,
+            kind: OptionKind::Bool,
+            default: OptionValue::Bool(false),
+            description: "Indent with spaces rather than tabs when retabbing",
+        },
+        OptionDef {
+            name: "retabonsave",
+            short: "",
+            scope: OptionScope::Buffer,
+            kind: OptionKind::Bool,
+            default: OptionValue::Bool(false),
+            description: "Convert indentation to match `expandtab` before writing",
+        },
+        OptionDef {
+            name: "trimwhitespace",
+            short: "",
+            scope: OptionScope::Buffer,
+            kind: OptionKind::Enum(&["off", "all", "modified"]),
+            default: OptionValue::Enum("off".into()),
+            description: "Strip trailing whitespace before writing, from every line or changed ones",
+        },
+        OptionDef {
+            name: "fixendofline",
+            short: "fixeol",
+            scope: OptionScope::Buffer,
+            kind: OptionKind::Bool,
+            default: OptionValue::Bool(false),
+            description: "End the file with exactly one newline when writing",
+        },
+    ];
+}
+
+/// Looks up an option by its name or abbreviation.
+pub fn find_option(name: &str) -> Option<&'static OptionDef> {
+    OPTION_DEFS
+        .iter()
+        .find(|def| def.name == name || (!def.short.is_empty() && def.short == name))
+}
+
+/// Current values of all options.
+///
+/// Values that were never set fall back to the default of the option, local
+/// values of buffer and window options win over the global ones.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    global: HashMap<&'static str, OptionValue>,
+    buffer: HashMap<&'static str, OptionValue>,
+    window: HashMap<&'static str, OptionValue>,
+    /// Values for the buffer's filetype, overridden by local values but
+    /// overriding global ones
+    filetype: HashMap<&'static str, OptionValue>,
+}
+
+impl Options {
+    pub fn get(&self, name: &str) -> &OptionValue {
+        let def = find_option(name).unwrap_or_else(|| panic!("unknown option {}", name));
+        let local = match def.scope {
+            OptionScope::Global => None,
+            OptionScope::Buffer => self.buffer.get(def.name),
+            OptionScope::Window => self.window.get(def.name),
+        };
+        local
+            .or_else(|| self.filetype.get(def.name))
+            .or_else(|| self.global.get(def.name))
+            .unwrap_or(&def.default)
+    }
+
+    pub fn get_bool(&self, name: &str) -> bool {
+        matches!(self.get(name), OptionValue::Bool(true))
+    }
+
+    pub fn get_int(&self, name: &str) -> i64 {
+        match self.get(name) {
+            OptionValue::Int(value) => *value,
+            _ => 0,
+        }
+    }
+
+    pub fn get_usize(&self, name: &str) -> usize {
+        usize::try_from(self.get_int(name)).unwrap_or_default()
+    }
+
+    pub fn get_str(&self, name: &str) -> &str {
+        match self.get(name) {
+            OptionValue::String(value) | OptionValue::Enum(value) => value,
+            _ => "",
+        }
+    }
+
+    pub fn get_list(&self, name: &str) -> &[String] {
+        match self.get(name) {
+            OptionValue::List(values) => values,
+            _ => &[],
+        }
+    }
+
+    /// Replaces the values set for the buffer's filetype.
+    pub fn set_filetype_values(&mut self, values: &[(String, OptionValue)]) {
+        self.filetype = values
+            .iter()
+            .filter_map(|(name, value)| Some((find_option(name)?.name, value.clone())))
+            .collect();
+    }
+
+    /// Sets an option, `local` only changes the value for the current buffer
+    /// or window like `:setlocal` while otherwise both are changed like `:set`.
+    pub fn set(&mut self, name: &str, value: OptionValue, local: bool) -> Result<(), String> {
+        let def = find_option(name).ok_or_else(|| format!("Unknown option: {}", name))?;
+        let value = def.kind.validate(value)?;
+        let local_values = match def.scope {
+            OptionScope::Global => None,
+            OptionScope::Buffer => Some(&mut self.buffer),
+            OptionScope::Window => Some(&mut self.window),
+        };
+        match local_values {
+            Some(local_values) => {
+                local_values.insert(def.name, value.clone());
+                if !local {
+                    self.global.insert(def.name, value);
+                }
+            }
+            None => {
+                self.global.insert(def.name, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a single `:set` argument.
+    ///
+    /// Understands `name`, `noname`, `invname`, `name!`, `name?`, `name&`,
+    /// `name=value`, `name+=value` and `name-=value`. Queries give back the
+    /// text to show the user.
+    pub fn apply(&mut self, arg: &str, local: bool) -> Result<Option<String>, String> {
+        if let Some(name) = arg.strip_suffix('?') {
+            return self.query(name).map(Some);
+        }
+        if let Some(name) = arg.strip_suffix('&') {
+            let def = find_option(name).ok_or_else(|| format!("Unknown option: {}", name))?;
+            self.set(def.name, def.default.clone(), local)?;
+            return Ok(None);
+        }
+        if let Some(name) = arg.strip_suffix('!').or_else(|| arg.strip_prefix("inv")) {
+            if let Some(def) = find_option(name).filter(|def| def.kind == OptionKind::Bool) {
+                let toggled = !self.get_bool(def.name);
+                self.set(def.name, OptionValue::Bool(toggled), local)?;
+                return Ok(None);
+            }
+        }
+
+        for operator in ["+=", "-=", "="] {
+            if let Some((name, value)) = arg.split_once(operator) {
+                let def = find_option(name).ok_or_else(|| format!("Unknown option: {}", name))?;
+                let value = def.kind.parse(value)?;
+                let value = match (operator, self.get(def.name), value) {
+                    ("=", _, value) => value,
+                    ("+=", OptionValue::Int(current), OptionValue::Int(value)) => {
+                        OptionValue::Int(current + value)
+                    }
+                    ("-=", OptionValue::Int(current), OptionValue::Int(value)) => {
+                        OptionValue::Int(current - value)
+                    }
+                    ("+=", OptionValue::List(current), OptionValue::List(value)) => {
+                        OptionValue::List([current.clone(), value].concat())
+                    }
+                    ("-=", OptionValue::List(current), OptionValue::List(value)) => {
+                        OptionValue::List(
+                            current
+                                .iter()
+                                .filter(|item| !value.contains(item))
+                                .cloned()
+                                .collect(),
+                        )
+                    }
+                    _ => return Err(format!("Can't use {} with {}", operator, def.name)),
+                };
+                self.set(def.name, value, local)?;
+                return Ok(None);
+            }
+        }
+
+        if let Some(def) = find_option(arg) {
+            if def.kind == OptionKind::Bool {
+                self.set(def.name, OptionValue::Bool(true), local)?;
+                return Ok(None);
+            }
+            // Like vim, naming a non boolean option shows its value
+            return self.query(arg).map(Some);
+        }
+        if let Some(def) = arg
+            .strip_prefix("no")
+            .and_then(find_option)
+            .filter(|def| def.kind == OptionKind::Bool)
+        {
+            self.set(def.name, OptionValue::Bool(false), local)?;
+            return Ok(None);
+        }
+        Err(format!("Unknown option: {}", arg))
+    }
+
+    fn query(&self, name: &str) -> Result<String, String> {
+        let def = find_option(name).ok_or_else(|| format!("Unknown option: {}", name))?;
+        Ok(match self.get(def.name) {
+            OptionValue::Bool(true) => def.name.to_string(),
+            OptionValue::Bool(false) => format!("no{}", def.name),
+            value => format!("{}={}", def.name, value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod miv_options_tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_used_until_set() {
+        let options = Options::default();
+        assert_eq!(options.get_usize("tabstop"), 4);
+        assert!(options.get_bool("number"));
+        assert_eq!(options.get_str("backup"), "off");
+    }
+
+    #[test]
+    fn set_forms() {
+        let mut options = Options::default();
+        options.apply("nonu", false).unwrap();
+        assert!(!options.get_bool("number"));
+        options.apply("number!", false).unwrap();
+        assert!(options.get_bool("number"));
+        options.apply("ts=8", false).unwrap();
+        options.apply("ts+=2", false).unwrap();
+        assert_eq!(options.get_int("tabstop"), 10);
+        options.apply("ts&", false).unwrap();
+        assert_eq!(options.get_int("tabstop"), 4);
+    }
+
+    #[test]
+    fn queries_show_the_value() {
+        let mut options = Options::default();
+        assert_eq!(options.apply("ts?", false).unwrap().unwrap(), "tabstop=4");
+        assert_eq!(options.apply("wrap?", false).unwrap().unwrap(), "nowrap");
+        assert_eq!(options.apply("so", false).unwrap().unwrap(), "scrolloff=0");
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let mut options = Options::default();
+        assert!(options.apply("ts=0", false).is_err());
+        assert!(options.apply("ts=abc", false).is_err());
+        assert!(options.apply("backup=sometimes", false).is_err());
+        assert!(options.apply("bogus", false).is_err());
+        assert_eq!(options.get_int("tabstop"), 4);
+    }
+
+    #[test]
+    fn setlocal_leaves_the_global_value_alone() {
+        let mut options = Options::default();
+        options.apply("ts=2", true).unwrap();
+        assert_eq!(options.get_int("tabstop"), 2);
+        assert_eq!(options.global.get("tabstop"), None);
+
+        // Global options have no local value to set
+        options.apply("tickrate=100", true).unwrap();
+        assert_eq!(options.global["tickrate"], OptionValue::Int(100));
+    }
+
+    #[test]
+    fn filetype_values_sit_between_local_and_global() {
+        let mut options = Options::default();
+        options.apply("ts=6", true).unwrap();
+        options.set_filetype_values(&[
+            ("tabstop".into(), OptionValue::Int(2)),
+            ("expandtab".into(), OptionValue::Bool(true)),
+        ]);
+        assert_eq!(options.get_int("tabstop"), 6);
+        assert!(options.get_bool("expandtab"));
+        options.apply("noet", false).unwrap();
+        assert!(!options.get_bool("expandtab"));
+    }
+
+    #[test]
+    fn lists_can_be_extended_and_shrunk() {
+        let kind = OptionKind::List;
+        let value = kind.parse("a,b,c").unwrap();
+        assert_eq!(
+            value,
+            OptionValue::List(vec!["a".into(), "b".into(), "c".into()])
+        );
+        assert!(kind.validate(OptionValue::Int(1)).is_err());
+    }
+}
@@ -0,0 +1,15 @@
+/// A floating window drawn above the editor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Popup {
+    pub title: String,
+    pub lines: Vec<String>,
+}
+
+impl Popup {
+    pub fn new(title: impl Into<String>, lines: Vec<String>) -> Self {
+        Self {
+            title: title.into(),
+            lines,
+        }
+    }
+}
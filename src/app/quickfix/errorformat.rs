@@ -0,0 +1,240 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer};
+
+use super::{QuickfixEntry, Severity};
+
+/// Compilers known without any config, as name, command and the patterns
+/// their messages are read with.
+pub const COMPILERS: &[(&str, &str, &[&str])] = &[
+    (
+        "cargo",
+        "cargo build --message-format=short",
+        &["%f:%l:%c: %t%*: %m"],
+    ),
+    ("gcc", "make", &["%f:%l:%c: %t: %m", "%f:%l: %t: %m"]),
+    ("grep", "grep -rn", &["%f:%l:%c:%m", "%f:%l:%m"]),
+];
+
+/// A pattern for reading compiler messages, like vim's `errorformat`.
+///
+/// `%f` is the file, `%l` the line, `%c` the column, `%t` a word such as
+/// `error` or `warning` giving the severity and `%m` the message. `%*`
+/// skips any text and `%%` is a percent sign, everything else has to match
+/// as it is. Patterns match whole lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorFormat {
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    File,
+    Line,
+    Column,
+    Kind,
+    Message,
+    Any,
+}
+
+/// What a pattern picked out of a line
+#[derive(Debug, Default)]
+struct Captures<'a> {
+    file: Option<&'a str>,
+    line: Option<&'a str>,
+    column: Option<&'a str>,
+    kind: Option<&'a str>,
+    message: Option<&'a str>,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let mut tokens = vec![];
+        let mut literal = String::new();
+        let mut chars = pattern.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '%' {
+                literal.push(ch);
+                continue;
+            }
+            let token = match chars.next() {
+                Some('%') => {
+                    literal.push('%');
+                    continue;
+                }
+                Some('f') => Token::File,
+                Some('l') => Token::Line,
+                Some('c') => Token::Column,
+                Some('t') => Token::Kind,
+                Some('m') => Token::Message,
+                Some('*') => Token::Any,
+                Some(other) => return Err(format!("unknown directive `%{}`", other)),
+                None => return Err("pattern ends in `%`".into()),
+            };
+            if !literal.is_empty() {
+                tokens.push(Token::Literal(std::mem::take(&mut literal)));
+            }
+            tokens.push(token);
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+        if !tokens.contains(&Token::Message) {
+            return Err(format!("`{}` has no `%m`", pattern));
+        }
+        Ok(Self {
+            pattern: pattern.into(),
+            tokens,
+        })
+    }
+}
+
+impl fmt::Display for ErrorFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+impl<'de> Deserialize<'de> for ErrorFormat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl ErrorFormat {
+    /// The entry a line describes, if it matches.
+    pub fn parse_line(&self, line: &str) -> Option<QuickfixEntry> {
+        let mut captures = Captures::default();
+        if !match_tokens(&self.tokens, line, &mut captures) {
+            return None;
+        }
+        let number = |value: Option<&str>| value.and_then(|value| value.parse().ok());
+        Some(QuickfixEntry {
+            path: captures.file.map(|file| PathBuf::from(file.trim())),
+            line: number(captures.line),
+            column: number(captures.column),
+            message: captures.message.unwrap_or_default().trim().to_string(),
+            severity: captures
+                .kind
+                .map(Severity::from_word)
+                .unwrap_or(Severity::Error),
+        })
+    }
+}
+
+/// Reads every line of `output` that one of `formats` matches, trying them
+/// in order.
+pub fn parse_output(output: &str, formats: &[ErrorFormat]) -> Vec<QuickfixEntry> {
+    output
+        .lines()
+        .filter_map(|line| formats.iter().find_map(|format| format.parse_line(line)))
+        .collect()
+}
+
+/// Matches the tokens against all of `input`, trying the shortest text for
+/// `%f`, `%m` and `%*` first.
+fn match_tokens<'a>(tokens: &[Token], input: &'a str, captures: &mut Captures<'a>) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return input.is_empty();
+    };
+    match token {
+        Token::Literal(literal) => match input.strip_prefix(literal.as_str()) {
+            Some(input) => match_tokens(rest, input, captures),
+            None => false,
+        },
+        Token::Line | Token::Column | Token::Kind => {
+            let length = input.len()
+                - match token {
+                    Token::Kind => input.trim_start_matches(char::is_alphabetic),
+                    _ => input.trim_start_matches(|ch: char| ch.is_ascii_digit()),
+                }
+                .len();
+            if length == 0 {
+                return false;
+            }
+            let value = Some(&input[..length]);
+            match token {
+                Token::Line => captures.line = value,
+                Token::Column => captures.column = value,
+                _ => captures.kind = value,
+            }
+            match_tokens(rest, &input[length..], captures)
+        }
+        Token::File | Token::Message | Token::Any => {
+            // A file name is never empty
+            let shortest = usize::from(*token == Token::File);
+            let ends = input
+                .char_indices()
+                .map(|(index, _)| index)
+                .chain(std::iter::once(input.len()))
+                .filter(|end| *end >= shortest);
+            for end in ends {
+                if match_tokens(rest, &input[end..], captures) {
+                    let value = Some(&input[..end]);
+                    match token {
+                        Token::File => captures.file = value,
+                        Token::Message => captures.message = value,
+                        _ => {}
+                    }
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod miv_errorformat_tests {
+    use super::*;
+
+    fn formats(compiler: &str) -> Vec<ErrorFormat> {
+        let (_, _, patterns) = COMPILERS
+            .iter()
+            .find(|(name, _, _)| *name == compiler)
+            .unwrap();
+        patterns
+            .iter()
+            .map(|pattern| pattern.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn cargo_messages_are_read() {
+        let output = "\
+   Compiling miv v0.1.0
+src/main.rs:10:5: error[E0425]: cannot find value `x` in this scope
+src/app/mod.rs:3:9: warning: unused import: `std::fs`
+warning: `miv` (bin \"miv\") generated 1 warning
+error: could not compile `miv` (bin \"miv\") due to 1 previous error
+
+Caused by: process didn't exit successfully
+";
+        let entries = parse_output(output, &formats("cargo"));
+        // The summaries at the end aren't diagnostics of their own
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, Some(PathBuf::from("src/main.rs")));
+        assert_eq!((entries[0].line, entries[0].column), (Some(10), Some(5)));
+        assert_eq!(entries[0].message, "cannot find value `x` in this scope");
+        assert_eq!(entries[0].severity, Severity::Error);
+        assert_eq!(entries[1].severity, Severity::Warning);
+        assert_eq!(entries[1].message, "unused import: `std::fs`");
+    }
+
+    #[test]
+    fn patterns_are_checked() {
+        assert!("%f:%l: %m".parse::<ErrorFormat>().is_ok());
+        assert!("%f:%l".parse::<ErrorFormat>().is_err());
+        assert!("%f:%x: %m".parse::<ErrorFormat>().is_err());
+        let format: ErrorFormat = "100%%: %m".parse().unwrap();
+        assert!(format.parse_line("100%: done").is_some());
+    }
+}
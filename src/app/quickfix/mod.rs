@@ -0,0 +1,384 @@
+pub mod errorformat;
+
+use std::fmt;
+use std::path::PathBuf;
+
+use strum_macros::Display;
+
+use self::errorformat::{parse_output, ErrorFormat, COMPILERS};
+use super::shell::shell_command;
+use super::{App, AppResult, InputMode, StatusMessage};
+
+/// How serious a quickfix entry is
+#[derive(Display, Debug, PartialEq, Eq, Clone, Copy)]
+#[strum(serialize_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+    Note,
+}
+
+impl Severity {
+    /// The severity a compiler means by a word such as `warning` or `W`
+    pub fn from_word(word: &str) -> Self {
+        match word.chars().next().map(|ch| ch.to_ascii_lowercase()) {
+            Some('w') => Severity::Warning,
+            Some('i') => Severity::Info,
+            Some('n' | 'h') => Severity::Note,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// A location a compiler had something to say about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuickfixEntry {
+    /// Relative to the working directory unless absolute
+    pub path: Option<PathBuf>,
+    /// Counting from 1
+    pub line: Option<usize>,
+    /// Counting from 1
+    pub column: Option<usize>,
+    pub message: String,
+    pub severity: Severity,
+}
+
+impl fmt::Display for QuickfixEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}", path.display())?;
+        }
+        write!(f, "|")?;
+        if let Some(line) = self.line {
+            write!(f, "{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, " col {}", column)?;
+            }
+            write!(f, " ")?;
+        }
+        write!(f, "{}| {}", self.severity, self.message)
+    }
+}
+
+/// Entries from the last `:make`, shown in a panel below the editor.
+#[derive(Debug, Default)]
+pub struct Quickfix {
+    pub entries: Vec<QuickfixEntry>,
+    /// Index of the entry last jumped to, none until the first jump
+    pub current: Option<usize>,
+    /// Index of the entry picked in the panel
+    pub selected: usize,
+    /// Whether the panel is shown
+    pub open: bool,
+    /// The command the entries came from
+    pub title: String,
+}
+
+impl Quickfix {
+    pub fn set(&mut self, title: String, entries: Vec<QuickfixEntry>) {
+        self.title = title;
+        self.entries = entries;
+        self.current = None;
+        self.selected = 0;
+    }
+
+    /// Moves the panel's selection `step` entries on, stopping at either end.
+    pub fn move_selection(&mut self, step: isize) {
+        let last = self.entries.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(step).min(last);
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.severity == severity)
+            .count()
+    }
+}
+
+impl App {
+    /// `:make`, running the compiler in the background and filling the
+    /// quickfix list from what it prints.
+    pub(super) fn make(&mut self, args: Option<String>) -> AppResult<()> {
+        let (command, formats) = self.compiler()?;
+        let command = match args {
+            Some(args) => format!("{} {}", command, args),
+            None => command,
+        };
+        let title = command.clone();
+        self.jobs
+            .spawn_process("make", shell_command(&command), None, move |app, output| {
+                // Compilers split their messages between stdout and stderr
+                let text = format!("{}{}", output.stdout, output.stderr);
+                let entries = parse_output(&text, &formats);
+                app.quickfix.set(title, entries);
+                app.quickfix.open = !app.quickfix.entries.is_empty();
+                let summary = format!(
+                    "{}, {}",
+                    plural(app.quickfix.count(Severity::Error), "error"),
+                    plural(app.quickfix.count(Severity::Warning), "warning")
+                );
+                app.message = Some(if output.success() {
+                    StatusMessage::Info(format!("Build finished: {}", summary))
+                } else {
+                    StatusMessage::Error(format!("Build failed: {}", summary))
+                });
+                Ok(())
+            })?;
+        Ok(())
+    }
+
+    /// The command and patterns of the `compiler` option's compiler, the
+    /// config taking precedence over the built in ones and `makeprg` over
+    /// both for the command.
+    fn compiler(&self) -> AppResult<(String, Vec<ErrorFormat>)> {
+        let name = self.options.get_str("compiler");
+        let builtin = COMPILERS.iter().find(|(builtin, _, _)| *builtin == name);
+        let config = self.config.compiler.get(name);
+        if builtin.is_none() && config.is_none() {
+            return Err(format!("Unknown compiler: {}", name).into());
+        }
+
+        let command = match self.options.get_str("makeprg") {
+            "" => config
+                .and_then(|config| config.command.clone())
+                .or_else(|| builtin.map(|(_, command, _)| command.to_string())),
+            makeprg => Some(makeprg.to_string()),
+        }
+        .ok_or_else(|| format!("No command for compiler {}, set makeprg", name))?;
+
+        let formats = match config {
+            Some(config) if !config.errorformat.is_empty() => config.errorformat.clone(),
+            _ => builtin
+                .map(|(_, _, patterns)| {
+                    patterns
+                        .iter()
+                        .map(|pattern| pattern.parse())
+                        .collect::<Result<_, _>>()
+                })
+                .transpose()?
+                .unwrap_or_default(),
+        };
+        if formats.is_empty() {
+            return Err(format!("No errorformat for compiler {}", name).into());
+        }
+        Ok((command, formats))
+    }
+
+    /// Opens the file of quickfix entry `index`, counting from 0, and puts
+    /// the cursor on its location.
+    pub(super) fn quickfix_jump(&mut self, index: usize) -> AppResult<()> {
+        let total = self.quickfix.entries.len();
+        if total == 0 {
+            return Err("No errors".into());
+        }
+        let Some(entry) = self.quickfix.entries.get(index).cloned() else {
+            return Err(format!("No entry {}, there are {}", index + 1, total).into());
+        };
+        if let Some(path) = &entry.path {
+            self.open_file(path)?;
+        }
+        self.quickfix.current = Some(index);
+        self.quickfix.selected = index;
+
+        if let Some(line) = entry.line {
+            let line = line.clamp(1, self.editor.line_count()) - 1;
+            let span = self.editor.line_span(line, line);
+            let text = self.editor.gap_buffer.get_text_as_chars();
+            let length = text[span.clone()]
+                .iter()
+                .take_while(|ch| **ch != '\n')
+                .count();
+            let column = entry.column.unwrap_or(1).saturating_sub(1).min(length);
+            self.editor
+                .set_cursor(span.start + column, InputMode::Normal);
+        }
+        self.message = Some(StatusMessage::Info(format!(
+            "({} of {}) {}: {}",
+            index + 1,
+            total,
+            entry.severity,
+            entry.message
+        )));
+        Ok(())
+    }
+
+    /// Jumps `step` entries on from the current one, stopping at either end,
+    /// or to the first entry when there's no current one yet.
+    pub(super) fn quickfix_step(&mut self, step: isize) -> AppResult<()> {
+        let last = self.quickfix.entries.len().saturating_sub(1);
+        let index = match self.quickfix.current {
+            Some(current) => {
+                let index = current.saturating_add_signed(step).min(last);
+                if index == current {
+                    return Err("No more items".into());
+                }
+                index
+            }
+            None => 0,
+        };
+        self.quickfix_jump(index)
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        count => format!("{} {}s", count, noun),
+    }
+}
+
+#[cfg(test)]
+mod miv_quickfix_tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+    use crate::app::command_line::parse_command;
+    use crate::event::Event;
+    use crate::input_handling::InputStack;
+
+    fn entry(line: usize, column: usize, severity: Severity) -> QuickfixEntry {
+        QuickfixEntry {
+            path: None,
+            line: Some(line),
+            column: Some(column),
+            message: "oops".into(),
+            severity,
+        }
+    }
+
+    #[test]
+    fn entries_are_jumped_between() {
+        let mut app = App::default();
+        app.editor
+            .insert("one\ntwo\nthree\n".into(), InputMode::Insert);
+        app.quickfix.set(
+            "test".into(),
+            vec![entry(2, 2, Severity::Error), entry(3, 9, Severity::Warning)],
+        );
+
+        app.execute(parse_command("cc")).unwrap();
+        assert_eq!(app.editor.cursor_index, 5);
+        app.execute(parse_command("cnext")).unwrap();
+        // Past the end of the line, so on its last character
+        assert_eq!(app.editor.cursor_index, 12);
+        assert_eq!(
+            app.message,
+            Some(StatusMessage::Info("(2 of 2) warning: oops".into()))
+        );
+        app.execute(parse_command("cn")).unwrap();
+        assert_eq!(
+            app.message,
+            Some(StatusMessage::Error("No more items".into()))
+        );
+        app.execute(parse_command("cc 1")).unwrap();
+        assert_eq!(app.quickfix.current, Some(0));
+        app.execute(parse_command("cc 3")).unwrap();
+        assert!(matches!(app.message, Some(StatusMessage::Error(_))));
+    }
+
+    /// Runs `:make` with `makeprg` as the command, waiting for it to finish
+    fn make(app: &mut App, makeprg: &str) {
+        let (sender, receiver) = mpsc::channel();
+        app.jobs.connect(sender);
+        app.options
+            .set(
+                "makeprg",
+                crate::app::options::OptionValue::String(makeprg.into()),
+                false,
+            )
+            .unwrap();
+        app.execute(parse_command("make")).unwrap();
+        while !app.jobs.is_empty() {
+            if let Event::JobDone(done) = receiver.recv_timeout(Duration::from_secs(10)).unwrap() {
+                app.job_done(done).unwrap();
+            }
+        }
+    }
+
+    /// An app after a `:make` finding two problems in a file of three lines
+    fn app_with_two_entries(dir: &TempDir) -> App {
+        let path = dir.path().join("lib.rs");
+        std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let mut app = App::default();
+        make(
+            &mut app,
+            &format!(
+                "printf '{0}:2:1: error: first\\n{0}:3:2: warning: second\\n'",
+                path.display()
+            ),
+        );
+        app
+    }
+
+    fn press(app: &mut App, input: &mut InputStack, keys: &str) {
+        for ch in keys.chars() {
+            let code = match ch {
+                '\n' => KeyCode::Enter,
+                ch => KeyCode::Char(ch),
+            };
+            let event = KeyEvent::new(code, KeyModifiers::NONE);
+            if let Some(commands) = input.handle_key_event(event, &app.mode, &app.keymaps) {
+                app.execute(commands).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn make_fills_the_list() {
+        let mut app = App::default();
+        make(
+            &mut app,
+            "printf 'src/lib.rs:4:1: error[E1]: bad\\n'; exit 101",
+        );
+        assert!(app.quickfix.open);
+        assert_eq!(
+            app.quickfix.entries,
+            vec![QuickfixEntry {
+                path: Some("src/lib.rs".into()),
+                message: "bad".into(),
+                ..entry(4, 1, Severity::Error)
+            }]
+        );
+        assert_eq!(
+            app.message,
+            Some(StatusMessage::Error(
+                "Build failed: 1 error, 0 warnings".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn the_first_cnext_after_make_goes_to_the_first_entry() {
+        let dir = tempdir().unwrap();
+        let mut app = app_with_two_entries(&dir);
+        assert_eq!(app.quickfix.current, None);
+        app.execute(parse_command("cnext")).unwrap();
+        assert_eq!(app.quickfix.current, Some(0));
+        assert_eq!(app.editor.cursor_index, 4);
+        assert_eq!(
+            app.message,
+            Some(StatusMessage::Info("(1 of 2) error: first".into()))
+        );
+    }
+
+    #[test]
+    fn enter_in_the_panel_jumps_to_the_selected_entry() {
+        let dir = tempdir().unwrap();
+        let mut app = app_with_two_entries(&dir);
+        let mut input = InputStack::new();
+        press(&mut app, &mut input, ":copen\n");
+        assert_eq!(app.mode, InputMode::Quickfix);
+        press(&mut app, &mut input, "jj");
+        assert_eq!(app.quickfix.selected, 1);
+        press(&mut app, &mut input, "\n");
+        assert_eq!(app.mode, InputMode::Normal);
+        assert_eq!(app.quickfix.current, Some(1));
+        assert_eq!(app.editor.cursor_index, 9);
+    }
+}
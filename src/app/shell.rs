@@ -0,0 +1,469 @@
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process::{Command, ExitStatus};
+
+use super::command_line::range::LineRange;
+use super::editor::motions::Motion;
+use super::jobs::ProcessOutput;
+use super::popup::Popup;
+use super::{App, AppResult, InputMode, StatusMessage};
+
+/// A shell command that ran but failed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellError {
+    /// What the status line shows
+    pub message: String,
+    /// Everything the command printed to stderr
+    pub stderr: String,
+}
+
+impl ShellError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            stderr: String::new(),
+        }
+    }
+}
+
+/// Quotes `text` as a single shell word
+pub fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Runs `command` with the user's shell, or `sh` when `$SHELL` isn't set.
+pub fn shell_command(command: &str) -> Command {
+    let shell = env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| "sh".into());
+    let mut shell = Command::new(shell);
+    shell.arg("-c").arg(command);
+    shell
+}
+
+/// What a filter printed, or a command that exited with an error reported
+/// with its stderr.
+pub fn filter_output(command: &str, output: ProcessOutput) -> Result<String, ShellError> {
+    if !output.success() {
+        let reason = output
+            .stderr
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default();
+        let message = match reason {
+            "" => format!("{} ({})", command, exit_reason(output.status)),
+            reason => format!("{} ({}): {}", command, exit_reason(output.status), reason),
+        };
+        return Err(ShellError {
+            message,
+            stderr: output.stderr,
+        });
+    }
+    Ok(output.stdout)
+}
+
+/// Runs `command` on the terminal, which the caller must have handed over,
+/// waiting for a key once it's done so its output can be read.
+pub fn run_interactive(command: &str) -> AppResult<ExitStatus> {
+    let status = shell_command(command).status()?;
+    let mut stdout = io::stdout();
+    write!(stdout, "\r\nPress ENTER to continue")?;
+    stdout.flush()?;
+    io::stdin().lock().read_line(&mut String::new())?;
+    Ok(status)
+}
+
+fn exit_reason(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exit {}", code),
+        None => "killed".into(),
+    }
+}
+
+impl App {
+    /// `:{range}!cmd`, replacing the lines with what `cmd` makes of them
+    /// once it's done in the background.
+    pub(super) fn filter_lines(&mut self, range: LineRange, command: &str) -> AppResult<()> {
+        let (first, last) = self.resolve_range(range)?;
+        if first == 0 {
+            return Err("Invalid range".into());
+        }
+        let span = self.editor.line_span(first - 1, last - 1);
+        let text: String = self.editor.gap_buffer.get_text_as_chars()[span.clone()]
+            .iter()
+            .collect();
+        let final_newline = text.ends_with('\n');
+        self.spawn_filter(command, Some(text), move |app, mut output| {
+            // The last line of a file without a final newline stays without one
+            if !final_newline && output.ends_with('\n') {
+                output.pop();
+            }
+            app.editor.undo.close_step();
+            app.editor.replace(span, &output);
+            app.editor.undo.close_step();
+            let lines = last - first + 1;
+            app.message = Some(StatusMessage::Info(format!(
+                "{} line{} filtered",
+                lines,
+                if lines == 1 { "" } else { "s" }
+            )));
+        })
+    }
+
+    /// `:{line}r !cmd`, putting what `cmd` prints below the line, or above
+    /// the first for line 0, once it's done in the background.
+    pub(super) fn read_shell(&mut self, range: Option<LineRange>, command: &str) -> AppResult<()> {
+        let line = match range {
+            Some(range) => self.resolve_range(range)?.1,
+            None => self.editor.line_of(self.editor.cursor_index) + 1,
+        };
+        let mut at = match line {
+            0 => 0,
+            line => self.editor.line_span(line - 1, line - 1).end,
+        };
+        let after_final_line = at > 0 && self.editor.gap_buffer.get_at(at - 1) != '\n';
+        self.spawn_filter(command, None, move |app, mut text| {
+            if text.is_empty() {
+                return;
+            }
+            if after_final_line {
+                // Reading below a last line without a final newline
+                text.insert(0, '\n');
+                if text.ends_with('\n') {
+                    text.pop();
+                }
+            } else if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            app.editor.undo.close_step();
+            app.editor.replace(at..at, &text);
+            app.editor.undo.close_step();
+            if text.starts_with('\n') {
+                at += 1;
+            }
+            app.editor.set_cursor(at, InputMode::Normal);
+        })
+    }
+
+    /// Runs `command` as a job with `input` on its stdin, handing what it
+    /// printed to `apply` unless it failed or the text changed meanwhile.
+    fn spawn_filter(
+        &mut self,
+        command: &str,
+        input: Option<String>,
+        apply: impl FnOnce(&mut App, String) + 'static,
+    ) -> AppResult<()> {
+        let tick = self.editor.change_tick();
+        let name = command.to_string();
+        self.jobs.spawn_process(
+            format!("!{}", command),
+            shell_command(command),
+            input,
+            move |app, output| {
+                let output = filter_output(&name, output).map_err(|err| app.shell_error(err))?;
+                if app.editor.change_tick() != tick {
+                    return Err(format!("Text changed while {} ran, output dropped", name).into());
+                }
+                apply(app, output);
+                Ok(())
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Starts `:.,.+N!` for the lines a `!` motion covers, waiting for the
+    /// filter to be typed.
+    pub(super) fn filter_prompt(&mut self, motion: Motion) {
+        let cursor = self.editor.cursor_index;
+        self.editor.move_cursor(&motion, self.mode);
+        let target = self.editor.line_of(self.editor.cursor_index);
+        self.editor.cursor_index = cursor;
+        let current = self.editor.line_of(cursor);
+
+        let range = match target.abs_diff(current) {
+            0 => ".".to_string(),
+            lines if target > current => format!(".,.+{}", lines),
+            lines => format!(".-{},.", lines),
+        };
+        self.change_input_mode(InputMode::Command);
+        self.command_line.value = format!("{}!", range);
+        self.command_line.cursor_position = self.command_line.value.chars().count();
+    }
+
+    /// `:Format`, running the buffer through its formatter in the
+    /// background.
+    pub(super) fn format_buffer(&mut self) -> AppResult<()> {
+        let command = self
+            .editor
+            .format_command()
+            .map_err(|err| self.shell_error(err))?;
+        let text = self.editor.gap_buffer.get_text_as_string();
+        self.spawn_filter(&command, Some(text), |app, formatted| {
+            if !app.editor.apply_step(&formatted) {
+                app.message = Some(StatusMessage::Info("Already formatted".into()));
+            }
+        })
+    }
+
+    /// Writes the buffer. With `formatonsave` the formatter runs in the
+    /// background first and the file is written once it's done, as it was
+    /// with a warning if the formatter failed, say on code that doesn't
+    /// parse yet.
+    pub(super) fn save(&mut self, force: bool) -> AppResult<()> {
+        if !self.editor.formats_on_save() {
+            return self.editor.save(force);
+        }
+        if self.is_saving() {
+            return Err("Already saving".into());
+        }
+        self.editor.check_writable(force)?;
+        self.editor.apply_save_transforms();
+        let command = self
+            .editor
+            .format_command()
+            .map_err(|err| self.shell_error(err))?;
+        let tick = self.editor.change_tick();
+        let text = self.editor.gap_buffer.get_text_as_string();
+        self.quit_after_save = false;
+        let id = self.jobs.spawn_process(
+            format!("!{}", command),
+            shell_command(&command),
+            Some(text),
+            move |app, output| {
+                let formatted = filter_output(&command, output).and_then(|formatted| {
+                    match app.editor.change_tick() == tick {
+                        true => Ok(formatted),
+                        false => Err(ShellError::new(format!(
+                            "text changed while {} ran",
+                            command
+                        ))),
+                    }
+                });
+                if let Ok(formatted) = &formatted {
+                    app.editor.apply_step(formatted);
+                }
+                let quit = std::mem::take(&mut app.quit_after_save);
+                app.editor.check_writable(force)?;
+                app.editor.write()?;
+                if let Err(err) = formatted {
+                    let err = app.shell_error(err);
+                    app.message = Some(StatusMessage::Warning(format!(
+                        "Written without formatting: {}",
+                        err
+                    )));
+                }
+                if quit {
+                    app.quit(false)?;
+                }
+                Ok(())
+            },
+        )?;
+        self.saving = Some(id);
+        Ok(())
+    }
+
+    /// Whether a save is waiting for the formatter.
+    pub(super) fn is_saving(&self) -> bool {
+        self.saving.is_some_and(|id| self.jobs.is_running(id))
+    }
+
+    /// Reports how a `:!cmd` run on the terminal went.
+    pub fn shell_finished(&mut self, status: AppResult<ExitStatus>) {
+        self.needs_redraw = true;
+        self.message = match status {
+            Ok(status) if status.success() => None,
+            Ok(status) => Some(StatusMessage::Error(format!(
+                "Shell returned {}",
+                exit_reason(status)
+            ))),
+            Err(err) => Some(StatusMessage::Error(err.to_string())),
+        };
+    }
+
+    /// First and last line of `range`, counting from 1
+    fn resolve_range(&self, range: LineRange) -> AppResult<(usize, usize)> {
+        let current = self.editor.line_of(self.editor.cursor_index) + 1;
+        Ok(range.resolve(current, self.editor.line_count())?)
+    }
+
+    /// The error to show for a failed command, with all of its stderr in a
+    /// popup when there's more than a line of it.
+    fn shell_error(&mut self, err: ShellError) -> Box<dyn std::error::Error> {
+        if err.stderr.trim_end().lines().count() > 1 {
+            let lines = err.stderr.lines().map(String::from).collect();
+            self.popup = Some(Popup::new("Shell error", lines));
+        }
+        err.message.into()
+    }
+}
+
+#[cfg(test)]
+mod miv_shell_tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::app::command_line::parse_command;
+    use crate::app::jobs::run_jobs;
+
+    fn app_with(text: &str) -> App {
+        let mut app = App::default();
+        app.editor.insert(text.into(), InputMode::Insert);
+        app.editor.set_cursor(0, InputMode::Normal);
+        app
+    }
+
+    /// Runs an ex command and the jobs it starts
+    fn run(app: &mut App, command: &str) {
+        let (sender, receiver) = mpsc::channel();
+        app.jobs.connect(sender);
+        app.execute(parse_command(command)).unwrap();
+        run_jobs(app, &receiver);
+    }
+
+    fn text(app: &App) -> String {
+        app.editor.gap_buffer.get_text_as_string()
+    }
+
+    #[test]
+    fn filters_replace_the_range() {
+        let mut app = app_with("c\nb\na\nz\n");
+        run(&mut app, "1,3!sort");
+        assert_eq!(text(&app), "a\nb\nc\nz\n");
+
+        let mut app = app_with("b\na");
+        run(&mut app, "%!sort");
+        assert_eq!(text(&app), "a\nb");
+    }
+
+    #[test]
+    fn failing_filters_leave_the_buffer_alone() {
+        let mut app = app_with("keep\n");
+        run(&mut app, "%!echo broken >&2; exit 3");
+        assert_eq!(text(&app), "keep\n");
+        assert_eq!(
+            app.message,
+            Some(StatusMessage::Error(
+                "echo broken >&2; exit 3 (exit 3): broken".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn output_is_dropped_when_the_text_changed_meanwhile() {
+        let mut app = app_with("b\na\n");
+        let (sender, receiver) = mpsc::channel();
+        app.jobs.connect(sender);
+        app.execute(parse_command("%!sort")).unwrap();
+        app.execute(vec![crate::commands::Command::EditorInsert("x".into())])
+            .unwrap();
+        run_jobs(&mut app, &receiver);
+        assert_eq!(text(&app), "xb\na\n");
+        assert_eq!(
+            app.message,
+            Some(StatusMessage::Error(
+                "Text changed while sort ran, output dropped".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn read_puts_output_below_the_line() {
+        let mut app = app_with("one\ntwo\n");
+        run(&mut app, "r !echo new");
+        assert_eq!(text(&app), "one\nnew\ntwo\n");
+        assert_eq!(app.editor.cursor_index, 4);
+
+        run(&mut app, "0r !echo top");
+        assert_eq!(text(&app), "top\none\nnew\ntwo\n");
+
+        let mut app = app_with("end");
+        run(&mut app, "$r !echo after");
+        assert_eq!(text(&app), "end\nafter");
+    }
+
+    #[test]
+    fn format_uses_the_buffer_formatprg() {
+        let mut app = app_with("b\na\n");
+        app.execute(parse_command("setlocal fp=sort")).unwrap();
+        run(&mut app, "Format");
+        assert_eq!(text(&app), "a\nb\n");
+
+        run(&mut app, "Format");
+        assert_eq!(
+            app.message,
+            Some(StatusMessage::Info("Already formatted".into()))
+        );
+        app.execute(parse_command("setlocal fp=false")).unwrap();
+        run(&mut app, "Format");
+        assert_eq!(text(&app), "a\nb\n");
+        assert!(matches!(app.message, Some(StatusMessage::Error(_))));
+    }
+
+    #[test]
+    fn failing_format_on_save_still_quits() {
+        let dir = tempfile::tempdir().unwrap();
+        let formatter = dir.path().join("formatter");
+        std::fs::write(
+            &formatter,
+            "#!/bin/sh\necho one >&2\necho two >&2\nexit 1\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&formatter, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut app = app_with("b\na\n");
+        app.editor.path = Some(dir.path().join("format.txt"));
+        app.execute(parse_command(&format!(
+            "setlocal fp={}",
+            formatter.display()
+        )))
+        .unwrap();
+        app.execute(parse_command("set formatonsave")).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        app.jobs.connect(sender);
+        app.execute(parse_command("wq")).unwrap();
+        // Quitting waits for the file to be written
+        assert!(app.running);
+        run_jobs(&mut app, &receiver);
+        assert!(!app.running);
+        assert_eq!(text(&app), "b\na\n");
+        assert!(matches!(app.message, Some(StatusMessage::Warning(_))));
+        assert!(app.popup.is_some());
+    }
+
+    #[test]
+    fn format_on_save_writes_the_formatted_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("format.txt");
+        let mut app = app_with("b\na\n");
+        app.editor.path = Some(path.clone());
+        app.execute(parse_command("setlocal fp=sort")).unwrap();
+        app.execute(parse_command("set formatonsave")).unwrap();
+        run(&mut app, "w");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb\n");
+        assert!(!app.editor.is_modified());
+
+        // Typing while the formatter runs gets the text written unformatted
+        let (sender, receiver) = mpsc::channel();
+        app.jobs.connect(sender);
+        app.execute(parse_command("w")).unwrap();
+        app.execute(vec![crate::commands::Command::EditorInsert("c\n".into())])
+            .unwrap();
+        run_jobs(&mut app, &receiver);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), text(&app));
+        assert_eq!(text(&app), "a\nc\nb\n");
+        assert!(matches!(app.message, Some(StatusMessage::Warning(_))));
+    }
+
+    #[test]
+    fn bang_motions_prompt_for_a_filter() {
+        let mut app = app_with("one\ntwo\n");
+        app.execute(vec![crate::commands::Command::EditorFilterPrompt(
+            Motion::NextLineStart,
+        )])
+        .unwrap();
+        assert_eq!(app.mode, InputMode::Command);
+        assert_eq!(app.command_line.value, ".,.+1!");
+        assert_eq!(app.editor.cursor_index, 0);
+    }
+}
@@ -0,0 +1,229 @@
+use std::env;
+
+use lazy_static::lazy_static;
+use ratatui::style::{Color, Modifier, Style};
+use strum_macros::{Display, EnumString};
+
+/// The colours a terminal can show, set with the `termcolors` option.
+#[derive(Display, Debug, EnumString, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ColorMode {
+    /// Any RGB colour
+    #[default]
+    #[strum(serialize = "truecolor")]
+    TrueColor,
+    /// The xterm 256 colour palette
+    #[strum(serialize = "256")]
+    Xterm256,
+    /// The 16 ANSI colours, as in the Linux console
+    #[strum(serialize = "16")]
+    Ansi16,
+    /// No colours at all, only bold, underline and reverse
+    #[strum(serialize = "none")]
+    None,
+}
+
+lazy_static! {
+    /// What the terminal miv runs in supports, the environment doesn't change
+    static ref DETECTED: ColorMode = detect_color_mode(
+        env::var("COLORTERM").ok().as_deref(),
+        env::var("TERM").ok().as_deref(),
+        env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()),
+    );
+}
+
+/// Modifiers that still make sense without colours
+const MONOCHROME_MODIFIERS: Modifier = Modifier::BOLD
+    .union(Modifier::UNDERLINED)
+    .union(Modifier::REVERSED);
+
+/// xterm's values for the 16 ANSI colours, which terminals mostly keep close to
+const ANSI_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Levels of each channel in the 6x6x6 colour cube starting at index 16
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorMode {
+    /// The mode for a `termcolors` value, detecting it for `auto`.
+    pub fn from_option(value: &str) -> Self {
+        value.parse().unwrap_or(*DETECTED)
+    }
+
+    /// Turns the colours of `style` into ones the terminal can show.
+    pub fn adapt(self, style: Style) -> Style {
+        let adapt_color = |color: Option<Color>| match self {
+            ColorMode::TrueColor => color,
+            ColorMode::Xterm256 => color.map(to_xterm_256),
+            ColorMode::Ansi16 => color.map(to_ansi_16),
+            ColorMode::None => None,
+        };
+        let mut style = Style {
+            fg: adapt_color(style.fg),
+            bg: adapt_color(style.bg),
+            ..style
+        };
+        if self == ColorMode::None {
+            style.add_modifier &= MONOCHROME_MODIFIERS;
+            style.sub_modifier &= MONOCHROME_MODIFIERS;
+        }
+        style
+    }
+}
+
+/// Works out the colours a terminal supports from its environment.
+///
+/// `COLORTERM` is the only reliable sign of RGB support, otherwise `TERM`
+/// names the palette. Unknown terminals get the 16 colours all of them have.
+pub fn detect_color_mode(colorterm: Option<&str>, term: Option<&str>, no_color: bool) -> ColorMode {
+    if no_color {
+        return ColorMode::None;
+    }
+    if matches!(colorterm, Some("truecolor" | "24bit")) {
+        return ColorMode::TrueColor;
+    }
+    match term.unwrap_or_default() {
+        "dumb" => ColorMode::None,
+        term if term.ends_with("-direct") => ColorMode::TrueColor,
+        term if term.contains("256color") => ColorMode::Xterm256,
+        _ => ColorMode::Ansi16,
+    }
+}
+
+/// The nearest entry of the xterm 256 colour palette, leaving the 16 ANSI
+/// colours and palette entries alone.
+fn to_xterm_256(color: Color) -> Color {
+    let Color::Rgb(r, g, b) = color else {
+        return color;
+    };
+    let cube_index = |value: u8| {
+        CUBE_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, level)| level.abs_diff(value))
+            .map(|(index, _)| index)
+            .unwrap()
+    };
+    let (ri, gi, bi) = (cube_index(r), cube_index(g), cube_index(b));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+    let cube_color = 16 + 36 * ri + 6 * gi + bi;
+
+    // The grey ramp runs from 8 to 238 in steps of 10
+    let average = (r as usize + g as usize + b as usize) / 3;
+    let grey_index = (average.saturating_sub(3) / 10).min(23);
+    let grey_level = (8 + grey_index * 10) as u8;
+    let grey = (grey_level, grey_level, grey_level);
+
+    if distance((r, g, b), grey) < distance((r, g, b), cube) {
+        Color::Indexed(232 + grey_index as u8)
+    } else {
+        Color::Indexed(cube_color as u8)
+    }
+}
+
+/// The nearest of the 16 ANSI colours.
+fn to_ansi_16(color: Color) -> Color {
+    let rgb = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(index) => indexed_rgb(index),
+        named => return named,
+    };
+    ANSI_COLORS
+        .iter()
+        .min_by_key(|(_, ansi)| distance(rgb, *ansi))
+        .map(|(color, _)| *color)
+        .unwrap()
+}
+
+/// The RGB value of an xterm 256 colour palette entry
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_COLORS[index as usize].1,
+        16..=231 => {
+            let index = (index - 16) as usize;
+            (
+                CUBE_LEVELS[index / 36],
+                CUBE_LEVELS[index / 6 % 6],
+                CUBE_LEVELS[index % 6],
+            )
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+/// Squared distance between two colours, weighted towards how the eye
+/// tells them apart
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let dr = a.0.abs_diff(b.0) as u32;
+    let dg = a.1.abs_diff(b.1) as u32;
+    let db = a.2.abs_diff(b.2) as u32;
+    2 * dr * dr + 4 * dg * dg + 3 * db * db
+}
+
+#[cfg(test)]
+mod miv_colors_tests {
+    use super::*;
+
+    #[test]
+    fn modes_are_detected_from_the_environment() {
+        let detect = |colorterm, term| detect_color_mode(colorterm, term, false);
+        assert_eq!(
+            detect(Some("truecolor"), Some("xterm-256color")),
+            ColorMode::TrueColor
+        );
+        assert_eq!(
+            detect(None, Some("xterm-kitty-direct")),
+            ColorMode::TrueColor
+        );
+        assert_eq!(detect(None, Some("tmux-256color")), ColorMode::Xterm256);
+        assert_eq!(detect(None, Some("linux")), ColorMode::Ansi16);
+        assert_eq!(detect(None, Some("dumb")), ColorMode::None);
+        assert_eq!(
+            detect_color_mode(Some("truecolor"), None, true),
+            ColorMode::None
+        );
+        assert_eq!(ColorMode::from_option("256"), ColorMode::Xterm256);
+    }
+
+    #[test]
+    fn rgb_maps_to_the_nearest_palette_entry() {
+        assert_eq!(to_xterm_256(Color::Rgb(255, 0, 0)), Color::Indexed(196));
+        assert_eq!(to_xterm_256(Color::Rgb(128, 128, 128)), Color::Indexed(244));
+        assert_eq!(to_xterm_256(Color::Rgb(95, 135, 175)), Color::Indexed(67));
+        assert_eq!(to_xterm_256(Color::Red), Color::Red);
+        assert_eq!(to_ansi_16(Color::Rgb(250, 10, 10)), Color::LightRed);
+        assert_eq!(to_ansi_16(Color::Rgb(30, 30, 46)), Color::Black);
+        assert_eq!(to_ansi_16(Color::Indexed(196)), Color::LightRed);
+    }
+
+    #[test]
+    fn no_colour_keeps_only_some_modifiers() {
+        let style = Style::default()
+            .fg(Color::Rgb(1, 2, 3))
+            .bg(Color::Blue)
+            .add_modifier(Modifier::BOLD | Modifier::ITALIC);
+        assert_eq!(
+            ColorMode::None.adapt(style),
+            Style::default().add_modifier(Modifier::BOLD)
+        );
+        assert_eq!(ColorMode::TrueColor.adapt(style), style);
+    }
+}
@@ -1,33 +1,100 @@
-use ratatui::style::Color;
+pub mod colors;
 
-#[derive(Debug)]
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+
+use crate::app::config::config_dir;
+
+use self::colors::ColorMode;
+
+/// Themes built into miv, as `(name, source)`
+const BUNDLED: &[(&str, &str)] = &[
+    ("catppuccin-frappe", include_str!("catppuccin-frappe.toml")),
+    ("catppuccin-latte", include_str!("catppuccin-latte.toml")),
+    (
+        "catppuccin-macchiato",
+        include_str!("catppuccin-macchiato.toml"),
+    ),
+    ("catppuccin-mocha", include_str!("catppuccin-mocha.toml")),
+];
+
+/// Hands out `Theme::id`s
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// How many themes deep `inherits` may go, which also stops cycles
+const MAX_INHERITS: usize = 8;
+
+/// What UI elements are drawn with when there are no colours, looked up
+/// like the styles in a theme
+const MONOCHROME_UI: &[(&str, Modifier)] = &[
+    ("cursorline", Modifier::UNDERLINED),
+    ("gutter.current", Modifier::BOLD),
+    ("selection", Modifier::REVERSED),
+    ("statusline", Modifier::REVERSED),
+    ("statusline.mode", Modifier::BOLD),
+    ("statusline.error", Modifier::BOLD),
+    ("popup.border", Modifier::BOLD),
+    ("whichkey.border", Modifier::BOLD),
+    ("quickfix.border", Modifier::BOLD),
+    ("quickfix.selected", Modifier::REVERSED),
+];
+
+/// Styles for highlight groups and parts of the UI, read from a theme file.
+///
+/// Both are looked up by dotted names, falling back to shorter names so
+/// `function.macro` is drawn as `function` when the theme doesn't style it.
+/// The UI elements are:
+///
+/// - `normal`: text in the editor and its background
+/// - `gutter`, `gutter.current`: line numbers, that of the cursor line
+/// - `cursorline`, `selection`
+/// - `statusline`, `statusline.mode.<mode>`, `statusline.prompt`,
+///   `statusline.info`, `statusline.error`, `statusline.warning`,
+///   `statusline.fileinfo`, `statusline.jobs`
+/// - `popup`, `popup.border`, `whichkey`, `whichkey.border`
+/// - `diff.plus`, `diff.minus`, `diff.delta`
+/// - `quickfix`, `quickfix.border`, `quickfix.selected`,
+///   `quickfix.<severity>`
+#[derive(Debug, Clone)]
 pub struct Theme {
-    pub rose: Color,
-    pub flamingo: Color,
-    pub pink: Color,
-    pub mauve: Color,
-    pub red: Color,
-    pub maroon: Color,
-    pub peach: Color,
-    pub yellow: Color,
-    pub green: Color,
-    pub teal: Color,
-    pub sky: Color,
-    pub sapphire: Color,
-    pub blue: Color,
-    pub lavender: Color,
-    pub text: Color,
-    pub subtext1: Color,
-    pub subtext2: Color,
-    pub overlay1: Color,
-    pub overlay2: Color,
-    pub overlay3: Color,
-    pub surface1: Color,
-    pub surface2: Color,
-    pub surface3: Color,
-    pub base: Color,
-    pub mantle: Color,
-    pub crust: Color,
+    pub name: String,
+    /// Tells loaded themes apart, even ones loaded from the same file
+    pub id: u64,
+    /// Colours the terminal can show, which the styles are adapted to
+    pub color_mode: ColorMode,
+    highlights: HashMap<String, Style>,
+    ui: HashMap<String, Style>,
+}
+
+/// A theme file as written, before its colours are resolved.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ThemeFile {
+    /// Theme whose tables this one extends
+    inherits: Option<String>,
+    /// Colour names usable in the styles, such as `blue = "#8caaee"`
+    palette: HashMap<String, String>,
+    highlights: HashMap<String, StyleDef>,
+    ui: HashMap<String, StyleDef>,
+}
+
+/// A style as written in a theme file, such as `{ fg = "blue", bold = true }`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct StyleDef {
+    fg: Option<String>,
+    bg: Option<String>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    /// Drawn as a plain underline, terminals are only told about those
+    undercurl: bool,
 }
 
 impl Default for Theme {
@@ -37,34 +104,234 @@ impl Default for Theme {
 }
 
 impl Theme {
+    /// Name of the theme used when none is configured
+    pub const DEFAULT_NAME: &'static str = "catppuccin-frappe";
+
+    /// The bundled default theme, a user's version of it is only read by
+    /// [`Theme::load`] so a broken one can be reported.
     pub fn new() -> Self {
-        Theme {
-            rose: Color::Rgb(242, 213, 207),
-            flamingo: Color::Rgb(238, 190, 190),
-            pink: Color::Rgb(244, 184, 228),
-            mauve: Color::Rgb(202, 158, 230),
-            red: Color::Rgb(231, 130, 132),
-            maroon: Color::Rgb(234, 153, 156),
-            peach: Color::Rgb(239, 159, 118),
-            yellow: Color::Rgb(229, 200, 144),
-            green: Color::Rgb(166, 209, 137),
-            teal: Color::Rgb(129, 200, 190),
-            sky: Color::Rgb(153, 209, 219),
-            sapphire: Color::Rgb(133, 193, 220),
-            blue: Color::Rgb(140, 170, 238),
-            lavender: Color::Rgb(186, 187, 241),
-            text: Color::Rgb(198, 208, 245),
-            subtext1: Color::Rgb(181, 191, 226),
-            subtext2: Color::Rgb(165, 173, 206),
-            overlay1: Color::Rgb(148, 156, 187),
-            overlay2: Color::Rgb(131, 139, 167),
-            overlay3: Color::Rgb(115, 121, 148),
-            surface1: Color::Rgb(98, 104, 128),
-            surface2: Color::Rgb(81, 87, 109),
-            surface3: Color::Rgb(65, 69, 89),
-            base: Color::Rgb(48, 52, 70),
-            mantle: Color::Rgb(41, 44, 60),
-            crust: Color::Rgb(35, 38, 52),
+        Self::read(Self::DEFAULT_NAME, false).expect("the bundled default theme is valid")
+    }
+
+    /// Loads the theme called `name`, a file in the themes directory taking
+    /// the place of a bundled theme of the same name.
+    pub fn load(name: &str) -> Result<Self, String> {
+        Self::read(name, true)
+    }
+
+    /// Reads the theme called `name`, looking in the themes directory only
+    /// if `user_themes` is set.
+    fn read(name: &str, user_themes: bool) -> Result<Self, String> {
+        let file = read_theme_file(name, 0, user_themes)?;
+        Self::from_file(name, file).map_err(|err| format!("Theme {}: {}", name, err))
+    }
+
+    /// Whether there is a theme called `name`, without reading it.
+    pub fn exists(name: &str) -> bool {
+        BUNDLED.iter().any(|(bundled, _)| *bundled == name) || user_theme_path(name).is_file()
+    }
+
+    /// Style for a highlight capture name such as `function.method`.
+    pub fn highlight(&self, name: &str) -> Style {
+        self.color_mode.adapt(lookup(&self.highlights, name))
+    }
+
+    /// Style for an element of the UI, see [`Theme`] for their names.
+    pub fn ui(&self, element: &str) -> Style {
+        let style = self.color_mode.adapt(lookup(&self.ui, element));
+        if self.color_mode != ColorMode::None {
+            return style;
+        }
+        let mut name = element;
+        loop {
+            if let Some((_, modifier)) = MONOCHROME_UI.iter().find(|(ui, _)| *ui == name) {
+                return style.add_modifier(*modifier);
+            }
+            match name.rsplit_once('.') {
+                Some((parent, _)) => name = parent,
+                None => return style,
+            }
+        }
+    }
+
+    fn from_file(name: &str, file: ThemeFile) -> Result<Self, String> {
+        let resolve = |table: HashMap<String, StyleDef>, kind: &str| {
+            table
+                .into_iter()
+                .map(|(key, def)| {
+                    let style = def
+                        .to_style(&file.palette)
+                        .map_err(|err| format!("{}.{}: {}", kind, key, err))?;
+                    Ok((key, style))
+                })
+                .collect::<Result<HashMap<_, _>, String>>()
+        };
+        Ok(Theme {
+            name: name.into(),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            color_mode: ColorMode::default(),
+            highlights: resolve(file.highlights, "highlights")?,
+            ui: resolve(file.ui, "ui")?,
+        })
+    }
+}
+
+impl StyleDef {
+    fn to_style(&self, palette: &HashMap<String, String>) -> Result<Style, String> {
+        let mut style = Style::default();
+        if let Some(fg) = &self.fg {
+            style = style.fg(resolve_color(fg, palette)?);
+        }
+        if let Some(bg) = &self.bg {
+            style = style.bg(resolve_color(bg, palette)?);
+        }
+        for (set, modifier) in [
+            (self.bold, Modifier::BOLD),
+            (self.italic, Modifier::ITALIC),
+            (self.underline || self.undercurl, Modifier::UNDERLINED),
+        ] {
+            if set {
+                style = style.add_modifier(modifier);
+            }
         }
+        Ok(style)
+    }
+}
+
+/// The style for `name` or, failing that, the one for the longest of its
+/// leading dotted parts that has one.
+fn lookup(table: &HashMap<String, Style>, name: &str) -> Style {
+    let mut name = name;
+    loop {
+        if let Some(style) = table.get(name) {
+            return *style;
+        }
+        match name.rsplit_once('.') {
+            Some((parent, _)) => name = parent,
+            None => return Style::default(),
+        }
+    }
+}
+
+/// A palette name, `#rrggbb`, a terminal colour name or a colour index.
+fn resolve_color(value: &str, palette: &HashMap<String, String>) -> Result<Color, String> {
+    let value = palette.get(value).map(String::as_str).unwrap_or(value);
+    Color::from_str(value).map_err(|_| format!("unknown colour `{}`", value))
+}
+
+/// Where user themes are kept, as `<name>.toml`
+pub fn themes_dir() -> PathBuf {
+    config_dir().join("themes")
+}
+
+fn user_theme_path(name: &str) -> PathBuf {
+    themes_dir().join(format!("{}.toml", name))
+}
+
+/// Reads a theme file with the tables of the themes it inherits from
+/// merged underneath its own, from the themes directory if `user_themes`
+/// is set and the bundled themes otherwise.
+fn read_theme_file(name: &str, depth: usize, user_themes: bool) -> Result<ThemeFile, String> {
+    if depth > MAX_INHERITS {
+        return Err(format!("Theme {}: inherits too deeply", name));
+    }
+    let bundled = || {
+        BUNDLED
+            .iter()
+            .find(|(bundled, _)| *bundled == name)
+            .map(|(_, source)| source.to_string())
+            .ok_or_else(|| format!("Unknown theme: {}", name))
+    };
+    let path = user_theme_path(name);
+    let source = if !user_themes {
+        bundled()?
+    } else {
+        match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => bundled()?,
+            Err(err) => return Err(format!("{}: {}", path.display(), err)),
+        }
+    };
+    let file = parse_theme_file(name, &source)?;
+    match &file.inherits {
+        Some(parent) => {
+            let mut merged = read_theme_file(parent, depth + 1, user_themes)?;
+            merged.palette.extend(file.palette);
+            merged.highlights.extend(file.highlights);
+            merged.ui.extend(file.ui);
+            Ok(merged)
+        }
+        None => Ok(file),
+    }
+}
+
+fn parse_theme_file(name: &str, source: &str) -> Result<ThemeFile, String> {
+    toml::from_str(source).map_err(|err| format!("Theme {}: {}", name, err.message()))
+}
+
+#[cfg(test)]
+mod miv_theme_tests {
+    use super::*;
+
+    fn theme(source: &str) -> Result<Theme, String> {
+        Theme::from_file("test", parse_theme_file("test", source)?)
+    }
+
+    #[test]
+    fn bundled_themes_load() {
+        for (name, _) in BUNDLED {
+            let theme = Theme::load(name).unwrap();
+            assert!(theme.ui("normal").bg.is_some(), "{}", name);
+        }
+        // The flavours share styles but not colours
+        let latte = Theme::load("catppuccin-latte").unwrap();
+        let mocha = Theme::load("catppuccin-mocha").unwrap();
+        assert_ne!(latte.highlight("keyword"), mocha.highlight("keyword"));
+        assert!(Theme::exists("catppuccin-mocha"));
+        assert!(!Theme::exists("solarized"));
+    }
+
+    #[test]
+    fn dotted_names_fall_back() {
+        let theme = theme(
+            "[palette]\nblue = \"#0000ff\"\n\
+             [highlights]\nfunction = { fg = \"blue\", bold = true }\n\
+             \"function.macro\" = { fg = \"red\" }\n",
+        )
+        .unwrap();
+        let function = Style::default()
+            .fg(Color::Rgb(0, 0, 255))
+            .add_modifier(Modifier::BOLD);
+        assert_eq!(theme.highlight("function.method.call"), function);
+        assert_eq!(theme.highlight("function.macro").fg, Some(Color::Red));
+        assert_eq!(theme.highlight("keyword"), Style::default());
+    }
+
+    #[test]
+    fn bad_styles_are_reported() {
+        let err = theme("[ui]\ngutter = { fg = \"blurple\" }\n").unwrap_err();
+        assert_eq!(err, "ui.gutter: unknown colour `blurple`");
+        assert!(theme("[ui]\ngutter = { colour = \"red\" }\n").is_err());
+        assert!(Theme::load("no-such-theme").is_err());
+    }
+
+    #[test]
+    fn no_colour_mode_uses_modifiers() {
+        let mut theme = Theme::new();
+        theme.color_mode = ColorMode::None;
+        assert_eq!(
+            theme.ui("selection"),
+            Style::default().add_modifier(Modifier::REVERSED)
+        );
+        assert_eq!(
+            theme.ui("statusline.mode.insert"),
+            Style::default().add_modifier(Modifier::BOLD)
+        );
+        assert_eq!(theme.highlight("keyword"), Style::default());
+        theme.color_mode = ColorMode::Xterm256;
+        assert!(matches!(
+            theme.highlight("keyword").fg,
+            Some(Color::Indexed(_))
+        ));
     }
 }
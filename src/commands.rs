@@ -1,8 +1,13 @@
-use crate::app::{editor::motions::Motion, InputMode};
+use serde::Deserialize;
 
-#[derive(Debug, Clone)]
+use crate::app::{command_line::range::LineRange, editor::motions::Motion, InputMode};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub enum Command {
     Quit,
+    ForceQuit,
+    QuitAll,
+    ForceQuitAll,
     CommandLineStop,
     CommandLineInsertChar(char),
     CommandLineDelete,
@@ -13,5 +18,83 @@ pub enum Command {
     EditorInsert(String),
     EditorDelete(Motion),
     EditorMove(Motion),
+    /// Moves the cursor to a character index
+    EditorSetCursor(usize),
+    EditorSelect {
+        anchor: usize,
+        cursor: usize,
+    },
+    /// Selects the word at a character index
+    EditorSelectWord(usize),
+    /// Scrolls by a number of screen rows, up when negative
+    EditorScroll(isize),
+    EditorUndo,
+    EditorRedo,
+    /// Ends the undo step, so what comes next is undone separately
+    EditorUndoBreak,
+    /// Starts typing a filter for the lines a motion covers, `!{motion}`
+    EditorFilterPrompt(Motion),
     EditorSave,
+    EditorSaveIfModified,
+    EditorSaveAll,
+    EditorForceSave,
+    EditorReload,
+    /// Runs the buffer through its formatter
+    EditorFormat,
+    /// Converts the indentation to tabs or spaces following `expandtab`
+    EditorRetab,
+    EditorKeepLocal,
+    EditorShowDiskDiff,
+    SwapRecover,
+    SwapDelete,
+    SwapOpenReadOnly,
+    SetOption(String),
+    SetLocalOption(String),
+    SourceConfig(Option<String>),
+    /// Switches to a theme, or shows the current one
+    ColorScheme(Option<String>),
+    /// Compiles the tree-sitter grammar in a directory into the config directory
+    InstallGrammar(String),
+    /// Binds keys to keys, looking the result up in the user's maps when `remap` is set
+    Map {
+        mode: InputMode,
+        lhs: String,
+        rhs: String,
+        remap: bool,
+    },
+    Unmap {
+        mode: InputMode,
+        lhs: String,
+    },
+    ListMappings,
+    /// Lists the jobs running in the background
+    ListJobs,
+    /// Stops a background job, or all of them
+    CancelJob(Option<u64>),
+    /// Runs a command on the terminal, `:!cmd`
+    Shell(String),
+    /// Replaces lines with what a command makes of them, `:{range}!cmd`
+    FilterLines {
+        range: LineRange,
+        command: String,
+    },
+    /// Runs the compiler in the background, filling the quickfix list
+    Make(Option<String>),
+    QuickfixOpen,
+    QuickfixClose,
+    QuickfixNext,
+    QuickfixPrev,
+    /// Jumps to a quickfix entry counting from 1, or the current one
+    QuickfixSelect(Option<usize>),
+    /// Moves the quickfix panel's selection by a number of entries
+    QuickfixMoveSelection(isize),
+    /// Jumps to the entry selected in the quickfix panel
+    QuickfixJumpSelected,
+    /// Puts what a command prints below a line, `:{line}r !cmd`
+    ReadShell {
+        range: Option<LineRange>,
+        command: String,
+    },
+    ShowError(String),
+    ClearMessage,
 }
@@ -1,11 +1,16 @@
+use crate::app::jobs::{JobDone, JobId};
 use crate::app::AppResult;
 use crossterm::event::{self, Event as CrosstermEvent, KeyEvent, MouseEvent};
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::{Duration, Instant};
 
+/// How often a paused handler thread checks whether to carry on
+const PAUSE_POLL: Duration = Duration::from_millis(10);
+
 /// Terminal events.
-#[derive(Clone, Copy, Debug)]
+#[derive(Debug)]
 pub enum Event {
     /// Terminal tick.
     Tick,
@@ -15,6 +20,14 @@ pub enum Event {
     Mouse(MouseEvent),
     /// Terminal resize.
     Resize(u16, u16),
+    /// Terminal window gained focus.
+    FocusGained,
+    /// Text pasted into the terminal.
+    Paste(String),
+    /// A background job finished.
+    JobDone(JobDone),
+    /// A background job reported how it's getting on.
+    JobProgress { id: JobId, message: String },
 }
 
 /// Terminal event handler.
@@ -27,18 +40,38 @@ pub struct EventHandler {
     receiver: mpsc::Receiver<Event>,
     /// Event handler thread.
     handler: thread::JoinHandle<()>,
+    /// Milliseconds between ticks, shared with the handler thread.
+    tick_rate: Arc<AtomicU64>,
+    /// Asks the handler thread to stop reading the terminal.
+    paused: Arc<AtomicBool>,
+    /// Set by the handler thread once it stopped reading.
+    parked: Arc<AtomicBool>,
 }
 
 impl EventHandler {
     /// Constructs a new instance of [`EventHandler`].
     pub fn new(tick_rate: u64) -> Self {
-        let tick_rate = Duration::from_millis(tick_rate);
+        let tick_rate = Arc::new(AtomicU64::new(tick_rate));
         let (sender, receiver) = mpsc::channel();
+        let paused = Arc::new(AtomicBool::new(false));
+        let parked = Arc::new(AtomicBool::new(false));
         let handler = {
             let sender = sender.clone();
+            let tick_rate = tick_rate.clone();
+            let paused = paused.clone();
+            let parked = parked.clone();
             thread::spawn(move || {
                 let mut last_tick = Instant::now();
                 loop {
+                    // Leaves the terminal's input to a program run from `:!`
+                    if paused.load(Ordering::Acquire) {
+                        parked.store(true, Ordering::Release);
+                        thread::sleep(PAUSE_POLL);
+                        continue;
+                    }
+                    parked.store(false, Ordering::Release);
+
+                    let tick_rate = Duration::from_millis(tick_rate.load(Ordering::Relaxed));
                     let timeout = tick_rate
                         .checked_sub(last_tick.elapsed())
                         .unwrap_or(tick_rate);
@@ -48,9 +81,9 @@ impl EventHandler {
                             CrosstermEvent::Key(e) => sender.send(Event::Key(e)),
                             CrosstermEvent::Mouse(e) => sender.send(Event::Mouse(e)),
                             CrosstermEvent::Resize(w, h) => sender.send(Event::Resize(w, h)),
-                            CrosstermEvent::FocusGained => Ok(()),
+                            CrosstermEvent::FocusGained => sender.send(Event::FocusGained),
                             CrosstermEvent::FocusLost => Ok(()),
-                            CrosstermEvent::Paste(_) => unimplemented!(),
+                            CrosstermEvent::Paste(text) => sender.send(Event::Paste(text)),
                         }
                         .expect("failed to send terminal event")
                     }
@@ -66,9 +99,31 @@ impl EventHandler {
             sender,
             receiver,
             handler,
+            tick_rate,
+            paused,
+            parked,
         }
     }
 
+    /// Stops reading the terminal, waiting for a read in progress to finish,
+    /// so another program can have its input.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Release);
+        while !self.parked.load(Ordering::Acquire) {
+            thread::sleep(PAUSE_POLL);
+        }
+    }
+
+    /// Reads the terminal again after [`EventHandler::pause`].
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Release);
+    }
+
+    /// Changes the time between ticks, taking effect from the next tick.
+    pub fn set_tick_rate(&self, tick_rate: u64) {
+        self.tick_rate.store(tick_rate, Ordering::Relaxed);
+    }
+
     /// Receive the next event from the handler thread.
     ///
     /// This function will always block the current thread if
@@ -76,4 +131,14 @@ impl EventHandler {
     pub fn next(&self) -> AppResult<Event> {
         Ok(self.receiver.recv()?)
     }
+
+    /// A sender for events from other threads, such as background jobs.
+    pub fn sender(&self) -> mpsc::Sender<Event> {
+        self.sender.clone()
+    }
+
+    /// Receive the next event if one is already waiting, without blocking.
+    pub fn try_next(&self) -> Option<Event> {
+        self.receiver.try_recv().ok()
+    }
 }
@@ -10,23 +10,36 @@ lazy_static! {
     pub static ref COMMAND_MAP: HashMap<String, Keymap> = {
         HashMap::from([
             (
-                "esc".into(),
+                "<Esc>".into(),
                 Keymap::One(vec![
                     Command::CommandLineStop,
                     Command::ChangeInputMode(InputMode::Normal),
-                ]),
+                ])
+                .describe("cancel"),
             ),
             (
-                "enter".into(),
+                "<CR>".into(),
+                // Back in normal mode first, so a command can switch to
+                // another mode such as `:copen` does
                 Keymap::One(vec![
+                    Command::ChangeInputMode(InputMode::Normal),
                     Command::CommandLineEnter,
                     Command::CommandLineStop,
-                    Command::ChangeInputMode(InputMode::Normal),
-                ]),
+                ])
+                .describe("run command"),
+            ),
+            (
+                "<BS>".into(),
+                Keymap::One(vec![Command::CommandLineDelete]).describe("delete previous character"),
+            ),
+            (
+                "<Left>".into(),
+                Keymap::One(vec![Command::CommandLineLeft]).describe("previous character"),
+            ),
+            (
+                "<Right>".into(),
+                Keymap::One(vec![Command::CommandLineRight]).describe("next character"),
             ),
-            ("back".into(), Keymap::One(vec![Command::CommandLineDelete])),
-            ("left".into(), Keymap::One(vec![Command::CommandLineLeft])),
-            ("right".into(), Keymap::One(vec![Command::CommandLineRight])),
         ])
     };
 }
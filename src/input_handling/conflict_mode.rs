@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+
+use crate::{app::InputMode, commands::Command};
+
+use super::Keymap;
+
+lazy_static! {
+    pub static ref CONFLICT_MAP: HashMap<String, Keymap> = {
+        HashMap::from([
+            (
+                "r".into(),
+                Keymap::One(vec![
+                    Command::EditorReload,
+                    Command::ChangeInputMode(InputMode::Normal),
+                ])
+                .describe("reload from disk"),
+            ),
+            (
+                "k".into(),
+                Keymap::One(vec![
+                    Command::EditorKeepLocal,
+                    Command::ChangeInputMode(InputMode::Normal),
+                ])
+                .describe("keep the buffer"),
+            ),
+            (
+                "d".into(),
+                Keymap::One(vec![Command::EditorShowDiskDiff]).describe("show the changes"),
+            ),
+            (
+                "<Esc>".into(),
+                Keymap::One(vec![
+                    Command::EditorKeepLocal,
+                    Command::ChangeInputMode(InputMode::Normal),
+                ])
+                .describe("keep the buffer"),
+            ),
+        ])
+    };
+}
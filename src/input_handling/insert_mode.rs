@@ -13,7 +13,7 @@ lazy_static! {
     pub static ref INSERT_MAP: HashMap<String, Keymap> = {
         HashMap::from([
             (
-                "esc".into(),
+                "<Esc>".into(),
                 Keymap::One(vec![
                     Command::ChangeInputMode(InputMode::Normal),
                     // The following is a fun hack that isn't too hard
@@ -21,23 +21,40 @@ lazy_static! {
                     // Have fun finding what it's a hack for.
                     Command::EditorMove(Motion::CharBackward),
                     Command::EditorMove(Motion::CharForward ),
-                ]),
+                ])
+                .describe("normal mode"),
             ),
             (
-                "back".into(),
-                Keymap::One(vec![Command::EditorDelete(Motion::CharBackward)]),
+                "<BS>".into(),
+                Keymap::One(vec![Command::EditorDelete(Motion::CharBackward)])
+                    .describe("delete previous character"),
             ),
             (
-                "right".into(),
-                Keymap::One(vec![Command::EditorMove(Motion::CharForward)]),
+                "<Right>".into(),
+                Keymap::One(vec![Command::EditorMove(Motion::CharForward)])
+                    .describe("next character"),
             ),
             (
-                "left".into(),
-                Keymap::One(vec![Command::EditorMove(Motion::CharBackward)]),
+                "<Left>".into(),
+                Keymap::One(vec![Command::EditorMove(Motion::CharBackward)])
+                    .describe("previous character"),
             ),
             (
-                "enter".into(),
-                Keymap::One(vec![Command::EditorInsert("\n".into())]),
+                "<CR>".into(),
+                Keymap::One(vec![Command::EditorInsert("\n".into())]).describe("new line"),
+            ),
+            (
+                "<Home>".into(),
+                Keymap::One(vec![Command::EditorMove(Motion::LineStart)]).describe("start of line"),
+            ),
+            (
+                "<End>".into(),
+                Keymap::One(vec![Command::EditorMove(Motion::LineEnd)]).describe("end of line"),
+            ),
+            (
+                "<Del>".into(),
+                Keymap::One(vec![Command::EditorDelete(Motion::CharForward)])
+                    .describe("delete next character"),
             ),
         ])
     };
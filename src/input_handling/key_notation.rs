@@ -0,0 +1,310 @@
+use std::fmt;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// A single key press along with its modifiers.
+///
+/// Keymaps are keyed by the canonical notation of a key, which is what
+/// [`fmt::Display`] prints: printable characters as they are and everything
+/// else in angle brackets like vim, e.g. `<C-w>`, `<A-j>`, `<S-Tab>`, `<F5>`.
+/// Shift is folded into the character for character keys, so shift and `a`
+/// is `A` rather than `<S-a>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+/// Names of the keys that are written in angle brackets, the first name for
+/// each key is the canonical one.
+const NAMED_KEYS: &[(&[&str], KeyCode)] = &[
+    (&["Esc", "Escape"], KeyCode::Esc),
+    (&["CR", "Enter", "Return"], KeyCode::Enter),
+    (&["BS", "Backspace"], KeyCode::Backspace),
+    (&["Tab"], KeyCode::Tab),
+    (&["Space"], KeyCode::Char(' ')),
+    (&["lt"], KeyCode::Char('<')),
+    (&["Bar"], KeyCode::Char('|')),
+    (&["Bslash"], KeyCode::Char('\\')),
+    (&["Left"], KeyCode::Left),
+    (&["Right"], KeyCode::Right),
+    (&["Up"], KeyCode::Up),
+    (&["Down"], KeyCode::Down),
+    (&["Home"], KeyCode::Home),
+    (&["End"], KeyCode::End),
+    (&["PageUp"], KeyCode::PageUp),
+    (&["PageDown"], KeyCode::PageDown),
+    (&["Del", "Delete"], KeyCode::Delete),
+    (&["Insert", "Ins"], KeyCode::Insert),
+];
+
+impl Key {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut key = Self { code, modifiers };
+        // Back tab is what terminals send for shift and tab
+        if key.code == KeyCode::BackTab {
+            key.code = KeyCode::Tab;
+            key.modifiers |= KeyModifiers::SHIFT;
+        }
+        if let KeyCode::Char(ch) = key.code {
+            if key.modifiers.contains(KeyModifiers::SHIFT) {
+                key.code = KeyCode::Char(ch.to_uppercase().next().unwrap_or(ch));
+                key.modifiers.remove(KeyModifiers::SHIFT);
+            }
+        }
+        key.modifiers &= KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT;
+        key
+    }
+
+    /// The key for a terminal event, if it's one miv knows how to bind.
+    pub fn from_event(event: KeyEvent) -> Option<Self> {
+        match event.code {
+            KeyCode::Char(_)
+            | KeyCode::Esc
+            | KeyCode::Enter
+            | KeyCode::Backspace
+            | KeyCode::Tab
+            | KeyCode::BackTab
+            | KeyCode::Left
+            | KeyCode::Right
+            | KeyCode::Up
+            | KeyCode::Down
+            | KeyCode::Home
+            | KeyCode::End
+            | KeyCode::PageUp
+            | KeyCode::PageDown
+            | KeyCode::Delete
+            | KeyCode::Insert
+            | KeyCode::F(_) => Some(Self::new(event.code, event.modifiers)),
+            _ => None,
+        }
+    }
+
+    /// The character typed by this key, if it types one.
+    pub fn char(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(ch) if self.modifiers.is_empty() => Some(ch),
+            _ => None,
+        }
+    }
+
+    /// Parses a single key, either a character or a name in angle brackets.
+    pub fn parse(notation: &str) -> Result<Self, String> {
+        match parse_keys(notation, "")?.as_slice() {
+            [key] => Ok(*key),
+            _ => Err(format!("Not a single key: {}", notation)),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.code {
+            KeyCode::F(n) => format!("F{}", n),
+            code => match NAMED_KEYS.iter().find(|(_, named)| *named == code) {
+                Some((names, _)) => names[0].to_string(),
+                None => match code {
+                    KeyCode::Char(ch) => ch.to_string(),
+                    _ => "Nop".to_string(),
+                },
+            },
+        };
+
+        let is_plain_char = matches!(self.code, KeyCode::Char(_)) && name.chars().count() == 1;
+        if self.modifiers.is_empty() && is_plain_char {
+            return write!(f, "{}", name);
+        }
+        write!(f, "<")?;
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "C-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "A-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "S-")?;
+        }
+        write!(f, "{}>", name)
+    }
+}
+
+/// Parses the name inside angle brackets, such as `C-w` or `F5`.
+fn parse_named(name: &str) -> Option<Key> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    // A trailing `-` is the minus key, as in `<C-->`
+    while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+        modifiers |= match rest.as_bytes()[0].to_ascii_lowercase() {
+            b'c' => KeyModifiers::CONTROL,
+            b'a' | b'm' => KeyModifiers::ALT,
+            b's' => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+        rest = &rest[2..];
+    }
+
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(ch), None) if !modifiers.is_empty() => KeyCode::Char(ch),
+        _ => {
+            let lower = rest.to_lowercase();
+            let named = NAMED_KEYS.iter().find(|(names, _)| {
+                names
+                    .iter()
+                    .any(|candidate| candidate.to_lowercase() == lower)
+            });
+            match named {
+                Some((_, code)) => *code,
+                None => {
+                    let number = lower.strip_prefix('f')?.parse().ok()?;
+                    if !(1..=24).contains(&number) {
+                        return None;
+                    }
+                    KeyCode::F(number)
+                }
+            }
+        }
+    };
+    Some(Key::new(code, modifiers))
+}
+
+/// Splits key notation such as `<leader>w` or `:w<CR>` into keys, with
+/// `<leader>` standing for the keys in `leader`.
+///
+/// A `<` that doesn't start a known key name is the `<` key itself.
+pub fn parse_keys(notation: &str, leader: &str) -> Result<Vec<Key>, String> {
+    let mut keys = vec![];
+    let mut rest = notation;
+    while let Some(ch) = rest.chars().next() {
+        let bracketed = rest
+            .strip_prefix('<')
+            .and_then(|after| {
+                after
+                    .find('>')
+                    .map(|end| (&after[..end], &after[end + 1..]))
+            })
+            .filter(|(name, _)| !name.is_empty() && !name.contains('<'));
+        // `<C->>` binds control and `>`
+        let bracketed = match bracketed {
+            Some((name, after)) if name.ends_with('-') && after.starts_with('>') => {
+                Some((&rest[1..name.len() + 2], &after[1..]))
+            }
+            bracketed => bracketed,
+        };
+
+        match bracketed {
+            Some((name, after)) if name.eq_ignore_ascii_case("leader") => {
+                keys.extend(parse_keys(leader, "")?);
+                rest = after;
+            }
+            Some((name, after)) => {
+                let key = parse_named(name).ok_or_else(|| format!("Unknown key: <{}>", name))?;
+                keys.push(key);
+                rest = after;
+            }
+            None => {
+                keys.push(Key::new(KeyCode::Char(ch), KeyModifiers::NONE));
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod miv_key_notation_tests {
+    use super::*;
+
+    fn canonical(notation: &str) -> String {
+        parse_keys(notation, "")
+            .unwrap()
+            .iter()
+            .map(Key::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn canonical_notation_round_trips() {
+        for notation in [
+            "a",
+            "A",
+            "<C-w>",
+            "<A-j>",
+            "<C-A-x>",
+            "<S-Tab>",
+            "<F5>",
+            "<F12>",
+            "<Home>",
+            "<End>",
+            "<PageUp>",
+            "<PageDown>",
+            "<Del>",
+            "<Insert>",
+            "<Esc>",
+            "<CR>",
+            "<BS>",
+            "<Space>",
+            "<lt>",
+            "<C-S-Left>",
+            "<C-->",
+        ] {
+            assert_eq!(canonical(notation), notation);
+            let key = Key::parse(notation).unwrap();
+            assert_eq!(Key::parse(&key.to_string()).unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn aliases_and_case_are_normalized() {
+        assert_eq!(canonical("<c-W>"), "<C-W>");
+        assert_eq!(canonical("<Enter><return><escape>"), "<CR><CR><Esc>");
+        assert_eq!(canonical("<M-j>"), "<A-j>");
+        assert_eq!(canonical("<S-a>"), "A");
+        assert_eq!(canonical("<delete><PAGEUP>"), "<Del><PageUp>");
+        assert_eq!(canonical(":w<CR>"), ":w<CR>");
+        assert_eq!(canonical("a<b"), "a<lt>b");
+    }
+
+    #[test]
+    fn events_use_the_same_notation() {
+        let key = |code, modifiers| Key::from_event(KeyEvent::new(code, modifiers)).unwrap();
+        assert_eq!(
+            key(KeyCode::BackTab, KeyModifiers::SHIFT).to_string(),
+            "<S-Tab>"
+        );
+        assert_eq!(
+            key(KeyCode::Char('A'), KeyModifiers::SHIFT).to_string(),
+            "A"
+        );
+        assert_eq!(
+            key(
+                KeyCode::Char('a'),
+                KeyModifiers::CONTROL | KeyModifiers::SHIFT
+            )
+            .to_string(),
+            "<C-A>"
+        );
+        assert_eq!(key(KeyCode::F(5), KeyModifiers::NONE).to_string(), "<F5>");
+        assert_eq!(
+            key(KeyCode::Char('j'), KeyModifiers::ALT),
+            Key::parse("<A-j>").unwrap()
+        );
+        assert_eq!(
+            key(KeyCode::Char(' '), KeyModifiers::NONE).char(),
+            Some(' ')
+        );
+        assert_eq!(
+            Key::from_event(KeyEvent::new(KeyCode::CapsLock, KeyModifiers::NONE)),
+            None
+        );
+    }
+
+    #[test]
+    fn leader_and_unknown_keys() {
+        let keys = parse_keys("<leader>w", "<Space>").unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].to_string(), "<Space>");
+        assert!(parse_keys("<Nope>", "").is_err());
+        assert!(parse_keys("<F25>", "").is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    app::{command_line::parse_command, InputMode},
+    commands::Command,
+};
+
+use super::{
+    default_map,
+    key_notation::{parse_keys, Key},
+    Keymap, SELF_KEY,
+};
+
+/// What a key sequence from the config file or `:map` is bound to.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Binding {
+    /// An ex command such as `"w"`
+    Ex(String),
+    /// Several ex commands, run in turn
+    ExSequence(Vec<String>),
+    /// One or more ex commands along with a description
+    DescribedEx {
+        #[serde(deserialize_with = "string_or_list")]
+        ex: Vec<String>,
+        desc: Option<String>,
+    },
+    /// Keys fed back in as if they were typed
+    Keys {
+        keys: String,
+        #[serde(default)]
+        noremap: bool,
+        desc: Option<String>,
+    },
+    /// Commands run as they are
+    Commands {
+        commands: Vec<Command>,
+        desc: Option<String>,
+    },
+}
+
+impl Binding {
+    /// What the binding does, shown in the which-key popup
+    pub fn description(&self) -> Option<&str> {
+        match self {
+            Binding::Ex(_) | Binding::ExSequence(_) => None,
+            Binding::DescribedEx { desc, .. }
+            | Binding::Keys { desc, .. }
+            | Binding::Commands { desc, .. } => desc.as_deref(),
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Ex(ex) => write!(f, ":{}", ex.trim_start_matches(':')),
+            Binding::ExSequence(ex) | Binding::DescribedEx { ex, .. } => {
+                write!(f, ":{}", ex.join(" | :"))
+            }
+            Binding::Keys { keys, noremap, .. } => {
+                write!(f, "{}{}", if *noremap { "* " } else { "" }, keys)
+            }
+            Binding::Commands { commands, .. } => write!(f, "{:?}", commands),
+        }
+    }
+}
+
+/// The key bindings of every mode.
+///
+/// They start out as the built in maps and are changed by the config file
+/// and `:map` while miv runs.
+#[derive(Debug, Clone)]
+pub struct Keymaps {
+    maps: HashMap<InputMode, HashMap<String, Keymap>>,
+    /// What the user mapped, by mode and keys as written, for `:map` to list
+    user: BTreeMap<(String, String), Binding>,
+}
+
+impl Default for Keymaps {
+    fn default() -> Self {
+        let modes = [
+            InputMode::Normal,
+            InputMode::Insert,
+            InputMode::Command,
+            InputMode::Conflict,
+            InputMode::Recovery,
+            InputMode::Quickfix,
+        ];
+        Self {
+            maps: modes
+                .into_iter()
+                .map(|mode| (mode, default_map(mode).clone()))
+                .collect(),
+            user: BTreeMap::new(),
+        }
+    }
+}
+
+impl Keymaps {
+    pub fn get(&self, mode: InputMode) -> &HashMap<String, Keymap> {
+        &self.maps[&mode]
+    }
+
+    /// Binds the keys written in `lhs`, with `<leader>` standing for `leader`.
+    pub fn map(
+        &mut self,
+        mode: InputMode,
+        lhs: &str,
+        binding: Binding,
+        leader: &str,
+    ) -> Result<(), String> {
+        let keys = key_names(lhs, leader)?;
+        if keys.is_empty() {
+            return Err("Argument required".into());
+        }
+        let keymap = match &binding {
+            Binding::Ex(ex) => Keymap::One(parse_command(ex.trim_start_matches(':'))),
+            Binding::ExSequence(ex) | Binding::DescribedEx { ex, .. } => Keymap::One(
+                ex.iter()
+                    .flat_map(|ex| parse_command(ex.trim_start_matches(':')))
+                    .collect(),
+            ),
+            Binding::Keys { keys, noremap, .. } => Keymap::Keys {
+                keys: key_names(keys, leader)?,
+                remap: !noremap,
+            },
+            Binding::Commands { commands, .. } => Keymap::One(commands.clone()),
+        };
+        // Without a description the popup shows what the keys are mapped
+        // to, as written
+        let keymap = match (binding.description(), &binding) {
+            (Some(description), _) => keymap.describe(description),
+            (None, Binding::Commands { .. }) => keymap,
+            (None, binding) => keymap.describe(binding.to_string()),
+        };
+        insert(self.maps.get_mut(&mode).unwrap(), &keys, keymap);
+        self.user.insert((mode.to_string(), lhs.into()), binding);
+        Ok(())
+    }
+
+    /// Removes the binding of the keys written in `lhs`.
+    pub fn unmap(&mut self, mode: InputMode, lhs: &str, leader: &str) -> Result<(), String> {
+        let keys = key_names(lhs, leader)?;
+        if keys.is_empty() || !remove(self.maps.get_mut(&mode).unwrap(), &keys) {
+            return Err(format!("No such mapping: {}", lhs));
+        }
+        self.user.remove(&(mode.to_string(), lhs.into()));
+        Ok(())
+    }
+
+    /// One line per mapping made by the user.
+    pub fn list(&self) -> Vec<String> {
+        self.user
+            .iter()
+            .map(|((mode, lhs), binding)| format!("{:<8}{:<12}{}", mode, lhs, binding))
+            .collect()
+    }
+}
+
+/// The keys following a prefix, looking through descriptions
+fn continuations(keymap: &mut Keymap) -> Option<&mut HashMap<String, Keymap>> {
+    match keymap {
+        Keymap::Many(next) => Some(next),
+        Keymap::Described(_, keymap) => continuations(keymap),
+        _ => None,
+    }
+}
+
+/// Adds a binding, keeping both when one sequence is a prefix of another
+fn insert(map: &mut HashMap<String, Keymap>, keys: &[String], keymap: Keymap) {
+    let (first, rest) = keys.split_first().unwrap();
+    if rest.is_empty() {
+        match map.get_mut(first).and_then(continuations) {
+            Some(next) => next.insert(SELF_KEY.into(), keymap),
+            None => map.insert(first.clone(), keymap),
+        };
+        return;
+    }
+    let entry = map
+        .entry(first.clone())
+        .or_insert_with(|| Keymap::Many(HashMap::new()));
+    if continuations(entry).is_none() {
+        let bound = std::mem::replace(entry, Keymap::Many(HashMap::new()));
+        continuations(entry).unwrap().insert(SELF_KEY.into(), bound);
+    }
+    insert(continuations(entry).unwrap(), rest, keymap);
+}
+
+fn remove(map: &mut HashMap<String, Keymap>, keys: &[String]) -> bool {
+    let (first, rest) = keys.split_first().unwrap();
+    let Some(next) = map.get_mut(first).and_then(continuations) else {
+        return rest.is_empty() && map.remove(first).is_some();
+    };
+    let removed = if rest.is_empty() {
+        next.remove(SELF_KEY).is_some()
+    } else {
+        remove(next, rest)
+    };
+    if next.is_empty() {
+        map.remove(first);
+    } else if next.len() == 1 {
+        // Only the prefix itself is left, which can go back to a plain binding
+        if let Some(bound) = next.remove(SELF_KEY) {
+            map.insert(first.clone(), bound);
+        }
+    }
+    removed
+}
+
+fn string_or_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        String(String),
+        List(Vec<String>),
+    }
+    Ok(match StringOrList::deserialize(deserializer)? {
+        StringOrList::String(string) => vec![string],
+        StringOrList::List(list) => list,
+    })
+}
+
+/// Key notation such as `<leader>w` or `:w<CR>` as the canonical key names
+/// the keymaps use.
+fn key_names(notation: &str, leader: &str) -> Result<Vec<String>, String> {
+    Ok(parse_keys(notation, leader)?
+        .iter()
+        .map(Key::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod miv_keymaps_tests {
+    use super::*;
+
+    #[test]
+    fn mappings_use_canonical_key_names() {
+        assert_eq!(
+            key_names("<leader>w<c-s>:w<Enter>", ",").unwrap(),
+            vec![",", "w", "<C-s>", ":", "w", "<CR>"]
+        );
+    }
+
+    #[test]
+    fn mapping_and_unmapping() {
+        let mut keymaps = Keymaps::default();
+        keymaps
+            .map(InputMode::Normal, "<leader>w", Binding::Ex("w".into()), " ")
+            .unwrap();
+        assert!(matches!(
+            keymaps.get(InputMode::Normal).get("<Space>"),
+            Some(Keymap::Many(_))
+        ));
+        assert_eq!(keymaps.list().len(), 1);
+
+        keymaps.unmap(InputMode::Normal, "<leader>w", " ").unwrap();
+        assert!(keymaps.get(InputMode::Normal).get("<Space>").is_none());
+        assert!(keymaps.list().is_empty());
+        assert!(keymaps.unmap(InputMode::Normal, "<leader>w", " ").is_err());
+    }
+
+    #[test]
+    fn every_default_binding_is_described() {
+        fn check(map: &HashMap<String, Keymap>) {
+            for (key, keymap) in map {
+                let Keymap::Described(_, inner) = keymap else {
+                    panic!("{} has no description", key);
+                };
+                if let Keymap::Many(next) = inner.as_ref() {
+                    check(next);
+                }
+            }
+        }
+        let keymaps = Keymaps::default();
+        for map in keymaps.maps.values() {
+            check(map);
+        }
+
+        let mut keymaps = Keymaps::default();
+        keymaps
+            .map(InputMode::Normal, "<leader>w", Binding::Ex("w".into()), " ")
+            .unwrap();
+        let Some(Keymap::Many(next)) = keymaps.get(InputMode::Normal).get("<Space>") else {
+            panic!("<leader> isn't a prefix");
+        };
+        assert_eq!(next["w"].label("w"), ":w");
+    }
+}
@@ -1,25 +1,98 @@
 pub mod command_mode;
+pub mod conflict_mode;
 pub mod insert_mode;
+pub mod key_notation;
+pub mod keymaps;
+pub mod mouse;
 pub mod normal_mode;
+pub mod quickfix_mode;
+pub mod recovery_mode;
 
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{KeyEvent, KeyEventKind};
 
-use crate::{app::InputMode, commands::Command};
+use crate::{
+    app::{options::Options, popup::Popup, InputMode},
+    commands::Command,
+};
 
-use self::{command_mode::COMMAND_MAP, insert_mode::INSERT_MAP, normal_mode::NORMAL_MAP};
+use self::{
+    command_mode::COMMAND_MAP, conflict_mode::CONFLICT_MAP, insert_mode::INSERT_MAP,
+    key_notation::Key, keymaps::Keymaps, normal_mode::NORMAL_MAP, quickfix_mode::QUICKFIX_MAP,
+    recovery_mode::RECOVERY_MAP,
+};
+
+/// How deep mappings may expand into other mappings before giving up,
+/// which stops `:nmap a b` and `:nmap b a` from looping forever.
+const MAX_MAP_DEPTH: usize = 100;
+
+/// Key under which a `Keymap::Many` keeps the binding of its own prefix, run
+/// when no other key follows within `timeoutlen`. Real keys are never empty.
+pub const SELF_KEY: &str = "";
 
 #[derive(Debug, Clone)]
 pub enum Keymap {
     One(Vec<Command>),
     Many(HashMap<String, Keymap>),
+    /// Keys fed back in, looked up in the user's maps when `remap` is set
+    /// and in the built in ones otherwise
+    Keys {
+        keys: Vec<String>,
+        remap: bool,
+    },
+    /// A binding along with what it does, for the which-key popup
+    Described(String, Box<Keymap>),
+}
+
+impl Keymap {
+    pub fn describe(self, description: impl Into<String>) -> Self {
+        Keymap::Described(description.into(), Box::new(self))
+    }
+
+    /// The binding without its description
+    fn inner(self) -> Self {
+        match self {
+            Keymap::Described(_, keymap) => keymap.inner(),
+            keymap => keymap,
+        }
+    }
+
+    /// Short text saying what the binding of `key` does, the key itself
+    /// when there's nothing better to say
+    pub fn label(&self, key: &str) -> String {
+        match self {
+            Keymap::Described(description, _) => description.clone(),
+            Keymap::Many(many) => format!("+{} keys", many.len()),
+            Keymap::One(_) => key.to_string(),
+            Keymap::Keys { keys, .. } => keys.concat(),
+        }
+    }
+}
+
+/// The built in keymap of a mode.
+pub fn default_map(mode: InputMode) -> &'static HashMap<String, Keymap> {
+    match mode {
+        InputMode::Normal => &NORMAL_MAP,
+        InputMode::Insert => &INSERT_MAP,
+        InputMode::Command => &COMMAND_MAP,
+        InputMode::Conflict => &CONFLICT_MAP,
+        InputMode::Recovery => &RECOVERY_MAP,
+        InputMode::Quickfix => &QUICKFIX_MAP,
+    }
 }
 
 #[derive(Debug)]
 pub struct InputStack {
     event_stack: Vec<String>,
     potentials: Option<HashMap<String, Keymap>>,
+    /// When the unfinished key sequence was last added to
+    pending_since: Option<Instant>,
+    /// Where the left button went down, for dragging out a selection
+    drag_anchor: Option<usize>,
+    /// When and where the last click was, for spotting double clicks
+    last_click: Option<(Instant, u16, u16)>,
 }
 
 impl Default for InputStack {
@@ -33,103 +106,395 @@ impl InputStack {
         Self {
             event_stack: vec![],
             potentials: None,
+            pending_since: None,
+            drag_anchor: None,
+            last_click: None,
         }
     }
 
-    fn character_input(&self, event: KeyEvent, mode: &InputMode) -> Option<char> {
-        if self.potentials.is_some() && !self.event_stack.is_empty() {
+    /// Gives up on an unfinished key sequence once `timeoutlen` has passed,
+    /// running the binding of the keys so far if they have one.
+    pub fn tick(&mut self, mode: &InputMode, keymaps: &Keymaps, options: &Options) -> Vec<Command> {
+        let timeout = Duration::from_millis(options.get_int("timeoutlen") as u64);
+        match self.pending_since {
+            Some(since) if options.get_bool("timeout") && since.elapsed() >= timeout => {}
+            _ => return vec![],
+        }
+        let potentials = self.potentials.take();
+        let pending = std::mem::take(&mut self.event_stack);
+        self.clear();
+        let result = match potentials.and_then(|many| many.get(SELF_KEY).cloned()) {
+            Some(bound) => self.resolve(bound, *mode, keymaps, 0),
+            None => Ok(pending
+                .iter()
+                .filter_map(|key| literal_input(key, *mode))
+                .collect()),
+        };
+        result.unwrap_or_else(|message| vec![Command::ShowError(message)])
+    }
+
+    /// The keys that may follow an unfinished sequence, once it has waited
+    /// for `whichkeydelay`.
+    pub fn which_key(&self, options: &Options) -> Option<Popup> {
+        let delay = Duration::from_millis(options.get_int("whichkeydelay") as u64);
+        if self.pending_since?.elapsed() < delay {
             return None;
         }
+        let mut continuations: Vec<(&String, &Keymap)> = self
+            .potentials
+            .as_ref()?
+            .iter()
+            .filter(|(key, _)| key.as_str() != SELF_KEY)
+            .collect();
+        continuations.sort_by(|a, b| a.0.cmp(b.0));
+        let width = continuations.iter().map(|(key, _)| key.len()).max()?;
+        let lines = continuations
+            .into_iter()
+            .map(|(key, keymap)| format!("{:<width$}  {}", key, keymap.label(key)))
+            .collect();
+        Some(Popup::new(self.event_stack.concat(), lines))
+    }
 
-        if let InputMode::Normal = mode {
+    pub fn handle_key_event(
+        &mut self,
+        event: KeyEvent,
+        mode: &InputMode,
+        keymaps: &Keymaps,
+    ) -> Option<Vec<Command>> {
+        // Terminals using the kitty keyboard protocol also report releases
+        // and repeats, which would otherwise run bindings a second time
+        if event.kind != KeyEventKind::Press {
             return None;
         }
+        let key = Key::from_event(event)?.to_string();
+        let commands = match self.feed(key, *mode, keymaps, true, 0) {
+            Ok(commands) => commands,
+            Err(message) => {
+                self.clear();
+                vec![Command::ShowError(message)]
+            }
+        };
+        (!commands.is_empty()).then_some(commands)
+    }
 
-        match event {
-            KeyEvent {
-                code: KeyCode::Char(_),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => None,
-            KeyEvent {
-                code: KeyCode::Char(ch),
-                ..
-            } => Some(ch),
-            _ => None,
+    /// Pasted text goes in as it is rather than being read as keys.
+    ///
+    /// The whole paste is a single insert in an undo step of its own, so it's
+    /// one change to the buffer however many lines it has.
+    pub fn handle_paste(&mut self, text: String, mode: &InputMode) -> Vec<Command> {
+        self.clear();
+        // Terminals send pasted line breaks as carriage returns
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        match mode {
+            InputMode::Normal | InputMode::Insert => vec![
+                Command::EditorUndoBreak,
+                Command::EditorInsert(text),
+                Command::EditorUndoBreak,
+            ],
+            InputMode::Command => text
+                .chars()
+                .filter(|ch| *ch != '\n')
+                .map(Command::CommandLineInsertChar)
+                .collect(),
+            InputMode::Conflict | InputMode::Recovery | InputMode::Quickfix => vec![],
+        }
+    }
+
+    /// Looks a key up following on from the keys before it.
+    ///
+    /// Keys that turn out not to be bound are typed as text in insert and
+    /// command mode, so a mapping on `jk` doesn't swallow a lone `j`.
+    fn feed(
+        &mut self,
+        key: String,
+        mode: InputMode,
+        keymaps: &Keymaps,
+        remap: bool,
+        depth: usize,
+    ) -> Result<Vec<Command>, String> {
+        let root = if remap {
+            keymaps.get(mode)
+        } else {
+            default_map(mode)
+        };
+        let pivot = self.potentials.as_ref().unwrap_or(root);
+        let candidate = pivot.get(&key).cloned();
+        self.event_stack.push(key);
+
+        match candidate.map(Keymap::inner) {
+            Some(Keymap::Many(many)) => {
+                self.potentials = Some(many);
+                self.pending_since = Some(Instant::now());
+                Ok(vec![])
+            }
+            Some(keymap) => {
+                self.clear();
+                self.resolve(keymap, mode, keymaps, depth)
+            }
+            None => {
+                let potentials = self.potentials.take();
+                let mut pending = std::mem::take(&mut self.event_stack);
+                self.clear();
+                let key = pending.pop().unwrap();
+                // Keys before the one that broke the sequence run the binding
+                // they have on their own, or are typed as they are
+                let mut commands = match potentials.and_then(|many| many.get(SELF_KEY).cloned()) {
+                    Some(bound) => self.resolve(bound, mode, keymaps, depth)?,
+                    None => pending
+                        .iter()
+                        .filter_map(|key| literal_input(key, mode))
+                        .collect(),
+                };
+                // The key that broke the sequence may start a new one
+                if pending.is_empty() {
+                    commands.extend(literal_input(&key, mode));
+                } else {
+                    let mode = mode_after(&commands, mode);
+                    commands.extend(self.feed(key, mode, keymaps, remap, depth)?);
+                }
+                Ok(commands)
+            }
         }
     }
 
-    pub fn handle_key_event(&mut self, event: KeyEvent, mode: &InputMode) -> Option<Vec<Command>> {
-        // First we will shortcircuit if we need to handle an editor InputMode
-        if let Some(ch) = self.character_input(event, mode) {
-            match mode {
-                InputMode::Normal => {}
-                InputMode::Insert => {
-                    return Some(vec![Command::EditorInsert(ch.into())]);
+    /// The commands a complete binding runs
+    fn resolve(
+        &mut self,
+        keymap: Keymap,
+        mode: InputMode,
+        keymaps: &Keymaps,
+        depth: usize,
+    ) -> Result<Vec<Command>, String> {
+        match keymap.inner() {
+            Keymap::One(one) => Ok(one),
+            Keymap::Keys { keys, remap } => {
+                if depth >= MAX_MAP_DEPTH {
+                    return Err("Recursive mapping".into());
                 }
-                InputMode::Command => {
-                    return Some(vec![Command::CommandLineInsertChar(ch)]);
+                let mut commands = vec![];
+                for key in keys {
+                    // Later keys are read in whichever mode the earlier ones switch to
+                    let mode = mode_after(&commands, mode);
+                    commands.extend(self.feed(key, mode, keymaps, remap, depth + 1)?);
                 }
+                Ok(commands)
             }
+            Keymap::Many(_) | Keymap::Described(..) => Ok(vec![]),
         }
+    }
+
+    fn clear(&mut self) {
+        self.potentials = None;
+        self.pending_since = None;
+        self.event_stack.clear();
+    }
+}
 
-        let key = get_valid_key_string_from_event(event);
+/// The mode the editor is in once `commands` have run
+fn mode_after(commands: &[Command], mode: InputMode) -> InputMode {
+    commands
+        .iter()
+        .rev()
+        .find_map(|command| match command {
+            Command::ChangeInputMode(new_mode) => Some(*new_mode),
+            _ => None,
+        })
+        .unwrap_or(mode)
+}
 
-        let candidate = if let Some(valid_key_press) = key {
-            self.event_stack.push(valid_key_press.clone());
+/// The command typing `key` as text would run, if the mode takes text
+fn literal_input(key: &str, mode: InputMode) -> Option<Command> {
+    let ch = Key::parse(key).ok()?.char()?;
+    match mode {
+        InputMode::Insert => Some(Command::EditorInsert(ch.into())),
+        InputMode::Command => Some(Command::CommandLineInsertChar(ch)),
+        InputMode::Normal | InputMode::Conflict | InputMode::Recovery | InputMode::Quickfix => None,
+    }
+}
 
-            let pivot: &HashMap<String, Keymap> = if let Some(pot) = &self.potentials {
-                pot
-            } else {
-                match mode {
-                    InputMode::Normal => &NORMAL_MAP,
-                    InputMode::Insert => &INSERT_MAP,
-                    InputMode::Command => &COMMAND_MAP,
-                }
-            };
+#[cfg(test)]
+mod miv_input_handling_tests {
+    use super::*;
+    use crate::app::editor::motions::Motion;
+    use crossterm::event::{KeyCode, KeyModifiers};
+    use keymaps::Binding;
 
-            pivot.get(&valid_key_press).cloned()
-        } else {
-            None
-        };
+    fn press(
+        stack: &mut InputStack,
+        keys: &str,
+        mode: InputMode,
+        keymaps: &Keymaps,
+    ) -> Vec<Command> {
+        keys.chars()
+            .flat_map(|ch| {
+                let event = KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE);
+                stack
+                    .handle_key_event(event, &mode, keymaps)
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
 
-        match candidate {
-            Some(km) => match km {
-                Keymap::Many(many) => {
-                    self.potentials = Some(many);
-                    None
-                }
-                Keymap::One(one) => {
-                    self.potentials = None;
-                    self.event_stack.clear();
-                    Some(one)
-                }
-            },
-            None => {
-                self.potentials = None;
-                self.event_stack.clear();
+    fn keys(keys: &str, noremap: bool) -> Binding {
+        Binding::Keys {
+            keys: keys.into(),
+            noremap,
+            desc: None,
+        }
+    }
+
+    #[test]
+    fn pastes_are_inserted_whole() {
+        let mut stack = InputStack::new();
+        assert_eq!(
+            stack.handle_paste("fn main() {\r\n}\r".into(), &InputMode::Insert),
+            vec![
+                Command::EditorUndoBreak,
+                Command::EditorInsert("fn main() {\n}\n".into()),
+                Command::EditorUndoBreak,
+            ]
+        );
+        assert_eq!(
+            stack.handle_paste("w\n".into(), &InputMode::Command),
+            vec![Command::CommandLineInsertChar('w')]
+        );
+    }
+
+    #[test]
+    fn key_releases_and_repeats_are_ignored() {
+        let keymaps = Keymaps::default();
+        let mut stack = InputStack::new();
+        let mut event = KeyEvent::new(KeyCode::Char(':'), KeyModifiers::NONE);
+        for kind in [KeyEventKind::Release, KeyEventKind::Repeat] {
+            event.kind = kind;
+            assert_eq!(
+                stack.handle_key_event(event, &InputMode::Normal, &keymaps),
                 None
-            }
+            );
         }
+        event.kind = KeyEventKind::Press;
+        assert_eq!(
+            stack.handle_key_event(event, &InputMode::Normal, &keymaps),
+            Some(vec![Command::ChangeInputMode(InputMode::Command)])
+        );
     }
-}
 
-fn get_valid_key_string_from_event(event: KeyEvent) -> Option<String> {
-    match event.code {
-        KeyCode::Backspace => Some("back".into()),
-        KeyCode::Enter => Some("enter".into()),
-        KeyCode::Left => Some("left".into()),
-        KeyCode::Right => Some("right".into()),
-        KeyCode::Up => Some("up".into()),
-        KeyCode::Down => Some("down".into()),
-        KeyCode::Tab => Some("tab".into()),
-        KeyCode::BackTab => Some("backtab".into()),
-        KeyCode::Char(ch) => Some(ch.into()),
-        KeyCode::Esc => Some("esc".into()),
-        _ => None,
-    }
-    .map(|key_as_str| match event.modifiers {
-        KeyModifiers::CONTROL => format!("ctrl+{}", key_as_str),
-        _ => key_as_str,
-    })
+    #[test]
+    fn key_mappings_switch_modes_as_they_go() {
+        let mut keymaps = Keymaps::default();
+        keymaps
+            .map(InputMode::Normal, "<leader>w", keys(":w<CR>", false), ",")
+            .unwrap();
+        let commands = press(&mut InputStack::new(), ",w", InputMode::Normal, &keymaps);
+        assert_eq!(commands[0], Command::ChangeInputMode(InputMode::Command));
+        assert_eq!(commands[1], Command::CommandLineInsertChar('w'));
+        assert_eq!(commands[2], Command::ChangeInputMode(InputMode::Normal));
+        assert_eq!(commands[3], Command::CommandLineEnter);
+    }
+
+    #[test]
+    fn unfinished_insert_mappings_type_their_keys() {
+        let mut keymaps = Keymaps::default();
+        keymaps
+            .map(InputMode::Insert, "jk", keys("<Esc>", false), "")
+            .unwrap();
+        let mut stack = InputStack::new();
+        assert_eq!(
+            press(&mut stack, "jx", InputMode::Insert, &keymaps),
+            vec![
+                Command::EditorInsert("j".into()),
+                Command::EditorInsert("x".into())
+            ]
+        );
+        assert_eq!(
+            press(&mut stack, "jk", InputMode::Insert, &keymaps)[0],
+            Command::ChangeInputMode(InputMode::Normal)
+        );
+    }
+
+    #[test]
+    fn noremap_uses_the_built_in_keys() {
+        let mut keymaps = Keymaps::default();
+        keymaps
+            .map(InputMode::Normal, "w", keys("b", false), "")
+            .unwrap();
+        keymaps
+            .map(InputMode::Normal, "x", keys("w", true), "")
+            .unwrap();
+        assert_eq!(
+            press(&mut InputStack::new(), "x", InputMode::Normal, &keymaps),
+            vec![Command::EditorMove(Motion::NextWordStart)]
+        );
+    }
+
+    #[test]
+    fn recursive_mappings_are_stopped() {
+        let mut keymaps = Keymaps::default();
+        keymaps
+            .map(InputMode::Normal, "a", keys("b", false), "")
+            .unwrap();
+        keymaps
+            .map(InputMode::Normal, "b", keys("a", false), "")
+            .unwrap();
+        assert_eq!(
+            press(&mut InputStack::new(), "a", InputMode::Normal, &keymaps),
+            vec![Command::ShowError("Recursive mapping".into())]
+        );
+    }
+
+    #[test]
+    fn timeout_runs_the_binding_of_the_prefix() {
+        let mut keymaps = Keymaps::default();
+        keymaps
+            .map(InputMode::Normal, "g", keys("0", false), "")
+            .unwrap();
+        let mut options = Options::default();
+        options.apply("timeoutlen=0", false).unwrap();
+
+        let mut stack = InputStack::new();
+        assert!(press(&mut stack, "g", InputMode::Normal, &keymaps).is_empty());
+        assert_eq!(
+            stack.tick(&InputMode::Normal, &keymaps, &options),
+            vec![Command::EditorMove(Motion::LineStart)]
+        );
+        // The longer sequence still works
+        assert_eq!(
+            press(&mut stack, "ge", InputMode::Normal, &keymaps),
+            vec![Command::EditorMove(Motion::LastWordEnd)]
+        );
+    }
+
+    #[test]
+    fn timeout_types_unbound_prefixes_in_insert_mode() {
+        let mut keymaps = Keymaps::default();
+        keymaps
+            .map(InputMode::Insert, "jk", keys("<Esc>", false), "")
+            .unwrap();
+        let mut options = Options::default();
+        options.apply("timeoutlen=0", false).unwrap();
+
+        let mut stack = InputStack::new();
+        press(&mut stack, "j", InputMode::Insert, &keymaps);
+        assert_eq!(
+            stack.tick(&InputMode::Insert, &keymaps, &options),
+            vec![Command::EditorInsert("j".into())]
+        );
+    }
+
+    #[test]
+    fn which_key_lists_continuations() {
+        let keymaps = Keymaps::default();
+        let mut options = Options::default();
+        options.apply("whichkeydelay=0", false).unwrap();
+
+        let mut stack = InputStack::new();
+        assert!(stack.which_key(&options).is_none());
+        press(&mut stack, "g", InputMode::Normal, &keymaps);
+        let which_key = stack.which_key(&options).unwrap();
+        assert_eq!(which_key.title, "g");
+        assert_eq!(
+            which_key.lines,
+            vec!["E  end of previous WORD", "e  end of previous word"]
+        );
+    }
 }
@@ -0,0 +1,178 @@
+use std::time::{Duration, Instant};
+
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+
+use crate::{
+    app::{editor::EditorBuffer, InputMode},
+    commands::Command,
+};
+
+use super::InputStack;
+
+/// Rows scrolled by one step of the wheel
+const SCROLL_ROWS: isize = 3;
+
+/// Longest gap between two clicks on the same cell for them to count as a
+/// double click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+impl InputStack {
+    /// Turns a mouse event into commands, using where the editor and the
+    /// status line on `status_row` were last drawn to find what's under the
+    /// pointer.
+    pub fn handle_mouse_event(
+        &mut self,
+        event: MouseEvent,
+        mode: &InputMode,
+        editor: &EditorBuffer,
+        status_row: u16,
+    ) -> Vec<Command> {
+        let viewport = &editor.viewport;
+        let (column, row) = (event.column, event.row);
+        let index_at = || viewport.index_at(column, row, editor.left_col);
+
+        match event.kind {
+            MouseEventKind::ScrollUp => vec![Command::EditorScroll(-SCROLL_ROWS)],
+            MouseEventKind::ScrollDown => vec![Command::EditorScroll(SCROLL_ROWS)],
+            // The command line and the prompts own the keyboard, leave the
+            // cursor where it is
+            _ if !matches!(mode, InputMode::Normal | InputMode::Insert) => vec![],
+            MouseEventKind::Down(MouseButton::Left) if viewport.contains(column, row) => {
+                let index = index_at();
+                let is_double_click = self.last_click.is_some_and(|(at, last_column, last_row)| {
+                    at.elapsed() < DOUBLE_CLICK && (last_column, last_row) == (column, row)
+                });
+                if is_double_click {
+                    self.last_click = None;
+                    self.drag_anchor = None;
+                    return vec![Command::EditorSelectWord(index)];
+                }
+                self.last_click = Some((Instant::now(), column, row));
+                self.drag_anchor = Some(index);
+                vec![Command::EditorSetCursor(index)]
+            }
+            // Clicking the status line dismisses the message shown there
+            MouseEventKind::Down(MouseButton::Left) if row == status_row => {
+                vec![Command::ClearMessage]
+            }
+            MouseEventKind::Drag(MouseButton::Left) => match self.drag_anchor {
+                Some(anchor) => vec![Command::EditorSelect {
+                    anchor,
+                    cursor: index_at(),
+                }],
+                None => vec![],
+            },
+            MouseEventKind::Up(MouseButton::Left) => {
+                self.drag_anchor = None;
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod miv_mouse_tests {
+    use super::*;
+    use crate::app::editor::viewport::{ViewRow, Viewport};
+    use crossterm::event::KeyModifiers;
+
+    /// Below the editor and a quickfix panel of 2 rows
+    const STATUS_ROW: u16 = 7;
+
+    /// "ab\ncd\n" drawn at the top left with a gutter of 2
+    fn editor() -> EditorBuffer {
+        let mut editor = EditorBuffer::default();
+        editor.viewport = Viewport {
+            x: 0,
+            y: 0,
+            width: 10,
+            height: 5,
+            gutter_width: 2,
+            rows: vec![
+                ViewRow {
+                    cells: vec![0, 1],
+                    end: 2,
+                },
+                ViewRow {
+                    cells: vec![3, 4],
+                    end: 5,
+                },
+            ],
+            ..Viewport::default()
+        };
+        editor
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    #[test]
+    fn clicks_drags_and_double_clicks() {
+        let editor = editor();
+        let mut stack = InputStack::new();
+        let down = MouseEventKind::Down(MouseButton::Left);
+        let up = MouseEventKind::Up(MouseButton::Left);
+        let mode = InputMode::Normal;
+
+        assert_eq!(
+            stack.handle_mouse_event(mouse(down, 3, 1), &mode, &editor, STATUS_ROW),
+            vec![Command::EditorSetCursor(4)]
+        );
+        assert_eq!(
+            stack.handle_mouse_event(
+                mouse(MouseEventKind::Drag(MouseButton::Left), 2, 0),
+                &mode,
+                &editor,
+                STATUS_ROW
+            ),
+            vec![Command::EditorSelect {
+                anchor: 4,
+                cursor: 0
+            }]
+        );
+        stack.handle_mouse_event(mouse(up, 2, 0), &mode, &editor, STATUS_ROW);
+        assert_eq!(
+            stack.handle_mouse_event(mouse(down, 2, 0), &mode, &editor, STATUS_ROW),
+            vec![Command::EditorSetCursor(0)]
+        );
+        assert_eq!(
+            stack.handle_mouse_event(mouse(down, 2, 0), &mode, &editor, STATUS_ROW),
+            vec![Command::EditorSelectWord(0)]
+        );
+    }
+
+    #[test]
+    fn status_line_scrolling_and_other_modes() {
+        let editor = editor();
+        let mut stack = InputStack::new();
+        let down = MouseEventKind::Down(MouseButton::Left);
+
+        assert_eq!(
+            stack.handle_mouse_event(mouse(down, 0, 7), &InputMode::Normal, &editor, STATUS_ROW),
+            vec![Command::ClearMessage]
+        );
+        // The quickfix panel right below the editor
+        assert!(stack
+            .handle_mouse_event(mouse(down, 0, 5), &InputMode::Normal, &editor, STATUS_ROW)
+            .is_empty());
+        assert!(stack
+            .handle_mouse_event(mouse(down, 3, 0), &InputMode::Command, &editor, STATUS_ROW)
+            .is_empty());
+        assert_eq!(
+            stack.handle_mouse_event(
+                mouse(MouseEventKind::ScrollDown, 3, 0),
+                &InputMode::Command,
+                &editor,
+                STATUS_ROW
+            ),
+            vec![Command::EditorScroll(3)]
+        );
+    }
+}
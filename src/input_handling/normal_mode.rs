@@ -14,120 +14,231 @@ lazy_static! {
         HashMap::from([
             (
                 ":".into(),
-                Keymap::One(vec![Command::ChangeInputMode(InputMode::Command)]),
+                Keymap::One(vec![Command::ChangeInputMode(InputMode::Command)])
+                    .describe("command line"),
             ),
             (
                 "i".into(),
-                Keymap::One(vec![Command::ChangeInputMode(InputMode::Insert)]),
+                Keymap::One(vec![Command::ChangeInputMode(InputMode::Insert)]).describe("insert"),
             ),
             (
                 "a".into(),
                 Keymap::One(vec![
                     Command::ChangeInputMode(InputMode::Insert),
                     Command::EditorMove(Motion::CharForward),
-                ]),
+                ])
+                .describe("append"),
             ),
             (
                 "W".into(),
-                Keymap::One(vec![Command::EditorMove(Motion::NextWordProperStart)]),
+                Keymap::One(vec![Command::EditorMove(Motion::NextWordProperStart)])
+                    .describe("next WORD"),
             ),
             (
                 "w".into(),
-                Keymap::One(vec![Command::EditorMove(Motion::NextWordStart)]),
+                Keymap::One(vec![Command::EditorMove(Motion::NextWordStart)]).describe("next word"),
             ),
             (
                 "E".into(),
-                Keymap::One(vec![Command::EditorMove(Motion::NextWordProperEnd)]),
+                Keymap::One(vec![Command::EditorMove(Motion::NextWordProperEnd)])
+                    .describe("end of WORD"),
             ),
             (
                 "e".into(),
-                Keymap::One(vec![Command::EditorMove(Motion::NextWordEnd)]),
+                Keymap::One(vec![Command::EditorMove(Motion::NextWordEnd)]).describe("end of word"),
             ),
             (
                 "B".into(),
-                Keymap::One(vec![Command::EditorMove(Motion::LastWordProperStart)]),
+                Keymap::One(vec![Command::EditorMove(Motion::LastWordProperStart)])
+                    .describe("previous WORD"),
             ),
             (
                 "b".into(),
-                Keymap::One(vec![Command::EditorMove(Motion::LastWordStart)]),
+                Keymap::One(vec![Command::EditorMove(Motion::LastWordStart)])
+                    .describe("previous word"),
             ),
             (
                 "g".into(),
                 Keymap::Many(HashMap::from([
                     (
                         "e".into(),
-                        Keymap::One(vec![Command::EditorMove(Motion::LastWordEnd)]),
+                        Keymap::One(vec![Command::EditorMove(Motion::LastWordEnd)])
+                            .describe("end of previous word"),
                     ),
                     (
                         "E".into(),
-                        Keymap::One(vec![Command::EditorMove(Motion::LastWordProperEnd)]),
+                        Keymap::One(vec![Command::EditorMove(Motion::LastWordProperEnd)])
+                            .describe("end of previous WORD"),
                     ),
-                ])),
+                ]))
+                .describe("+go"),
             ),
             (
                 "$".into(),
-                Keymap::One(vec![Command::EditorMove(Motion::LineEnd)]),
+                Keymap::One(vec![Command::EditorMove(Motion::LineEnd)]).describe("end of line"),
             ),
             (
                 "0".into(),
-                Keymap::One(vec![Command::EditorMove(Motion::LineStart)]),
+                Keymap::One(vec![Command::EditorMove(Motion::LineStart)]).describe("start of line"),
             ),
             (
                 "d".into(),
                 Keymap::Many(HashMap::from([
                     (
                         "W".into(),
-                        Keymap::One(vec![Command::EditorDelete(Motion::NextWordProperStart)]),
+                        Keymap::One(vec![Command::EditorDelete(Motion::NextWordProperStart)])
+                            .describe("to next WORD"),
                     ),
                     (
                         "w".into(),
-                        Keymap::One(vec![Command::EditorDelete(Motion::NextWordStart)]),
+                        Keymap::One(vec![Command::EditorDelete(Motion::NextWordStart)])
+                            .describe("to next word"),
                     ),
                     (
                         "E".into(),
-                        Keymap::One(vec![Command::EditorDelete(Motion::NextWordProperEnd)]),
+                        Keymap::One(vec![Command::EditorDelete(Motion::NextWordProperEnd)])
+                            .describe("to end of WORD"),
                     ),
                     (
                         "e".into(),
-                        Keymap::One(vec![Command::EditorDelete(Motion::NextWordEnd)]),
+                        Keymap::One(vec![Command::EditorDelete(Motion::NextWordEnd)])
+                            .describe("to end of word"),
                     ),
                     (
                         "B".into(),
-                        Keymap::One(vec![Command::EditorDelete(Motion::LastWordProperStart)]),
+                        Keymap::One(vec![Command::EditorDelete(Motion::LastWordProperStart)])
+                            .describe("to previous WORD"),
                     ),
                     (
                         "b".into(),
-                        Keymap::One(vec![Command::EditorDelete(Motion::LastWordStart)]),
+                        Keymap::One(vec![Command::EditorDelete(Motion::LastWordStart)])
+                            .describe("to previous word"),
                     ),
                     (
                         "g".into(),
                         Keymap::Many(HashMap::from([
                             (
                                 "e".into(),
-                                Keymap::One(vec![Command::EditorDelete(Motion::LastWordEnd)]),
+                                Keymap::One(vec![Command::EditorDelete(Motion::LastWordEnd)])
+                                    .describe("to end of previous word"),
                             ),
                             (
                                 "E".into(),
-                                Keymap::One(vec![Command::EditorDelete(Motion::LastWordProperEnd)]),
+                                Keymap::One(vec![Command::EditorDelete(Motion::LastWordProperEnd)])
+                                    .describe("to end of previous WORD"),
                             ),
-                        ])),
+                        ]))
+                        .describe("+end of previous word"),
                     ),
                     (
                         "$".into(),
                         Keymap::One(vec![
                             Command::EditorInsert("\n".into()),
                             Command::EditorDelete(Motion::NextLineStart),
-                        ]),
+                        ])
+                        .describe("to end of line"),
                     ),
                     (
                         "0".into(),
-                        Keymap::One(vec![Command::EditorDelete(Motion::LineStart)]),
+                        Keymap::One(vec![Command::EditorDelete(Motion::LineStart)])
+                            .describe("to start of line"),
                     ),
-                ])),
+                ]))
+                .describe("+delete"),
+            ),
+            (
+                "!".into(),
+                Keymap::Many(HashMap::from([
+                    (
+                        "!".into(),
+                        Keymap::One(vec![Command::EditorFilterPrompt(Motion::LineStart)])
+                            .describe("current line"),
+                    ),
+                    (
+                        "W".into(),
+                        Keymap::One(vec![Command::EditorFilterPrompt(
+                            Motion::NextWordProperStart,
+                        )])
+                        .describe("to next WORD"),
+                    ),
+                    (
+                        "w".into(),
+                        Keymap::One(vec![Command::EditorFilterPrompt(Motion::NextWordStart)])
+                            .describe("to next word"),
+                    ),
+                    (
+                        "E".into(),
+                        Keymap::One(vec![Command::EditorFilterPrompt(Motion::NextWordProperEnd)])
+                            .describe("to end of WORD"),
+                    ),
+                    (
+                        "e".into(),
+                        Keymap::One(vec![Command::EditorFilterPrompt(Motion::NextWordEnd)])
+                            .describe("to end of word"),
+                    ),
+                    (
+                        "B".into(),
+                        Keymap::One(vec![Command::EditorFilterPrompt(
+                            Motion::LastWordProperStart,
+                        )])
+                        .describe("to previous WORD"),
+                    ),
+                    (
+                        "b".into(),
+                        Keymap::One(vec![Command::EditorFilterPrompt(Motion::LastWordStart)])
+                            .describe("to previous word"),
+                    ),
+                    (
+                        "j".into(),
+                        Keymap::One(vec![Command::EditorFilterPrompt(Motion::NextLineStart)])
+                            .describe("to next line"),
+                    ),
+                ]))
+                .describe("+filter through command"),
+            ),
+            (
+                "]".into(),
+                Keymap::Many(HashMap::from([(
+                    "q".into(),
+                    Keymap::One(vec![Command::QuickfixNext]).describe("next quickfix entry"),
+                )]))
+                .describe("+next"),
+            ),
+            (
+                "[".into(),
+                Keymap::Many(HashMap::from([(
+                    "q".into(),
+                    Keymap::One(vec![Command::QuickfixPrev]).describe("previous quickfix entry"),
+                )]))
+                .describe("+previous"),
+            ),
+            (
+                "u".into(),
+                Keymap::One(vec![Command::EditorUndo]).describe("undo"),
+            ),
+            (
+                "<C-r>".into(),
+                Keymap::One(vec![Command::EditorRedo]).describe("redo"),
+            ),
+            (
+                "Z".into(),
+                Keymap::Many(HashMap::from([
+                    (
+                        "Z".into(),
+                        Keymap::One(vec![Command::EditorSaveIfModified, Command::Quit])
+                            .describe("write if modified and quit"),
+                    ),
+                    (
+                        "Q".into(),
+                        Keymap::One(vec![Command::ForceQuit]).describe("quit without writing"),
+                    ),
+                ]))
+                .describe("+quit"),
             ),
             (
                 "l".into(),
-                Keymap::One(vec![Command::EditorMove(Motion::CharForward)]),
+                Keymap::One(vec![Command::EditorMove(Motion::CharForward)])
+                    .describe("next character"),
             ),
         ])
     };
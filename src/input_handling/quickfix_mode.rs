@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+
+use crate::{app::InputMode, commands::Command};
+
+use super::Keymap;
+
+lazy_static! {
+    pub static ref QUICKFIX_MAP: HashMap<String, Keymap> = {
+        let next = Keymap::One(vec![Command::QuickfixMoveSelection(1)]).describe("next entry");
+        let prev = Keymap::One(vec![Command::QuickfixMoveSelection(-1)]).describe("previous entry");
+        HashMap::from([
+            ("j".into(), next.clone()),
+            ("<Down>".into(), next),
+            ("k".into(), prev.clone()),
+            ("<Up>".into(), prev),
+            (
+                "<CR>".into(),
+                Keymap::One(vec![
+                    Command::ChangeInputMode(InputMode::Normal),
+                    Command::QuickfixJumpSelected,
+                ])
+                .describe("jump to entry"),
+            ),
+            (
+                "q".into(),
+                Keymap::One(vec![
+                    Command::QuickfixClose,
+                    Command::ChangeInputMode(InputMode::Normal),
+                ])
+                .describe("close the list"),
+            ),
+            (
+                "<Esc>".into(),
+                Keymap::One(vec![Command::ChangeInputMode(InputMode::Normal)])
+                    .describe("back to the editor"),
+            ),
+        ])
+    };
+}
@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+
+use crate::{app::InputMode, commands::Command};
+
+use super::Keymap;
+
+lazy_static! {
+    pub static ref RECOVERY_MAP: HashMap<String, Keymap> = {
+        HashMap::from([
+            (
+                "r".into(),
+                Keymap::One(vec![
+                    Command::SwapRecover,
+                    Command::ChangeInputMode(InputMode::Normal),
+                ])
+                .describe("recover unsaved changes"),
+            ),
+            (
+                "d".into(),
+                Keymap::One(vec![
+                    Command::SwapDelete,
+                    Command::ChangeInputMode(InputMode::Normal),
+                ])
+                .describe("delete the swap file"),
+            ),
+            (
+                "o".into(),
+                Keymap::One(vec![
+                    Command::SwapOpenReadOnly,
+                    Command::ChangeInputMode(InputMode::Normal),
+                ])
+                .describe("open read-only"),
+            ),
+            (
+                "q".into(),
+                Keymap::One(vec![Command::ForceQuit]).describe("quit"),
+            ),
+        ])
+    };
+}
@@ -4,7 +4,7 @@ use clap::Parser;
 use crossterm::cursor::SetCursorStyle;
 use crossterm::execute;
 use log::info;
-use miv_editor::app::{App, AppResult, InputMode};
+use miv_editor::app::{shell, App, AppResult, InputMode, StatusMessage};
 use miv_editor::cli::Cli;
 use miv_editor::event::Event;
 use miv_editor::input_handling::InputStack;
@@ -24,35 +24,56 @@ fn main() -> AppResult<()> {
 
     // Create an application.
     let mut app = App::new(args.filename);
-    let mut tui = Tui::new()?;
+    let mut tui = Tui::new(app.options.get_int("tickrate") as u64)?;
     tui.init()?;
+    tui.set_mouse_capture(app.options.get_bool("mouse"))?;
+    app.jobs.connect(tui.events.sender());
+    let mut cursor_mode = None;
 
     // Start the main loop.
     while app.running {
-        // Render the user interface.
-        tui.draw(&mut app)?;
-        // Handle events.
-        let commands = match tui.events.next()? {
-            Event::Tick => {
-                app.tick();
-                vec![]
-            }
-            Event::Key(key_event) => input_stack
-                .handle_key_event(key_event, &app.mode)
-                .unwrap_or_default(),
-            Event::Mouse(_) => vec![],
-            Event::Resize(_, _) => vec![],
-        };
-
-        app.execute(commands)?;
-
-        match app.mode {
-            InputMode::Normal => {
-                execute!(io::stdout(), SetCursorStyle::BlinkingBlock).unwrap();
+        // Render the user interface, only when something changed.
+        if app.needs_redraw {
+            tui.draw(&mut app)?;
+        }
+        // Handle the next event along with any that queued up meanwhile, so
+        // a burst of keys is drawn once.
+        let mut event = Some(tui.events.next()?);
+        while let Some(current) = event.take() {
+            handle_event(current, &mut app, &mut input_stack)?;
+            if app.running {
+                event = tui.events.try_next();
             }
-            InputMode::Insert | InputMode::Command => {
-                execute!(io::stdout(), SetCursorStyle::BlinkingBar).unwrap();
+        }
+
+        // `:!cmd` gets the terminal to itself until it's done
+        if let Some(command) = app.pending_shell.take() {
+            tui.events.pause();
+            tui.exit()?;
+            let status = shell::run_interactive(&command);
+            tui.init()?;
+            tui.events.resume();
+            app.shell_finished(status);
+            cursor_mode = None;
+        }
+
+        tui.events
+            .set_tick_rate(app.options.get_int("tickrate") as u64);
+        tui.set_mouse_capture(app.options.get_bool("mouse"))?;
+
+        if cursor_mode != Some(app.mode) {
+            match app.mode {
+                InputMode::Normal
+                | InputMode::Conflict
+                | InputMode::Recovery
+                | InputMode::Quickfix => {
+                    execute!(io::stdout(), SetCursorStyle::BlinkingBlock).unwrap();
+                }
+                InputMode::Insert | InputMode::Command => {
+                    execute!(io::stdout(), SetCursorStyle::BlinkingBar).unwrap();
+                }
             }
+            cursor_mode = Some(app.mode);
         }
     }
 
@@ -60,3 +81,47 @@ fn main() -> AppResult<()> {
     tui.exit()?;
     Ok(())
 }
+
+/// Runs whatever a terminal event asks for.
+fn handle_event(event: Event, app: &mut App, input_stack: &mut InputStack) -> AppResult<()> {
+    let commands = match event {
+        Event::Tick => {
+            app.tick();
+            input_stack.tick(&app.mode, &app.keymaps, &app.options)
+        }
+        Event::Key(key_event) => input_stack
+            .handle_key_event(key_event, &app.mode, &app.keymaps)
+            .unwrap_or_default(),
+        Event::Mouse(mouse_event) if app.options.get_bool("mouse") => {
+            input_stack.handle_mouse_event(mouse_event, &app.mode, &app.editor, app.status_row)
+        }
+        Event::Mouse(_) => vec![],
+        Event::Resize(_, _) => {
+            app.needs_redraw = true;
+            vec![]
+        }
+        Event::Paste(text) => input_stack.handle_paste(text, &app.mode),
+        Event::FocusGained => {
+            app.focus_gained();
+            vec![]
+        }
+        Event::JobDone(done) => {
+            if let Err(err) = app.job_done(done) {
+                app.message = Some(StatusMessage::Error(err.to_string()));
+            }
+            vec![]
+        }
+        Event::JobProgress { id, message } => {
+            app.job_progress(id, message);
+            vec![]
+        }
+    };
+
+    app.execute(commands)?;
+    let which_key = input_stack.which_key(&app.options);
+    if which_key != app.which_key {
+        app.which_key = which_key;
+        app.needs_redraw = true;
+    }
+    Ok(())
+}
@@ -3,14 +3,28 @@ use crate::event::EventHandler;
 use crate::ui;
 use crate::utils::initialize_panic_handler;
 use color_eyre::eyre::Result;
-use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::event::{
+    DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste,
+    EnableFocusChange, EnableMouseCapture, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+    PushKeyboardEnhancementFlags,
+};
 use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub type IO = std::io::Stdout;
 
+/// Whether the kitty keyboard protocol flags were pushed and need popping.
+///
+/// [`Tui::reset`] runs from the panic hook too, which has no [`Tui`] to ask.
+static KEYBOARD_ENHANCED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the panic and error hooks are in, they can only be installed once
+/// and [`Tui::init`] runs again after a `:!` command.
+static HOOKS_INSTALLED: AtomicBool = AtomicBool::new(false);
+
 /// Representation of a terminal user interface.
 ///
 /// It is responsible for setting up the terminal,
@@ -21,16 +35,22 @@ pub struct Tui {
     terminal: Terminal<CrosstermBackend<IO>>,
     /// Terminal event handler.
     pub events: EventHandler,
+    /// Whether the terminal sends mouse events
+    mouse_capture: bool,
 }
 
 impl Tui {
     /// Constructs a new instance of [`Tui`].
-    pub fn new() -> Result<Self> {
+    pub fn new(tick_rate: u64) -> Result<Self> {
         // Initialize the terminal user interface.
         let backend = CrosstermBackend::new(io::stdout());
         let terminal = Terminal::new(backend)?;
-        let events = EventHandler::new(250);
-        Ok(Self { terminal, events })
+        let events = EventHandler::new(tick_rate);
+        Ok(Self {
+            terminal,
+            events,
+            mouse_capture: false,
+        })
     }
 
     /// Initializes the terminal interface.
@@ -38,9 +58,31 @@ impl Tui {
     /// It enables the raw mode and sets terminal properties.
     pub fn init(&mut self) -> AppResult<()> {
         terminal::enable_raw_mode()?;
-        crossterm::execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
+        crossterm::execute!(
+            io::stderr(),
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            EnableFocusChange,
+            EnableBracketedPaste
+        )?;
+
+        // Lets ctrl+i and Tab, or Esc and Alt, be told apart. Terminals that
+        // don't speak the protocol carry on with the legacy encoding.
+        if terminal::supports_keyboard_enhancement().unwrap_or(false) {
+            crossterm::execute!(
+                io::stderr(),
+                PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                        | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                )
+            )?;
+            KEYBOARD_ENHANCED.store(true, Ordering::Relaxed);
+        }
 
-        initialize_panic_handler()?;
+        self.mouse_capture = true;
+        if !HOOKS_INSTALLED.swap(true, Ordering::Relaxed) {
+            initialize_panic_handler()?;
+        }
 
         self.terminal.hide_cursor()?;
         self.terminal.clear()?;
@@ -53,6 +95,7 @@ impl Tui {
     /// [`rendering`]: crate::ui:render
     pub fn draw(&mut self, app: &mut App) -> AppResult<()> {
         self.terminal.draw(|frame| ui::render(frame, app))?;
+        app.needs_redraw = false;
         Ok(())
     }
 
@@ -61,8 +104,31 @@ impl Tui {
     /// This function is also used for the panic hook to revert
     /// the terminal properties if unexpected errors occur.
     fn reset() -> AppResult<()> {
+        if KEYBOARD_ENHANCED.swap(false, Ordering::Relaxed) {
+            crossterm::execute!(io::stderr(), PopKeyboardEnhancementFlags)?;
+        }
         terminal::disable_raw_mode()?;
-        crossterm::execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture)?;
+        crossterm::execute!(
+            io::stderr(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            DisableFocusChange,
+            DisableBracketedPaste
+        )?;
+        Ok(())
+    }
+
+    /// Turns mouse reporting on or off, leaving the terminal's own text
+    /// selection to work when it's off.
+    pub fn set_mouse_capture(&mut self, enabled: bool) -> AppResult<()> {
+        if enabled != self.mouse_capture {
+            if enabled {
+                crossterm::execute!(io::stderr(), EnableMouseCapture)?;
+            } else {
+                crossterm::execute!(io::stderr(), DisableMouseCapture)?;
+            }
+            self.mouse_capture = enabled;
+        }
         Ok(())
     }
 
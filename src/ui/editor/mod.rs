@@ -6,105 +6,223 @@ use ratatui::{
     Frame,
 };
 
-use crate::app::{editor::highlighting::HighlightGroup, App};
+use crate::app::{
+    editor::{
+        style_cache::Cell,
+        viewport::{LineLayout, RowPos, ViewRow, Viewport},
+    },
+    App,
+};
 
-pub fn draw_editor(f: &mut Frame, app: &App, chunk: Rect) {
-    let colors = &app.theme;
-    let content = app.editor.gap_buffer.get_text_as_string();
-    let highlight_spans = &app.editor.highlight_groups;
+/// A screen row, the line number is only set on the first row of a line
+struct Row {
+    line_number: Option<usize>,
+    cells: Vec<Cell>,
+    view: ViewRow,
+    /// Whether the row shows part of the cursor line
+    current: bool,
+}
 
-    let mut lines_as_spans = vec![];
-    let mut current_line = vec![];
+pub fn draw_editor(f: &mut Frame, app: &mut App, chunk: Rect) {
+    app.editor.update_highlights();
+    let theme = &app.theme;
+    let options = &app.options;
+    let cursorline = options.get_bool("cursorline");
+    let normal_style = theme.ui("normal");
+    let cursorline_style = theme.ui("cursorline");
+    let line_count = app.editor.gap_buffer.line_count();
 
-    let mut cursor_coord = (0, 0);
-    let mut cursor_index_render_accumulator = 0;
-    let mut cursor_coord_found = false;
+    // Gutter
+    let number_width = line_count
+        .to_string()
+        .len()
+        .max(options.get_usize("numberwidth"));
+    let gutter_padding = options.get_usize("gutterpadding");
+    let show_numbers = options.get_bool("number");
+    let gutter_width = if show_numbers {
+        number_width + gutter_padding
+    } else {
+        0
+    };
+    let text_width = (chunk.width as usize).saturating_sub(gutter_width).max(1);
+    let layout = LineLayout {
+        tabstop: options.get_usize("tabstop"),
+        text_width,
+        wrap: options.get_bool("wrap"),
+    };
+    let editor = &mut app.editor;
+    let buffer = &editor.gap_buffer;
+    let cursor = layout.position_of(buffer, editor.cursor_index);
 
-    for span in highlight_spans {
-        let slice_to_highlight = &content[span.start..span.end];
-        let style = match span.group {
-            HighlightGroup::Attribute => Style::default().fg(colors.rose),
-            HighlightGroup::Comment => Style::default().fg(colors.surface1),
-            HighlightGroup::Constant => Style::default().fg(colors.peach),
-            HighlightGroup::ConstantBuiltin => Style::default().fg(colors.peach),
-            HighlightGroup::Constructor => Style::default().fg(colors.peach),
-            HighlightGroup::Function => Style::default().fg(colors.blue),
-            HighlightGroup::FunctionBuiltin => Style::default().fg(colors.blue),
-            HighlightGroup::FunctionMethod => Style::default().fg(colors.blue),
-            HighlightGroup::FunctionMacro => Style::default().fg(colors.blue),
-            HighlightGroup::Keyword => Style::default().fg(colors.mauve),
-            HighlightGroup::Property => Style::default().fg(colors.lavender),
-            HighlightGroup::Punctuation => Style::default().fg(colors.green),
-            HighlightGroup::PunctuationDelimiter => Style::default().fg(colors.text),
-            HighlightGroup::String => Style::default().fg(colors.green),
-            HighlightGroup::StringSpecial => Style::default().fg(colors.green),
-            HighlightGroup::Type => Style::default().fg(colors.yellow),
-            HighlightGroup::TypeBuiltin => Style::default().fg(colors.sapphire),
-            HighlightGroup::Variable => Style::default().fg(colors.text),
-            HighlightGroup::VariableBuiltin => Style::default().fg(colors.text),
-            HighlightGroup::VariableParameter => Style::default().fg(colors.yellow),
-            HighlightGroup::None => Style::default().fg(colors.text),
-            _ => Style::default().fg(colors.text),
-        };
+    // Scroll to keep the cursor in view with `scrolloff` rows around it,
+    // only looking at the lines between the top of the window and the cursor
+    let height = chunk.height as usize;
+    let scrolloff = options
+        .get_usize("scrolloff")
+        .min(height.saturating_sub(1) / 2);
+    let top_line = editor.top.line.min(line_count - 1);
+    let top = RowPos {
+        line: top_line,
+        row: editor
+            .top
+            .row
+            .min(layout.row_count(&buffer.line(top_line)) - 1),
+    };
+    let cursor_row = cursor.row_pos();
+    let below = height.saturating_sub(scrolloff + 1);
+    editor.top = if cursor_row < top {
+        layout.rows_up(buffer, cursor_row, scrolloff)
+    } else {
+        match layout.rows_between(buffer, top, cursor_row, height) {
+            Some(rows) if rows < scrolloff => layout.rows_up(buffer, cursor_row, scrolloff),
+            Some(rows) if rows <= below => top,
+            _ => layout.rows_up(buffer, cursor_row, below),
+        }
+    };
+    if layout.wrap {
+        editor.left_col = 0;
+    } else if cursor.column < editor.left_col {
+        editor.left_col = cursor.column;
+    } else if cursor.column >= editor.left_col + text_width {
+        editor.left_col = cursor.column + 1 - text_width;
+    }
+    let (top, left_col) = (editor.top, editor.left_col);
+    let cursor_screen_row = layout
+        .rows_between(buffer, top, cursor_row, height)
+        .unwrap_or_default();
 
-        for line in slice_to_highlight.split_inclusive('\n') {
-            if line.is_empty() || line.ends_with('\n') {
-                current_line.push(Span::styled(line, style));
-                lines_as_spans.push(current_line.clone());
-                current_line.clear();
-            } else {
-                current_line.push(Span::styled(line, style));
+    // Lay out the lines in the window into screen rows, starting with the
+    // rows of the top line that fit
+    let selection = editor.selection_range();
+    let mut skip = top.row;
+    let mut rows = vec![];
+    for index in top.line..line_count {
+        if rows.len() >= height {
+            break;
+        }
+        let line = editor.gap_buffer.line(index);
+        let line_start = editor.gap_buffer.line_start(index).unwrap_or_default();
+        let mut cells = expand_tabs(editor.style_cache.line(index, &line, theme), layout.tabstop);
+        let view_rows = layout.rows(&line, line_start);
+        let current = index == cursor.line;
+        if cursorline && current {
+            for cell in cells.iter_mut() {
+                cell.1 = cell.1.patch(cursorline_style);
+            }
+        }
+        if let Some(selection) = &selection {
+            let selection_style = theme.ui("selection");
+            let indices = view_rows.iter().flat_map(|row| &row.cells);
+            for (cell, char_index) in cells.iter_mut().zip(indices) {
+                if selection.contains(char_index) {
+                    cell.1 = cell.1.patch(selection_style);
+                }
             }
         }
-    }
-    if !current_line.is_empty() {
-        lines_as_spans.push(current_line);
-    }
 
-    if content.ends_with('\n') {
-        lines_as_spans.push(vec![Span::raw("")]);
+        let mut cells = cells.into_iter();
+        for (row_index, view) in view_rows.into_iter().enumerate() {
+            let row_cells = cells.by_ref().take(view.cells.len()).collect();
+            if row_index < skip {
+                continue;
+            }
+            rows.push(Row {
+                line_number: (row_index == 0).then_some(index + 1),
+                cells: row_cells,
+                view,
+                current,
+            });
+        }
+        skip = 0;
     }
+    rows.truncate(height);
+    editor.viewport = Viewport {
+        x: chunk.x,
+        y: chunk.y,
+        width: chunk.width,
+        height: chunk.height,
+        gutter_width: gutter_width as u16,
+        layout,
+        rows: rows.iter().map(|row| row.view.clone()).collect(),
+    };
 
-    let max_line_number_digits = (lines_as_spans.len()).to_string().len();
-
-    let mut lines = vec![];
-    for mut line in lines_as_spans {
-        let width = line.iter().fold(0, |acc, span| acc + span.width());
-        if !cursor_coord_found {
-            if width + 1 + cursor_index_render_accumulator > app.editor.cursor_index {
-                let x = app.editor.cursor_index - cursor_index_render_accumulator;
-                let y = lines.len();
-                cursor_coord_found = true;
-                cursor_coord = (x, y);
+    let mut rendered = vec![];
+    for row in &rows {
+        let highlight_row = cursorline && row.current;
+        let mut spans = if show_numbers {
+            let gutter_style = if highlight_row {
+                theme.ui("gutter.current")
             } else {
-                cursor_index_render_accumulator += width + 1;
-            }
+                theme.ui("gutter")
+            };
+            gutter(row.line_number, number_width, gutter_padding, gutter_style)
+        } else {
+            vec![]
+        };
+        let visible = row.cells.get(left_col..).unwrap_or(&[]);
+        let mut cells = visible[..visible.len().min(text_width)].to_vec();
+        // The cursor line is highlighted across the whole window
+        if highlight_row {
+            cells.resize(text_width, (' ', normal_style.patch(cursorline_style)));
         }
-        let line_number = lines.len() + 1;
-        let leading_spacing = max_line_number_digits - (line_number).to_string().len();
-        let mut prefix = vec![
-            Span::styled(
-                " ".repeat(leading_spacing),
-                Style::default().fg(colors.yellow),
-            ),
-            Span::styled(
-                (line_number).to_string(),
-                Style::default().fg(colors.yellow),
-            ),
-            Span::styled(
-                " ".repeat(max_line_number_digits + 2),
-                Style::default().fg(colors.yellow),
-            ),
-        ];
-
-        prefix.append(&mut line);
-        lines.push(Line::from(prefix));
+        spans.extend(to_spans(&cells));
+        rendered.push(Line::from(spans));
     }
 
-    let text_content = Paragraph::new(lines).style(Style::default().bg(colors.crust));
+    let text_content = Paragraph::new(rendered).style(normal_style);
     f.render_widget(text_content, chunk);
     f.set_cursor(
-        chunk.x + cursor_coord.0 as u16 + ((2 * max_line_number_digits) as u16 + 2),
-        chunk.y + cursor_coord.1 as u16,
+        chunk.x + (gutter_width + cursor.column - left_col) as u16,
+        chunk.y + cursor_screen_row as u16,
     )
 }
+
+/// Replaces tabs with spaces up to the next tab stop
+fn expand_tabs(line: &[Cell], tabstop: usize) -> Vec<Cell> {
+    let mut cells = Vec::with_capacity(line.len());
+    for &(ch, style) in line {
+        if ch == '\t' {
+            let width = tabstop - cells.len() % tabstop;
+            cells.extend(std::iter::repeat_n((' ', style), width));
+        } else {
+            cells.push((ch, style));
+        }
+    }
+    cells
+}
+
+/// Groups runs of equally styled characters into spans
+fn to_spans(cells: &[Cell]) -> Vec<Span<'static>> {
+    let mut spans = vec![];
+    let mut text = String::new();
+    let mut current_style = None;
+    for &(ch, style) in cells {
+        if current_style.is_some_and(|current| current != style) {
+            spans.push(Span::styled(
+                std::mem::take(&mut text),
+                current_style.unwrap(),
+            ));
+        }
+        current_style = Some(style);
+        text.push(ch);
+    }
+    if let Some(style) = current_style {
+        spans.push(Span::styled(text, style));
+    }
+    spans
+}
+
+fn gutter(
+    line_number: Option<usize>,
+    number_width: usize,
+    padding: usize,
+    style: Style,
+) -> Vec<Span<'static>> {
+    let number = line_number.map(|n| n.to_string()).unwrap_or_default();
+    let leading_spacing = number_width - number.len();
+    vec![
+        Span::styled(" ".repeat(leading_spacing), style),
+        Span::styled(number, style),
+        Span::styled(" ".repeat(padding), style),
+    ]
+}
@@ -1,20 +1,43 @@
 // mod editors;
 mod editor;
+mod popup;
+mod quickfix;
 mod status_line;
 
 use ratatui::prelude::*;
 
 use crate::app::App;
 
-use self::{editor::draw_editor, status_line::draw_status_line};
+use self::{
+    editor::draw_editor,
+    popup::{draw_popup, draw_which_key},
+    quickfix::{draw_quickfix, quickfix_height},
+    status_line::draw_status_line,
+};
 
-pub fn render(f: &mut Frame, app: &App) {
-    let global_layout_constraints = vec![Constraint::Min(1), Constraint::Length(1)];
+pub fn render(f: &mut Frame, app: &mut App) {
+    let mut global_layout_constraints = vec![Constraint::Min(1), Constraint::Length(1)];
+    if app.quickfix.open {
+        let height = quickfix_height(app, f.size());
+        global_layout_constraints.insert(1, Constraint::Length(height));
+    }
     let global_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints(global_layout_constraints)
         .split(f.size());
 
+    let status_line = global_layout[global_layout.len() - 1];
+    app.status_row = status_line.y;
     draw_editor(f, app, global_layout[0]);
-    draw_status_line(f, app, global_layout[1])
+    draw_status_line(f, app, status_line);
+    if app.quickfix.open {
+        draw_quickfix(f, app, global_layout[1]);
+    }
+
+    if let Some(popup) = &app.popup {
+        draw_popup(f, app, popup, global_layout[0]);
+    }
+    if let Some(which_key) = &app.which_key {
+        draw_which_key(f, app, which_key, global_layout[0]);
+    }
 }
@@ -0,0 +1,89 @@
+use ratatui::{
+    layout::Rect,
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use crate::app::{popup::Popup, App};
+
+pub fn draw_popup(f: &mut Frame, app: &App, popup: &Popup, area: Rect) {
+    let theme = &app.theme;
+    let popup_area = centered_rect(area, 80, 80);
+
+    let lines: Vec<Line> = popup
+        .lines
+        .iter()
+        .map(|line| {
+            // Colour diff output, everything else is plain text
+            let style = if line.starts_with('+') {
+                theme.ui("diff.plus")
+            } else if line.starts_with('-') {
+                theme.ui("diff.minus")
+            } else if line.starts_with("@@") {
+                theme.ui("diff.delta")
+            } else {
+                Style::default()
+            };
+            Line::from(Span::styled(line.as_str(), style))
+        })
+        .collect();
+
+    let block = Block::default()
+        .title(format!(" {} ", popup.title))
+        .borders(Borders::ALL)
+        .border_style(theme.ui("popup.border"));
+    let widget = Paragraph::new(lines).block(block).style(theme.ui("popup"));
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(widget, popup_area);
+}
+
+/// Lists the keys that may follow in the bottom right corner of `area`.
+pub fn draw_which_key(f: &mut Frame, app: &App, which_key: &Popup, area: Rect) {
+    let theme = &app.theme;
+    let content_width = which_key
+        .lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(which_key.title.chars().count() + 2);
+    let width = (content_width as u16 + 2).min(area.width);
+    let height = (which_key.lines.len() as u16 + 2).min(area.height);
+    let popup_area = Rect {
+        x: area.x + area.width - width,
+        y: area.y + area.height - height,
+        width,
+        height,
+    };
+
+    let lines: Vec<Line> = which_key
+        .lines
+        .iter()
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    let block = Block::default()
+        .title(format!(" {} ", which_key.title))
+        .borders(Borders::ALL)
+        .border_style(theme.ui("whichkey.border"));
+    let widget = Paragraph::new(lines)
+        .block(block)
+        .style(theme.ui("whichkey"));
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(widget, popup_area);
+}
+
+/// A rect taking up the given percentage of `area`, centred within it.
+fn centered_rect(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
+    let width = area.width * percent_x / 100;
+    let height = area.height * percent_y / 100;
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
@@ -0,0 +1,50 @@
+use ratatui::{
+    layout::Rect,
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use crate::app::App;
+
+/// Most entries the panel shows at once
+const MAX_ROWS: u16 = 10;
+
+/// Rows the quickfix panel takes up in `area`, its border included.
+pub fn quickfix_height(app: &App, area: Rect) -> u16 {
+    let rows = (app.quickfix.entries.len() as u16).clamp(1, MAX_ROWS);
+    // Leave the editor and status line a row each
+    (rows + 1).min(area.height.saturating_sub(2))
+}
+
+/// Lists the quickfix entries, scrolled so the selected one is visible.
+pub fn draw_quickfix(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
+    let quickfix = &app.quickfix;
+    let rows = area.height.saturating_sub(1) as usize;
+    let scroll = (quickfix.selected + 1).saturating_sub(rows);
+
+    let lines: Vec<Line> = quickfix
+        .entries
+        .iter()
+        .enumerate()
+        .skip(scroll)
+        .take(rows)
+        .map(|(index, entry)| {
+            let mut style = theme.ui(&format!("quickfix.{}", entry.severity));
+            if index == quickfix.selected {
+                style = style.patch(theme.ui("quickfix.selected"));
+            }
+            Line::from(Span::styled(entry.to_string(), style))
+        })
+        .collect();
+
+    let block = Block::default()
+        .title(format!(" Quickfix: {} ", quickfix.title))
+        .borders(Borders::TOP)
+        .border_style(theme.ui("quickfix.border"));
+    let widget = Paragraph::new(lines)
+        .block(block)
+        .style(theme.ui("quickfix"));
+    f.render_widget(widget, area);
+}
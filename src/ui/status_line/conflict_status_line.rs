@@ -0,0 +1,33 @@
+use crate::app::App;
+
+use ratatui::{
+    layout::Rect,
+    text::{Line, Span},
+    widgets::Paragraph,
+    Frame,
+};
+
+pub fn draw_conflict_mode_status_line(f: &mut Frame, app: &App, chunk: Rect) {
+    // Mode indicator
+    let input_mode_style = app.theme.ui(&format!(
+        "statusline.mode.{}",
+        app.mode.to_string().to_lowercase()
+    ));
+    let mode_padding_front = Span::styled(" ", input_mode_style);
+    let mode = Span::styled(app.mode.to_string().to_uppercase(), input_mode_style);
+    let mode_padding_back = Span::styled(" ", input_mode_style);
+    let mode_indicator = vec![mode_padding_front, mode, mode_padding_back];
+
+    // Content
+    let prompt = Span::styled(
+        " File changed on disk: (r)eload, (k)eep yours, (d)iff",
+        app.theme.ui("statusline.warning"),
+    );
+    let content = vec![prompt];
+
+    // Combine the spans
+    let status_line_spans = [mode_indicator, content].concat();
+    let status_line_widget =
+        Paragraph::new(Line::from(status_line_spans)).style(app.theme.ui("statusline"));
+    f.render_widget(status_line_widget, chunk);
+}
@@ -1,21 +1,64 @@
 mod command_status_line;
+mod conflict_status_line;
 mod insert_status_line;
 mod normal_status_line;
+mod recovery_status_line;
 
-use ratatui::{prelude::Rect, Frame};
+use ratatui::{
+    layout::{Alignment, Rect},
+    text::{Line, Span},
+    widgets::Paragraph,
+    Frame,
+};
 
 use crate::app::{App, InputMode};
 
 use self::{
     command_status_line::draw_command_mode_status_line,
+    conflict_status_line::draw_conflict_mode_status_line,
     insert_status_line::draw_insert_mode_status_line,
     normal_status_line::draw_normal_mode_status_line,
+    recovery_status_line::draw_recovery_mode_status_line,
 };
 
 pub fn draw_status_line(f: &mut Frame, app: &App, chunk: Rect) {
     match app.mode {
-        InputMode::Normal => draw_normal_mode_status_line(f, app, chunk),
+        InputMode::Normal | InputMode::Quickfix => draw_normal_mode_status_line(f, app, chunk),
         InputMode::Insert => draw_insert_mode_status_line(f, app, chunk),
         InputMode::Command => draw_command_mode_status_line(f, app, chunk),
+        InputMode::Conflict => draw_conflict_mode_status_line(f, app, chunk),
+        InputMode::Recovery => draw_recovery_mode_status_line(f, app, chunk),
+    };
+}
+
+/// Running jobs and the file format, on the right of the status line
+fn draw_file_info(f: &mut Frame, app: &App, chunk: Rect) {
+    let file_format = &app.editor.file_format;
+    let jobs = jobs_segment(app);
+    let jobs = if jobs.is_empty() {
+        Span::raw("")
+    } else {
+        Span::styled(format!("{}  ", jobs), app.theme.ui("statusline.jobs"))
     };
+    let file_info = Paragraph::new(Line::from(vec![
+        jobs,
+        Span::styled(
+            format!("{} [{}] ", file_format.encoding, file_format.line_ending),
+            app.theme.ui("statusline.fileinfo"),
+        ),
+    ]))
+    .alignment(Alignment::Right);
+    f.render_widget(file_info, chunk);
+}
+
+/// Running background jobs, as `name: progress` separated by bars
+fn jobs_segment(app: &App) -> String {
+    app.jobs
+        .running()
+        .map(|(_, name, progress)| match progress {
+            Some(progress) => format!("{}: {}", name, progress),
+            None => name.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
 }
@@ -1,50 +1,74 @@
-use crate::app::App;
+use crate::app::{App, StatusMessage};
 
 use ratatui::{
     layout::Rect,
-    style::{Style, Stylize},
-    text::Span,
+    text::{Line, Span},
     widgets::Paragraph,
     Frame,
 };
 
+use super::draw_file_info;
+
 pub fn draw_normal_mode_status_line(f: &mut Frame, app: &App, chunk: Rect) {
     // Mode indicator
-    let input_mode_style = Style::default()
-        .bg(app.theme.yellow)
-        .fg(app.theme.crust)
-        .bold();
+    let input_mode_style = app.theme.ui(&format!(
+        "statusline.mode.{}",
+        app.mode.to_string().to_lowercase()
+    ));
     let mode_padding_front = Span::styled(" ", input_mode_style);
     let mode = Span::styled(app.mode.to_string().to_uppercase(), input_mode_style);
     let mode_padding_back = Span::styled(" ", input_mode_style);
     let mode_indicator = vec![mode_padding_front, mode, mode_padding_back];
 
     // Content
-    let path_padding_front = Span::styled(" ", Style::default().bg(app.theme.mantle));
+    let path_padding_front = Span::styled(" ", app.theme.ui("statusline"));
     let path = if let Some(path) = &app.editor.path {
         if let Some(filename) = path.file_name() {
             if let Some(filename_str) = filename.to_str() {
-                Span::styled(filename_str, Style::default().bg(app.theme.mantle))
+                Span::styled(filename_str, app.theme.ui("statusline"))
             } else {
-                Span::styled(
-                    "Cannot get filepath!",
-                    Style::default().fg(app.theme.red).bg(app.theme.mantle),
-                )
+                Span::styled("Cannot get filepath!", app.theme.ui("statusline.error"))
             }
         } else {
-            Span::styled(
-                "Cannot get filepath!",
-                Style::default().fg(app.theme.red).bg(app.theme.mantle),
-            )
+            Span::styled("Cannot get filepath!", app.theme.ui("statusline.error"))
         }
     } else {
-        Span::styled("New File", Style::default().bg(app.theme.mantle))
+        Span::styled("New File", app.theme.ui("statusline"))
+    };
+    let modified = if app.editor.is_modified() {
+        Span::styled(" [+]", app.theme.ui("statusline"))
+    } else {
+        Span::raw("")
+    };
+    let read_only = if app.editor.read_only {
+        Span::styled(" [RO]", app.theme.ui("statusline"))
+    } else {
+        Span::raw("")
+    };
+    let content = vec![path_padding_front, path, modified, read_only];
+
+    // Feedback from the last command
+    let message = match &app.message {
+        Some(StatusMessage::Info(message)) => vec![Span::styled(
+            format!("  {}", message),
+            app.theme.ui("statusline.info"),
+        )],
+        Some(StatusMessage::Warning(message)) => vec![Span::styled(
+            format!("  {}", message),
+            app.theme.ui("statusline.warning"),
+        )],
+        Some(StatusMessage::Error(message)) => vec![Span::styled(
+            format!("  {}", message),
+            app.theme.ui("statusline.error"),
+        )],
+        None => vec![],
     };
-    let content = vec![path_padding_front, path];
 
     // Combine the spans
-    let status_line_spans = [mode_indicator, content].concat();
-    let status_line_widget = Paragraph::new(ratatui::text::Line::from(status_line_spans))
-        .style(Style::default().bg(app.theme.mantle));
+    let status_line_spans = [mode_indicator, content, message].concat();
+    let status_line_widget =
+        Paragraph::new(Line::from(status_line_spans)).style(app.theme.ui("statusline"));
     f.render_widget(status_line_widget, chunk);
+
+    draw_file_info(f, app, chunk);
 }
@@ -0,0 +1,40 @@
+use crate::app::App;
+
+use ratatui::{
+    layout::Rect,
+    text::{Line, Span},
+    widgets::Paragraph,
+    Frame,
+};
+
+pub fn draw_recovery_mode_status_line(f: &mut Frame, app: &App, chunk: Rect) {
+    // Mode indicator
+    let input_mode_style = app.theme.ui(&format!(
+        "statusline.mode.{}",
+        app.mode.to_string().to_lowercase()
+    ));
+    let mode_padding_front = Span::styled(" ", input_mode_style);
+    let mode = Span::styled(app.mode.to_string().to_uppercase(), input_mode_style);
+    let mode_padding_back = Span::styled(" ", input_mode_style);
+    let mode_indicator = vec![mode_padding_front, mode, mode_padding_back];
+
+    // Content
+    let prompt = match &app.editor.swap.found {
+        Some(found) if found.in_use => format!(
+            " Already being edited by miv (pid {}): (o)pen read-only, (d)elete swap and edit, (q)uit",
+            found.pid
+        ),
+        Some(found) => format!(
+            " Unsaved changes found from a crashed miv (pid {}): (r)ecover, (d)elete, (o)pen read-only, (q)uit",
+            found.pid
+        ),
+        None => String::new(),
+    };
+    let content = vec![Span::styled(prompt, app.theme.ui("statusline.warning"))];
+
+    // Combine the spans
+    let status_line_spans = [mode_indicator, content].concat();
+    let status_line_widget =
+        Paragraph::new(Line::from(status_line_spans)).style(app.theme.ui("statusline"));
+    f.render_widget(status_line_widget, chunk);
+}
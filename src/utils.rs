@@ -39,7 +39,11 @@ pub fn initialize_panic_handler() -> Result<()> {
         .into_hooks();
     eyre_hook.install()?;
     std::panic::set_hook(Box::new(move |panic_info| {
-        if let Ok(mut t) = crate::tui::Tui::new() {
+        // Save whatever unsaved work we can before anything else
+        crate::app::editor::swap::dump_dirty_buffers();
+
+        let tick_rate = crate::app::options::Options::default().get_int("tickrate") as u64;
+        if let Ok(mut t) = crate::tui::Tui::new(tick_rate) {
             if let Err(r) = t.exit() {
                 error!("Unable to exit Terminal: {:?}", r);
             }
@@ -79,8 +83,24 @@ pub fn initialize_panic_handler() -> Result<()> {
     Ok(())
 }
 
+/// Stands in for the data and config directories in tests, so they neither
+/// read the developer's config nor leave swap files in their data directory.
+#[cfg(test)]
+fn test_dir(name: &str) -> Option<PathBuf> {
+    Some(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join("test-dirs")
+            .join(name),
+    )
+}
+
 pub fn get_data_dir() -> PathBuf {
-    let directory = if let Some(s) = DATA_FOLDER.clone() {
+    #[cfg(test)]
+    let directory = test_dir("data");
+    #[cfg(not(test))]
+    let directory = DATA_FOLDER.clone();
+    let directory = if let Some(s) = directory {
         s
     } else if let Some(proj_dirs) = project_directory() {
         proj_dirs.data_local_dir().to_path_buf()
@@ -91,7 +111,11 @@ pub fn get_data_dir() -> PathBuf {
 }
 
 pub fn get_config_dir() -> PathBuf {
-    let directory = if let Some(s) = CONFIG_FOLDER.clone() {
+    #[cfg(test)]
+    let directory = test_dir("config");
+    #[cfg(not(test))]
+    let directory = CONFIG_FOLDER.clone();
+    let directory = if let Some(s) = directory {
         s
     } else if let Some(proj_dirs) = project_directory() {
         proj_dirs.config_local_dir().to_path_buf()